@@ -0,0 +1,163 @@
+//! Builds Polyglot opening books from PGN files
+//!
+//! Every move played in the first plies of the games is counted together
+//! with the game result from the mover's point of view. Moves played in at
+//! least the minimum number of games are written with a weight of two per
+//! win and one per draw, scaled down if needed to fit the format, so moves
+//! which only ever lost are left out. Games without a result don't count.
+//!
+//! Games may start from a `FEN` tag. Only main lines are used, each up to
+//! its first move which can't be played, and games which can't be read are
+//! skipped.
+
+#[macro_use]
+extern crate clap;
+
+extern crate deeprust;
+
+use deeprust::engine::{encode_move, polyglot_key, BookEntry, ZobKey};
+use deeprust::interfaces::parse_san;
+use deeprust::interfaces::pgn::{GameResult, PgnGame, PgnReader};
+use deeprust::primitives::*;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+
+/// Games and summed scores, with a win counting 2, per position and move
+type Stats = HashMap<(ZobKey, u16), (u32, u32)>;
+
+/// Counts the book moves of `game`, returning how many plies were read
+fn add_game(game: &PgnGame, path: &str, max_ply: usize, stats: &mut Stats) -> usize {
+    let white_score = match game.result {
+        GameResult::WhiteWins => 2,
+        GameResult::Draw => 1,
+        GameResult::BlackWins => 0,
+        GameResult::Unfinished => return 0,
+    };
+    let mut board = match game.start() {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 0;
+        }
+    };
+
+    for (ply, pgn_move) in game.moves.iter().take(max_ply).enumerate() {
+        let mov = match parse_san(board.current(), &pgn_move.san) {
+            Ok(mov) => mov,
+            Err(e) => {
+                eprintln!("{}: line {}: {}: {}", path, pgn_move.line, pgn_move.san, e);
+                return ply;
+            }
+        };
+        let pos = board.current();
+        let score = if pos.to_move() == colors::WHITE {
+            white_score
+        } else {
+            2 - white_score
+        };
+        let entry = stats
+            .entry((polyglot_key(pos), encode_move(pos, mov)))
+            .or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += score;
+        board.make_move(mov);
+    }
+    game.moves.len().min(max_ply)
+}
+
+/// Book entries of the moves played in at least `min_games` games, sorted
+/// by key, then by falling weight and then by move
+fn book_entries(stats: &Stats, min_games: u32) -> Vec<BookEntry> {
+    let max_score = stats.values().map(|&(_, score)| score).max().unwrap_or(0);
+    let scale = |score: u32| {
+        if max_score <= u32::from(u16::MAX) {
+            score as u16
+        } else {
+            (u64::from(score) * u64::from(u16::MAX) / u64::from(max_score)) as u16
+        }
+    };
+
+    let mut entries: Vec<BookEntry> = stats
+        .iter()
+        .filter(|(_, &(games, _))| games >= min_games)
+        .map(|(&(key, mov), &(_, score))| BookEntry {
+            key,
+            mov,
+            weight: scale(score),
+            learn: 0,
+        })
+        .filter(|entry| entry.weight > 0)
+        .collect();
+    entries.sort_by(|a, b| {
+        a.key
+            .cmp(&b.key)
+            .then(b.weight.cmp(&a.weight))
+            .then(a.mov.cmp(&b.mov))
+    });
+    entries
+}
+
+fn main() {
+    let matches = clap_app!(book =>
+        (version: env!("CARGO_PKG_VERSION"))
+        (author: env!("CARGO_PKG_AUTHORS"))
+        (about: "Builds Polyglot opening books from PGN files.")
+        (@arg OUTPUT: -o --output +takes_value "Book file to write (default: book.bin)")
+        (@arg MAX_PLY: -p --("max-ply") +takes_value "Plies of each game to read (default: 20)")
+        (@arg MIN_GAMES: -g --("min-games") +takes_value "Games a move needs to be played in (default: 3)")
+        (@arg PGN: +required ... "PGN files to read")
+    )
+    .get_matches();
+
+    let output = matches.value_of("OUTPUT").unwrap_or("book.bin");
+    let max_ply = value_t!(matches, "MAX_PLY", usize).unwrap_or(20);
+    let min_games = value_t!(matches, "MIN_GAMES", u32).unwrap_or(3);
+
+    let mut stats = Stats::new();
+    for path in matches.values_of("PGN").unwrap() {
+        let reader = match PgnReader::open(path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("Could not read {}: {}", path, e);
+                process::exit(1);
+            }
+        };
+        let (mut games, mut plies) = (0, 0);
+        for game in reader {
+            match game {
+                Ok(game) => {
+                    games += 1;
+                    plies += add_game(&game, path, max_ply, &mut stats);
+                }
+                Err(e) => eprintln!("{}: {}", path, e),
+            }
+        }
+        println!("{}: {} games, {} plies", path, games, plies);
+    }
+
+    let entries = book_entries(&stats, min_games);
+    let write = || -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(output)?);
+        for entry in &entries {
+            file.write_all(&entry.to_bytes())?;
+        }
+        file.flush()
+    };
+    if let Err(e) = write() {
+        eprintln!("Could not write {}: {}", output, e);
+        process::exit(1);
+    }
+    println!(
+        "{}: {} entries for {} positions",
+        output,
+        entries.len(),
+        entries
+            .iter()
+            .map(|entry| entry.key)
+            .collect::<HashSet<_>>()
+            .len()
+    );
+}
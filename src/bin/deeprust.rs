@@ -25,8 +25,10 @@ extern crate deeprust;
 // pub mod uci;
 
 // use clap::{App, Arg};
-use deeprust::engine::{Board, Search};
-use deeprust::interfaces::FenInterface;
+use deeprust::engine::{Board, Search, SearchLimits, Searcher};
+use deeprust::interfaces::epd::Epd;
+use deeprust::interfaces::{to_san, FenInterface};
+use deeprust::primitives::Move;
 
 use ansi_term::Colour::{Cyan, Green, Red};
 use log::LevelFilter;
@@ -52,29 +54,24 @@ fn batchperft(f: &str) {
         if line.trim().is_empty() || line.trim().starts_with('#') {
             continue;
         } else {
-            let parts: Vec<&str> = line.split(';').map(|l| l.trim()).collect();
-
-            let fen_str = parts[0];
-            let mut b = Board::from_fen_str(&fen_str).unwrap();
+            let epd = match line.parse::<Epd>() {
+                Ok(epd) => epd,
+                Err(e) => {
+                    println!("Line {}: {}", num + 1, e);
+                    num_error += 1;
+                    continue;
+                }
+            };
+            let mut b = Board::new();
+            b.set_position(&epd.position);
 
             let mut ok = true;
 
-            print!("perft({}) ", fen_str);
-            for depth_def in &parts[1..] {
-                let depth_def_parts: Vec<u64> = depth_def
-                    .split_whitespace()
-                    .map(|d| u64::from_str_radix(d, 10).unwrap())
-                    .collect();
-                let (depth, nodes) = (depth_def_parts[0], depth_def_parts[1]);
-                // print!("Running perft {} on '{}' expecting {} nodes...", depth, fen_str, nodes);
-                // print!("{}..", depth);
-                // io::stdout().flush().unwrap();
-                let res = b.perft(depth as u32);
-                if res.nodes == nodes {
-                    // println!("OK");
-                } else {
+            print!("perft({}) ", epd.position.to_fen_string());
+            for (depth, nodes) in epd.perft() {
+                let res = b.perft(depth);
+                if res.nodes != nodes {
                     ok = false;
-                    // println!("Line {} failed at depth {}: got {} instead of {} nodes", num + 1, depth, res.nodes, nodes);
                     debug!(
                         "Line {} failed at depth {}: got {} instead of {} nodes",
                         num + 1,
@@ -106,6 +103,104 @@ fn batchperft(f: &str) {
     );
 }
 
+/// Searches every position of an EPD test suite for `movetime`
+/// milliseconds, a position being solved when the move found is one of its
+/// `bm` moves and none of its `am` moves
+fn testsuite(f: &str, movetime: u64) {
+    let path = PathBuf::from(f);
+    let file = match File::open(&path) {
+        Err(why) => panic!("Could not open {}: {}", path.display(), why),
+        Ok(file) => file,
+    };
+
+    println!("Running test suite from {}", path.display());
+    debug!("Running test suite from {}", path.display());
+
+    let mut searcher = Searcher::new(16);
+    let limits = SearchLimits {
+        movetime: Some(movetime),
+        ..SearchLimits::default()
+    };
+    let (mut num_ok, mut num_error) = (0, 0);
+    let mut solution_time = 0;
+
+    for (num, line) in BufReader::new(file).lines().map(|l| l.unwrap()).enumerate() {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
+            continue;
+        }
+        let epd = match line.parse::<Epd>() {
+            Ok(epd) => epd,
+            Err(e) => {
+                println!("Line {}: {}", num + 1, e);
+                num_error += 1;
+                continue;
+            }
+        };
+        let (best, avoid) = (epd.best_moves(), epd.avoid_moves());
+        if best.is_empty() && avoid.is_empty() {
+            println!("Line {}: no bm or am operation", num + 1);
+            continue;
+        }
+        let solves = |mov: Move| (best.is_empty() || best.contains(&mov)) && !avoid.contains(&mov);
+        let sans = |moves: &[Move]| {
+            let sans: Vec<String> = moves.iter().map(|&m| to_san(&epd.position, m)).collect();
+            sans.join(" ")
+        };
+
+        let mut b = Board::new();
+        b.set_position(&epd.position);
+        searcher.clear();
+        // when the last change to a solving move happened, in nanoseconds
+        let mut solved_at = None;
+        let info = searcher.search(&mut b, limits, |info| match info.best_move().map(solves) {
+            Some(true) => solved_at = solved_at.or(Some(info.elapsed)),
+            _ => solved_at = None,
+        });
+
+        print!(
+            "{} ",
+            epd.id().map_or(format!("line {}", num + 1), String::from)
+        );
+        if !best.is_empty() {
+            print!("bm {} ", sans(best));
+        }
+        if !avoid.is_empty() {
+            print!("am {} ", sans(avoid));
+        }
+        let found = info
+            .best_move()
+            .map_or(String::from("none"), |m| to_san(&epd.position, m));
+        match solved_at {
+            Some(elapsed) if info.best_move().is_some_and(solves) => {
+                println!(
+                    "found {} in {:.3}s {}",
+                    found,
+                    elapsed as f64 / 1e9,
+                    Green.bold().paint("[OK]")
+                );
+                solution_time += elapsed;
+                num_ok += 1;
+            }
+            _ => {
+                println!("found {} {}", found, Red.bold().paint("[FAIL]"));
+                num_error += 1;
+            }
+        }
+        io::stdout().flush().unwrap();
+    }
+
+    let average = solution_time as f64 / 1e9 / f64::from(num_ok.max(1));
+    println!();
+    println!(
+        "Finished test suite: {} passed, {} failed, {:.3}s average time to solution.",
+        num_ok, num_error, average
+    );
+    debug!(
+        "Finished test suite: {} passed, {} failed, {:.3}s average time to solution.",
+        num_ok, num_error, average
+    );
+}
+
 fn main() {
     simple_logging::log_to_file("deeprust.log", LevelFilter::Debug).unwrap();
 
@@ -128,7 +223,8 @@ fn main() {
     //             .takes_value(true),
     //     )
     //     .get_matches();
-    if cfg!(windows) {
+    #[cfg(windows)]
+    {
         let _enabled = ansi_term::enable_ansi_support();
     }
 
@@ -138,6 +234,12 @@ fn main() {
         (about: "A chess playing program.")
         (@arg cli: -c --cli "Starts in CLI mode")
         (@arg FENFILE: -b --batch +takes_value "Runs perfts from a FEN file")
+        (@arg TESTSUITE: --testsuite +takes_value "Runs an EPD test suite of bm and am positions")
+        (@arg MOVETIME: --movetime +takes_value "Search time per test suite position in milliseconds (default: 1000)")
+        (@arg EVALFILE: -e --evalfile +takes_value "Loads evaluation parameters from a file")
+        (@arg NNUEFILE: -n --nnue +takes_value "Evaluates with the neural network in a file")
+        (@arg DTMPATH: --dtm +takes_value "Probes the DTM tablebases in a directory")
+        (@arg SYZYGYPATH: --syzygy +takes_value "Probes the Syzygy tablebases in directories")
         (@arg debug: -d ... "Sets the level of debugging information")
     )
     .get_matches();
@@ -149,8 +251,39 @@ fn main() {
             .value_of("FENFILE")
             .unwrap_or("tests/perftsuite.epd");
         batchperft(config);
+    } else if let Some(path) = matches.value_of("TESTSUITE") {
+        let movetime = if matches.is_present("MOVETIME") {
+            value_t!(matches, "MOVETIME", u64).unwrap_or_else(|e| e.exit())
+        } else {
+            1000
+        };
+        testsuite(path, movetime);
     } else {
         let mut c = deeprust::frontends::UCIFrontend::new();
+        if let Some(path) = matches.value_of("EVALFILE") {
+            if let Err(e) = c.load_eval_file(path) {
+                eprintln!("Could not load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(path) = matches.value_of("NNUEFILE") {
+            if let Err(e) = c.load_nnue_file(path) {
+                eprintln!("Could not load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(path) = matches.value_of("DTMPATH") {
+            if let Err(e) = c.load_dtm_path(path) {
+                eprintln!("Could not load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(path) = matches.value_of("SYZYGYPATH") {
+            if let Err(e) = c.load_syzygy_path(path) {
+                eprintln!("Could not load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
         c.run();
     }
 }
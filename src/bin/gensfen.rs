@@ -0,0 +1,250 @@
+//! Generates training data from self-play games
+//!
+//! Each game starts with a number of random moves from the initial position
+//! and is then played out by fixed-depth or fixed-node searches. Quiet
+//! positions, those where the side to move is not in check and the best move
+//! is neither a capture nor a promotion, are written one per line as
+//!
+//! ```text
+//! <FEN> | <score> | <result>
+//! ```
+//!
+//! with the search score in centipawns and the game result (`1.0`, `0.5` or
+//! `0.0`) both from white's point of view. The tuner reads this format as is.
+//!
+//! With `--pgn` the games are saved as well, with the search score of every
+//! move after the random opening in an `[%eval]` comment.
+
+#[macro_use]
+extern crate clap;
+
+extern crate deeprust;
+
+use deeprust::engine::{
+    Board, EvalParams, MoveGenerator, Network, Score, ScorePrimitives, SearchLimits, Searcher,
+};
+use deeprust::interfaces::pgn::{GameResult, PgnGame};
+use deeprust::interfaces::FenInterface;
+use deeprust::primitives::*;
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Games still undecided after this many plies are scored as draws
+const MAX_GAME_PLIES: usize = 400;
+
+struct Settings {
+    limits: SearchLimits,
+    random_moves: usize,
+    seed: u64,
+    network: Option<Arc<Network>>,
+}
+
+/// Plays random moves from the initial position, retrying until the
+/// opening doesn't end the game
+fn random_opening(rng: &mut Pcg64, plies: usize) -> Board {
+    'retry: loop {
+        let mut board = Board::startpos();
+        for _ in 0..plies {
+            match board.legal_moves().choose(rng) {
+                Some(&mov) => board.make_move(mov),
+                None => continue 'retry,
+            }
+        }
+        if !board.legal_moves().is_empty() {
+            return board;
+        }
+    }
+}
+
+/// Plays one game and returns its quiet positions with white-relative
+/// scores, and the game with the search scores of its moves
+fn play_game(
+    searcher: &mut Searcher,
+    settings: &Settings,
+    game: u64,
+) -> (Vec<(String, Score)>, PgnGame) {
+    let mut rng = Pcg64::seed_from_u64(settings.seed.wrapping_add(game));
+    let mut board = random_opening(&mut rng, settings.random_moves);
+    board.set_network(settings.network.clone());
+    searcher.clear();
+
+    let mut positions = Vec::new();
+    let mut scores = vec![None; board.history().len()];
+    let mut result = GameResult::Draw;
+    for _ in 0..MAX_GAME_PLIES {
+        let to_move = board.current().to_move();
+        // game result given whether the side to move won
+        let winner = |won: bool| {
+            if won == (to_move == colors::WHITE) {
+                GameResult::WhiteWins
+            } else {
+                GameResult::BlackWins
+            }
+        };
+
+        if board.is_draw() {
+            break;
+        }
+        if board.legal_moves().is_empty() {
+            if board.is_in_check(to_move) {
+                result = winner(false);
+            }
+            break;
+        }
+        let info = searcher.search(&mut board, settings.limits, |_| {});
+        // proven mates are played out no further
+        if info.score.is_mate() {
+            result = winner(info.score > 0);
+            break;
+        }
+        let mov = match info.best_move() {
+            Some(mov) => mov,
+            None => break,
+        };
+
+        let score = if to_move == colors::WHITE {
+            info.score
+        } else {
+            -info.score
+        };
+        if !board.is_in_check(to_move) && !mov.is_capture() && !mov.is_promotion() {
+            positions.push((board.current().to_fen_string(), score));
+        }
+        scores.push(Some(score));
+        board.make_move(mov);
+    }
+
+    let mut pgn = PgnGame::from_board(&board, result);
+    pgn.set_tag("Event", "gensfen");
+    pgn.set_tag("Round", &(game + 1).to_string());
+    pgn.set_tag("White", "deeprust");
+    pgn.set_tag("Black", "deeprust");
+    for (mov, &score) in pgn.moves.iter_mut().zip(scores.iter()) {
+        mov.annotate(score, None);
+    }
+    (positions, pgn)
+}
+
+fn main() {
+    let matches = clap_app!(gensfen =>
+        (version: env!("CARGO_PKG_VERSION"))
+        (author: env!("CARGO_PKG_AUTHORS"))
+        (about: "Generates scored positions from self-play games.")
+        (@arg OUTPUT: +required "File to write the positions to")
+        (@arg GAMES: -g --games +takes_value "Number of games to play")
+        (@arg DEPTH: -d --depth +takes_value "Search depth per move")
+        (@arg NODES: -n --nodes +takes_value "Node limit per move, instead of a depth")
+        (@arg RANDOM: -r --random +takes_value "Random plies at the start of each game")
+        (@arg THREADS: -t --threads +takes_value "Number of games played in parallel")
+        (@arg HASH: -H --hash +takes_value "Transposition table size per thread in MB")
+        (@arg SEED: -s --seed +takes_value "Seed for the random openings")
+        (@arg EVALFILE: -e --evalfile +takes_value "Loads evaluation parameters from a file")
+        (@arg NNUEFILE: --nnue +takes_value "Evaluates with the neural network in a file")
+        (@arg PGN: --pgn +takes_value "Also saves the games to a PGN file")
+    )
+    .get_matches();
+
+    let output = matches.value_of("OUTPUT").unwrap();
+    let games = value_t!(matches, "GAMES", u64).unwrap_or(100);
+    let hash = value_t!(matches, "HASH", usize).unwrap_or(16);
+    let limits = match value_t!(matches, "NODES", u64) {
+        Ok(nodes) => SearchLimits {
+            nodes: Some(nodes),
+            ..SearchLimits::default()
+        },
+        Err(_) => SearchLimits {
+            depth: Some(value_t!(matches, "DEPTH", u32).unwrap_or(6)),
+            ..SearchLimits::default()
+        },
+    };
+
+    let params = match matches.value_of("EVALFILE") {
+        Some(path) => EvalParams::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+    let network = matches.value_of("NNUEFILE").map(|path| {
+        let network = Network::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", path, e);
+            process::exit(1);
+        });
+        Arc::new(network)
+    });
+    let settings = Settings {
+        limits,
+        random_moves: value_t!(matches, "RANDOM", usize).unwrap_or(8),
+        seed: value_t!(matches, "SEED", u64).unwrap_or(0),
+        network,
+    };
+
+    if let Ok(threads) = value_t!(matches, "THREADS", usize) {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let create = |path: &str| {
+        let file = File::create(path).unwrap_or_else(|e| {
+            eprintln!("Could not create {}: {}", path, e);
+            process::exit(1);
+        });
+        Mutex::new(BufWriter::new(file))
+    };
+    let writer = create(output);
+    let pgn_path = matches.value_of("PGN");
+    let pgn_writer = pgn_path.map(create);
+    let finished = AtomicUsize::new(0);
+    let written = AtomicUsize::new(0);
+
+    let result = (0..games).into_par_iter().map_init(
+        || {
+            let mut searcher = Searcher::new(hash);
+            searcher.set_params(params.clone());
+            searcher
+        },
+        |searcher, game| -> Result<(), (&str, io::Error)> {
+            let (positions, pgn) = play_game(searcher, &settings, game);
+            let result = match pgn.result {
+                GameResult::WhiteWins => 1.0,
+                GameResult::BlackWins => 0.0,
+                _ => 0.5,
+            };
+            let write = || -> io::Result<()> {
+                let mut writer = writer.lock().unwrap();
+                for (fen, score) in positions.iter() {
+                    writeln!(writer, "{} | {} | {:.1}", fen, score, result)?;
+                }
+                writer.flush()
+            };
+            write().map_err(|e| (output, e))?;
+            if let (Some(path), Some(pgn_writer)) = (pgn_path, &pgn_writer) {
+                let write = || -> io::Result<()> {
+                    let mut pgn_writer = pgn_writer.lock().unwrap();
+                    writeln!(pgn_writer, "{}", pgn)?;
+                    pgn_writer.flush()
+                };
+                write().map_err(|e| (path, e))?;
+            }
+
+            let total = written.fetch_add(positions.len(), Ordering::Relaxed) + positions.len();
+            let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+            println!("{} of {} games, {} positions", done, games, total);
+            Ok(())
+        },
+    );
+    if let Err((path, e)) = result.collect::<Result<(), _>>() {
+        eprintln!("Could not write {}: {}", path, e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Generates distance-to-mate tablebases
+//!
+//! Without table names, all endings with up to four pieces are generated.
+//! Tables an ending captures or promotes into are generated first, and
+//! tables already in the output directory are loaded instead of generated
+//! again.
+
+#[macro_use]
+extern crate clap;
+
+extern crate deeprust;
+
+use deeprust::engine::{Material, Table, Tablebases, MAX_PIECES};
+
+use quanta::Clock;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// Makes sure there is a table for `material` and all it depends on
+fn generate(material: Material, dir: &Path, tablebases: &mut Tablebases, clock: &Clock) {
+    if tablebases.contains(&material) {
+        return;
+    }
+    for dep in material.dependencies() {
+        generate(dep, dir, tablebases, clock);
+    }
+
+    let path = dir.join(format!("{}.dtm", material));
+    if let Ok(table) = Table::read(&path) {
+        println!("{}: loaded {}", material, path.display());
+        tablebases.insert(table);
+        return;
+    }
+
+    let start = clock.now();
+    let table = Table::generate(&material, tablebases);
+    let seconds = (clock.now() - start) as f64 / 1e9;
+    let (wins, losses, draws, longest) = table.stats();
+    println!(
+        "{}: {} wins, {} losses, {} draws, longest mate {} plies ({:.1}s)",
+        material, wins, losses, draws, longest, seconds
+    );
+    if let Err(e) = table.write(dir) {
+        eprintln!("Could not write {}: {}", path.display(), e);
+        process::exit(1);
+    }
+    tablebases.insert(table);
+}
+
+fn main() {
+    let matches = clap_app!(tbgen =>
+        (version: env!("CARGO_PKG_VERSION"))
+        (author: env!("CARGO_PKG_AUTHORS"))
+        (about: "Generates distance-to-mate tablebases.")
+        (@arg OUTPUT: -o --output +takes_value "Directory to write the tables to")
+        (@arg THREADS: -t --threads +takes_value "Number of threads to generate with")
+        (@arg TABLES: ... "Endings to generate, like KRKP, instead of all")
+    )
+    .get_matches();
+
+    let dir = Path::new(matches.value_of("OUTPUT").unwrap_or("tablebases"));
+    let materials = match matches.values_of("TABLES") {
+        Some(names) => names
+            .map(|name| match name.parse::<Material>() {
+                Ok(material) if material.count() <= MAX_PIECES => material.canonical().0,
+                _ => {
+                    eprintln!("Not an ending of up to {} pieces: {}", MAX_PIECES, name);
+                    process::exit(1);
+                }
+            })
+            .collect(),
+        None => Material::all(MAX_PIECES),
+    };
+
+    if let Ok(threads) = value_t!(matches, "THREADS", usize) {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+    if let Err(e) = fs::create_dir_all(dir) {
+        eprintln!("Could not create {}: {}", dir.display(), e);
+        process::exit(1);
+    }
+
+    let clock = Clock::new();
+    let mut tablebases = Tablebases::new();
+    for material in materials {
+        if material.is_insufficient() {
+            println!("{}: drawn by insufficient material", material);
+            continue;
+        }
+        generate(material, dir, &mut tablebases, &clock);
+    }
+}
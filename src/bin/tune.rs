@@ -0,0 +1,254 @@
+//! Texel-style tuning of the evaluation parameters
+//!
+//! Reads quiet positions labelled with the game result, one per line, as a
+//! FEN (the move counters may be left out) followed by the result, either
+//! as a PGN result (`1-0`, `0-1`, `1/2-1/2`, quoted or not, e.g. in an EPD
+//! `c9` opcode) or as a number from white's point of view (`[1.0]`, `0.5`).
+//!
+//! The parameters are fitted by local search, minimizing the mean squared
+//! error between the results and the static evaluation mapped through
+//! `sigmoid(K * eval)`, see https://www.chessprogramming.org/Texel%27s_Tuning_Method
+
+#[macro_use]
+extern crate clap;
+
+extern crate deeprust;
+
+use deeprust::engine::{EvalParams, Evaluation, Position, Score};
+use deeprust::interfaces::FenInterface;
+use deeprust::primitives::colors;
+
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+
+struct Sample {
+    pos: Position,
+    /// Game result from white's point of view, 1 for a win
+    result: f64,
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c| c == '"' || c == ';' || c == '[' || c == ']');
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        _ => token
+            .parse::<f64>()
+            .ok()
+            .filter(|r| (0.0..=1.0).contains(r)),
+    }
+}
+
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 5 {
+        return Err("expected a FEN and a result".to_string());
+    }
+    let result = tokens[4..]
+        .iter()
+        .rev()
+        .find_map(|token| parse_result(token))
+        .ok_or_else(|| "no game result".to_string())?;
+
+    // the half- and fullmove counters are optional
+    let counters =
+        tokens.len() >= 6 && tokens[4].parse::<u32>().is_ok() && tokens[5].parse::<u32>().is_ok();
+    let fen = if counters {
+        tokens[..6].join(" ")
+    } else {
+        format!("{} 0 1", tokens[..4].join(" "))
+    };
+    let pos = Position::from_fen_str(&fen).map_err(|e| e.to_string())?;
+    Ok(Sample { pos, result })
+}
+
+fn read_samples(path: &str) -> io::Result<Vec<Sample>> {
+    let file = File::open(path)?;
+    let mut samples = Vec::new();
+    for (num, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.trim().starts_with('#') {
+            continue;
+        }
+        match parse_sample(&line) {
+            Ok(sample) => samples.push(sample),
+            Err(e) => eprintln!("Skipping line {}: {}", num + 1, e),
+        }
+    }
+    Ok(samples)
+}
+
+#[inline]
+fn sigmoid(k: f64, score: Score) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * f64::from(score) / 400.0))
+}
+
+/// Mean squared error of the predicted results over all samples
+fn error(samples: &[Sample], params: &EvalParams, k: f64) -> f64 {
+    let sum: f64 = samples
+        .par_iter()
+        .map(|sample| {
+            let score = sample.pos.evaluate_with(params);
+            let score = if sample.pos.to_move() == colors::WHITE {
+                score
+            } else {
+                -score
+            };
+            (sample.result - sigmoid(k, score)).powi(2)
+        })
+        .sum();
+    sum / samples.len() as f64
+}
+
+/// Finds the scaling constant that best maps the current evaluation to the
+/// results, so the tuner doesn't just rescale all parameters
+fn find_k(samples: &[Sample], params: &EvalParams) -> f64 {
+    let mut k = 1.0;
+    let mut best = error(samples, params, k);
+    for &step in &[0.1, 0.01, 0.001] {
+        loop {
+            let (up, down) = (k + step, k - step);
+            let (up_error, down_error) = (error(samples, params, up), error(samples, params, down));
+            if up_error < best && up_error <= down_error {
+                k = up;
+                best = up_error;
+            } else if down_error < best && down > 0.0 {
+                k = down;
+                best = down_error;
+            } else {
+                break;
+            }
+        }
+    }
+    k
+}
+
+fn write_params(path: &str, params: &EvalParams) -> io::Result<()> {
+    fs::write(
+        path,
+        format!("# deeprust evaluation parameters\n{}", params),
+    )
+}
+
+/// Finds the parameters that never come into play (like the material value
+/// of a king): neither raising nor lowering them by `step` changes the error
+fn find_unused(samples: &[Sample], params: &mut EvalParams, k: f64, step: Score) -> Vec<bool> {
+    let mut values = params.values();
+    let base = error(samples, params, k);
+    let unused = (0..values.len())
+        .map(|i| {
+            [step, -step].iter().all(|&delta| {
+                values[i] += delta;
+                params.set_values(&values);
+                let e = error(samples, params, k);
+                values[i] -= delta;
+                (e - base).abs() < f64::EPSILON
+            })
+        })
+        .collect();
+    params.set_values(&values);
+    unused
+}
+
+fn tune(
+    samples: &[Sample],
+    params: &mut EvalParams,
+    k: f64,
+    step: Score,
+    iterations: usize,
+    output: &str,
+) -> io::Result<()> {
+    let mut values = params.values();
+    let mut best = error(samples, params, k);
+    println!("Initial error: {:.8}", best);
+
+    let unused = find_unused(samples, params, k, step);
+    println!(
+        "Skipping {} of {} parameters that don't affect the error",
+        unused.iter().filter(|&&u| u).count(),
+        values.len()
+    );
+
+    for iteration in 1..=iterations {
+        let mut improved = 0;
+        for i in 0..values.len() {
+            if unused[i] {
+                continue;
+            }
+            for &delta in &[step, -step] {
+                values[i] += delta;
+                params.set_values(&values);
+                let e = error(samples, params, k);
+                if e < best {
+                    best = e;
+                    improved += 1;
+                    break;
+                }
+                values[i] -= delta;
+            }
+        }
+        params.set_values(&values);
+        write_params(output, params)?;
+        println!(
+            "Iteration {}: error {:.8}, {} parameters changed",
+            iteration, best, improved
+        );
+        io::stdout().flush()?;
+        if improved == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let matches = clap_app!(tune =>
+        (version: env!("CARGO_PKG_VERSION"))
+        (author: env!("CARGO_PKG_AUTHORS"))
+        (about: "Tunes the evaluation parameters on labelled positions.")
+        (@arg INPUT: +required "File with one FEN and game result per line")
+        (@arg PARAMS: -p --params +takes_value "Parameters to start from instead of the defaults")
+        (@arg OUTPUT: -o --output +takes_value "Where to write the tuned parameters")
+        (@arg ITERATIONS: -i --iterations +takes_value "Maximum number of passes over all parameters")
+        (@arg STEP: -s --step +takes_value "Amount to change a parameter by per try")
+        (@arg K: -k +takes_value "Sigmoid scaling constant, computed if not given")
+    )
+    .get_matches();
+
+    let output = matches.value_of("OUTPUT").unwrap_or("tuned.params");
+    let iterations = value_t!(matches, "ITERATIONS", usize).unwrap_or(100);
+    let step = value_t!(matches, "STEP", Score).unwrap_or(1);
+    let input = matches.value_of("INPUT").unwrap();
+
+    let samples = match read_samples(input) {
+        Ok(samples) if !samples.is_empty() => samples,
+        Ok(_) => {
+            eprintln!("No positions in {}", input);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not read {}: {}", input, e);
+            process::exit(1);
+        }
+    };
+    println!("Loaded {} positions", samples.len());
+
+    let mut params = match matches.value_of("PARAMS") {
+        Some(path) => EvalParams::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+    let k = value_t!(matches, "K", f64).unwrap_or_else(|_| find_k(&samples, &params));
+    println!("K = {:.3}", k);
+
+    if let Err(e) = tune(&samples, &mut params, k, step, iterations, output) {
+        eprintln!("Could not write {}: {}", output, e);
+        process::exit(1);
+    }
+    println!("Wrote tuned parameters to {}", output);
+}
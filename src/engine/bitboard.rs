@@ -265,6 +265,56 @@ pub mod bitboards {
             arr
         };
 
+        /// Files directly left and right of a file
+        pub static ref BB_ADJACENT_FILES: [Bitboard; 8] = {
+            let mut arr: [Bitboard; 8] = [0; 8];
+            for (file, adjacent) in arr.iter_mut().enumerate() {
+                *adjacent = east_one(BB_FILES[file]) | west_one(BB_FILES[file]);
+            }
+            arr
+        };
+
+        /// All ranks in front of a rank, as seen by white and black
+        pub static ref BB_FORWARD_RANKS: [[Bitboard; 8]; 2] = {
+            let mut arr: [[Bitboard; 8]; 2] = [[0; 8]; 2];
+            for rank in 0..8 {
+                arr[0][rank] = north_fill(north_one(BB_RANKS[rank]));
+                arr[1][rank] = south_fill(south_one(BB_RANKS[rank]));
+            }
+            arr
+        };
+
+        /// Squares in front of a square on the same file, per color
+        pub static ref BB_FORWARD_FILE: [[Bitboard; 64]; 2] = {
+            let mut arr: [[Bitboard; 64]; 2] = [[0; 64]; 2];
+            for i in 0..64 {
+                arr[0][i] = north_fill(north_one(BB_SQUARES[i]));
+                arr[1][i] = south_fill(south_one(BB_SQUARES[i]));
+            }
+            arr
+        };
+
+        /// Squares a pawn may attack while advancing, per color
+        pub static ref BB_PAWN_ATTACK_SPAN: [[Bitboard; 64]; 2] = {
+            let mut arr: [[Bitboard; 64]; 2] = [[0; 64]; 2];
+            for i in 0..64 {
+                let sides = east_one(BB_SQUARES[i]) | west_one(BB_SQUARES[i]);
+                arr[0][i] = north_fill(north_one(sides));
+                arr[1][i] = south_fill(south_one(sides));
+            }
+            arr
+        };
+
+        /// Squares that must be free of enemy pawns for a pawn to be passed
+        pub static ref BB_PASSED_PAWN_MASK: [[Bitboard; 64]; 2] = {
+            let mut arr: [[Bitboard; 64]; 2] = [[0; 64]; 2];
+            for i in 0..64 {
+                arr[0][i] = BB_FORWARD_FILE[0][i] | BB_PAWN_ATTACK_SPAN[0][i];
+                arr[1][i] = BB_FORWARD_FILE[1][i] | BB_PAWN_ATTACK_SPAN[1][i];
+            }
+            arr
+        };
+
         pub static ref BB_DIAG: [Bitboard; 64] = {
             let mut arr: [Bitboard; 64] = [0; 64];
             for i in 0i64..64i64 {
@@ -477,6 +527,23 @@ pub mod bitboards {
     #[rustfmt::skip] #[inline] pub const fn west_one(bb: Bitboard) -> Bitboard       { (bb & BB_NOT_FILE_A) >> 1 }
     #[rustfmt::skip] #[inline] pub const fn north_west_one(bb: Bitboard) -> Bitboard { (bb & BB_NOT_FILE_A) << 7 }
 
+    /// see https://www.chessprogramming.org/Pawn_Fills
+    #[inline]
+    pub const fn north_fill(mut bb: Bitboard) -> Bitboard {
+        bb |= bb << 8;
+        bb |= bb << 16;
+        bb |= bb << 32;
+        bb
+    }
+
+    #[inline]
+    pub const fn south_fill(mut bb: Bitboard) -> Bitboard {
+        bb |= bb >> 8;
+        bb |= bb >> 16;
+        bb |= bb >> 32;
+        bb
+    }
+
     /// see https://chessprogramming.org/Flipping_Mirroring_and_Rotating
     pub const fn flip_diag_a1h8(mut bb: Bitboard) -> Bitboard {
         let k1 = 0x5500_5500_5500_5500;
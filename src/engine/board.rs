@@ -2,18 +2,22 @@ use std::fmt;
 
 use crate::engine::bitboard::*;
 
+use crate::error::Error;
 use crate::interfaces::lan;
-use crate::interfaces::lan::LanParseError;
+use crate::interfaces::san;
 use crate::interfaces::FenInterface;
 // use crate::primitives::r#move::{Move, MoveStack};
 // use crate::primitives::piece::Piece;
 use crate::engine::position::{Position, PositionStack};
+use crate::engine::{Accumulator, Network, Score};
 // use crate::primitives::square::{Square, SquarePrimitives};
 
 use crate::primitives::*;
 
 //use regex::Regex;
 
+use std::sync::Arc;
+
 //use std::str::FromStr;
 
 pub const PSTACK_SIZE: usize = 64;
@@ -23,6 +27,9 @@ pub struct Board {
     positions: PositionStack,
     pcursor: usize,
     history: MoveStack,
+    /// Whether moves are read as in Chess960, castling only given by the
+    /// king taking its own rook
+    chess960: bool,
 }
 
 impl fmt::Debug for Board {
@@ -64,6 +71,7 @@ impl Board {
             positions: vec![Position::new(); PSTACK_SIZE],
             pcursor: 0,
             history: MoveStack::with_capacity(32),
+            chess960: false,
         }
     }
 
@@ -82,9 +90,57 @@ impl Board {
         // Self::from_fen_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     }
 
+    /// Sets up `position`, evaluated by the board's network if one is set
     #[inline]
     pub fn set_position(&mut self, position: &Position) {
-        self.positions[self.pcursor] = *position;
+        let network = self.network_arc();
+        self.positions[self.pcursor] = position.clone();
+        self.positions[self.pcursor].set_network(network);
+    }
+
+    #[inline]
+    fn network_arc(&self) -> Option<Arc<Network>> {
+        self.current()
+            .accumulator()
+            .map(|acc| Arc::clone(acc.network()))
+    }
+
+    /// Reads moves as in Chess960 from now on, see `find_legal_move`
+    #[inline]
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    #[inline]
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Evaluates with `network` from now on, see `Position::set_network`.
+    /// The current position and the ones before it compute their hidden
+    /// layers from scratch, later ones inherit them through `make_move`.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        for position in self.positions[..=self.pcursor].iter_mut() {
+            position.set_network(network.clone());
+        }
+    }
+
+    #[inline]
+    pub fn network(&self) -> Option<&Network> {
+        self.current().network()
+    }
+
+    /// Hidden layer of the network for the current position, if one is set
+    #[inline]
+    pub fn accumulator(&self) -> Option<&Accumulator> {
+        self.current().accumulator()
+    }
+
+    /// Evaluation of the current position by the network, from the side to
+    /// move's point of view, if one is set
+    #[inline]
+    pub fn evaluate_network(&self) -> Option<Score> {
+        self.current().evaluate_network()
     }
 
     #[inline]
@@ -97,12 +153,19 @@ impl Board {
         &self.history
     }
 
+    /// The position the moves of `history` were played from
+    #[inline]
+    pub fn root(&self) -> &Position {
+        &self.positions[self.pcursor - self.history.len()]
+    }
+
     #[inline]
     pub fn make_move(&mut self, mov: Move) {
-        debug_assert!(self.pcursor + 1 < PSTACK_SIZE);
+        self.reserve_position();
         // self.positions[self.pcursor + 1] = self.current().clone();
-        self.positions[self.pcursor + 1] = self.positions[self.pcursor]; //*self.current();
-        self.positions[self.pcursor + 1].make_move(mov);
+        let (done, next) = self.positions.split_at_mut(self.pcursor + 1);
+        next[0].clone_from(&done[self.pcursor]);
+        next[0].make_move(mov);
         self.history.push(mov);
         self.pcursor += 1;
     }
@@ -114,28 +177,109 @@ impl Board {
         self.pcursor -= 1;
     }
 
+    /// Grows the position stack if the next move would run past its end
+    #[inline]
+    fn reserve_position(&mut self) {
+        if self.pcursor + 1 == self.positions.len() {
+            self.positions.push(Position::new());
+        }
+    }
+
+    /// Number of half moves played since the root position was set up
+    #[inline]
+    pub fn ply(&self) -> usize {
+        self.pcursor
+    }
+
+    /// Checks whether the current position occurred before, looking back
+    /// no further than the last capture or pawn move
+    pub fn is_repetition(&self) -> bool {
+        let current = self.current();
+        let lookback = (current.halfmoves() as usize).min(self.pcursor);
+        (4..=lookback)
+            .step_by(2)
+            .any(|back| self.positions[self.pcursor - back].hash() == current.hash())
+    }
+
+    /// Checks for draws by repetition or the fifty move rule
+    pub fn is_draw(&self) -> bool {
+        self.current().halfmoves() >= 100 || self.is_repetition()
+    }
+
+    /// The legal moves in the current position
+    #[inline]
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.current().legal_moves()
+    }
+
+    /// The legal move from `orig` to `dest`, promoting to `promote_to`.
+    /// Castling is given by the square of the rook, as in Chess960, or
+    /// outside of Chess960 by the king's destination if it starts on the
+    /// e-file.
+    fn find_legal_move(&self, orig: Square, dest: Square, promote_to: Option<Piece>) -> Option<Move> {
+        let castling = self.current().castling();
+        let color = self.current().to_move();
+        self.legal_moves().into_iter().find(|&mov| {
+            if mov.orig() != orig {
+                false
+            } else if mov.is_promotion() {
+                mov.dest() == dest && promote_to.map(Piece::code) == Some(mov.prom_piece_code())
+            } else if promote_to.is_some() {
+                false
+            } else if mov.is_king_castle() || mov.is_queen_castle() {
+                let side = if mov.is_king_castle() {
+                    sides::KING_SIDE
+                } else {
+                    sides::QUEEN_SIDE
+                };
+                let rook = (orig & !7) + castling.rook_file(color, side);
+                dest == rook || (!self.chess960 && dest == mov.dest() && orig & 7 == 4)
+            } else {
+                mov.dest() == dest
+            }
+        })
+    }
+
+    /// The legal move written as `uci_move` in coordinate notation, see
+    /// `find_legal_move` for castling
+    pub fn parse_uci_move(&self, uci_move: &str) -> Result<Move, Error> {
+        let parsed = lan(uci_move)?;
+        self.find_legal_move(parsed.from, parsed.to, parsed.prom)
+            .ok_or_else(|| Error::IllegalMove(uci_move.to_string()))
+    }
+
+    /// Plays the legal move from `orig` to `dest`, see `find_legal_move`
     pub fn input_move(
         &mut self,
         orig: Square,
         dest: Square,
         promote_to: Option<Piece>,
-    ) -> Result<bool, &'static str> {
-        debug_assert!(self.pcursor + 1 < PSTACK_SIZE);
-        self.positions[self.pcursor + 1] = *self.current();
-        match self.positions[self.pcursor + 1].input_move(orig, dest, promote_to) {
-            Ok(mov) => {
-                self.history.push(mov);
-                self.pcursor += 1;
-                Ok(true)
+    ) -> Result<Move, Error> {
+        match self.find_legal_move(orig, dest, promote_to) {
+            Some(mov) => {
+                self.make_move(mov);
+                Ok(mov)
             }
-            Err(err) => Err(err),
+            None => Err(Error::IllegalMove(format!(
+                "{}{}",
+                orig.to_san_string(),
+                dest.to_san_string()
+            ))),
         }
     }
 
-    pub fn input_san_move(&mut self, san_move: &str) -> Result<(), LanParseError> {
-        let lan_mov = lan(san_move)?;
-        self.input_move(lan_mov.from, lan_mov.to, lan_mov.prom).unwrap();
-        Ok(())
+    /// Plays the legal move written as `lan_move` in coordinate notation
+    pub fn input_lan_move(&mut self, lan_move: &str) -> Result<Move, Error> {
+        let mov = self.parse_uci_move(lan_move)?;
+        self.make_move(mov);
+        Ok(mov)
+    }
+
+    /// Plays the legal move written as `san_move` in algebraic notation
+    pub fn input_san_move(&mut self, san_move: &str) -> Result<Move, Error> {
+        let mov = san::parse_san(self.current(), san_move)?;
+        self.make_move(mov);
+        Ok(mov)
     }
 
     pub fn panic_dump(&self) {
@@ -147,7 +291,9 @@ impl Board {
 mod tests {
     use super::*;
 
-    use crate::engine::Search;
+    use crate::engine::{MoveGenerator, Search};
+    use crate::interfaces::epd::Epd;
+    use crate::interfaces::fen::{read_fen, FenErrorKind, FenField, FenParseError};
     use crate::primitives::colors;
     // use crate::primitives::square;
     use std::error::Error;
@@ -215,17 +361,30 @@ mod tests {
             Ok(file) => file,
         };
 
+        // the file has one en passant square no pawn can just have passed
+        let mut rejected = 0;
         for (line, position) in BufReader::new(posfile)
             .lines()
             .map(|l| l.unwrap())
             .enumerate()
         {
-            let b = Board::from_fen_str(&position);
-            match b {
-                Err(e) => panic!("Error reading {}:{}:{}", pospath.display(), line, e),
-                Ok(board) => assert_eq!(position, board.to_fen_string()),
+            match position.parse::<Epd>() {
+                Err(e) => {
+                    let error = read_fen(&position).err().unwrap();
+                    assert_eq!(
+                        (FenField::EnPassant, FenErrorKind::ImpossibleEnPassant),
+                        (error.field, error.kind),
+                        "Error reading {}:{}:{}",
+                        pospath.display(),
+                        line,
+                        e
+                    );
+                    rejected += 1;
+                }
+                Ok(epd) => assert_eq!(position, epd.position.to_fen_string()),
             }
         }
+        assert_eq!(1, rejected);
     }
 
     #[test]
@@ -234,7 +393,7 @@ mod tests {
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq abcdefg 0 1",
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR y KQkq e3 0 1",
-            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HFhf e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx e3 0 1",
         ];
 
         for fen_str in fen_strs {
@@ -246,6 +405,208 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_explains_invalid_fen_strings() {
+        let errors = vec![
+            ("", FenField::Placement, 0, FenErrorKind::Empty),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenField::ToMove,
+                43,
+                FenErrorKind::Missing,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR y KQkq - 0 1",
+                FenField::ToMove,
+                44,
+                FenErrorKind::Invalid,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+                FenField::Castling,
+                49,
+                FenErrorKind::Invalid,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+                FenField::Fullmoves,
+                54,
+                FenErrorKind::Missing,
+            ),
+            (
+                "rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::Placement,
+                9,
+                FenErrorKind::RankLength(9),
+            ),
+            // even if the squares add up to 64
+            (
+                "rnbqkbnr/ppppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::Placement,
+                9,
+                FenErrorKind::RankLength(9),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenField::Placement,
+                0,
+                FenErrorKind::RankCount(7),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w kq - 0 1",
+                FenField::Placement,
+                0,
+                FenErrorKind::KingCount(colors::WHITE, 0),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+                FenField::Placement,
+                16,
+                FenErrorKind::PawnOnBackRank,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+                FenField::EnPassant,
+                24,
+                FenErrorKind::ImpossibleEnPassant,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - e3 0 1",
+                FenField::EnPassant,
+                48,
+                FenErrorKind::ImpossibleEnPassant,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenField::Castling,
+                22,
+                FenErrorKind::CastlingWithoutPieces('K'),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w kH - 0 1",
+                FenField::Castling,
+                22,
+                FenErrorKind::CastlingWithoutPieces('k'),
+            ),
+            (
+                "4k3/8/8/8/8/8/4K3/7R w K - 0 1",
+                FenField::Castling,
+                23,
+                FenErrorKind::CastlingWithoutPieces('K'),
+            ),
+            (
+                "4k2P/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenField::Placement,
+                0,
+                FenErrorKind::PawnOnBackRank,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/r3K3 b - - 0 1",
+                FenField::ToMove,
+                21,
+                FenErrorKind::OpponentInCheck,
+            ),
+        ];
+
+        for (fen_str, field, offset, kind) in errors {
+            let error = FenParseError {
+                field,
+                offset,
+                kind,
+            };
+            assert_eq!(Err(error), read_fen(fen_str).map(|_| ()));
+        }
+        assert_eq!(
+            "invalid FEN: castling rights at offset 22: no king and rook at home for K",
+            Board::from_fen_str("4k3/8/8/8/8/8/8/4K3 w K - 0 1")
+                .err()
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn it_reads_shredder_and_x_fen_castling() {
+        let fen_strs = vec![
+            // files of the outermost rooks are written as KQkq
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Hh - 0 1",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Kk - 0 1",
+            ),
+            // Chess960 start positions
+            (
+                "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1",
+                "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w KQkq - 0 1",
+            ),
+            // inner rooks need their files
+            (
+                "4k3/8/8/8/8/8/8/R1R1K1RR w CG - 0 1",
+                "4k3/8/8/8/8/8/8/R1R1K1RR w GC - 0 1",
+            ),
+            (
+                "r1r1k2r/8/8/8/8/8/8/4K2R b cK - 0 1",
+                "r1r1k2r/8/8/8/8/8/8/4K2R b Kc - 0 1",
+            ),
+        ];
+        for (fen_str, written) in fen_strs {
+            let board = Board::from_fen_str(fen_str).unwrap();
+            assert_eq!(written, board.to_fen_string());
+            assert_eq!(
+                written,
+                Board::from_fen_str(written).unwrap().to_fen_string()
+            );
+        }
+
+        let castling = Board::from_fen_str("4k3/8/8/8/8/8/8/R1R1K1RR w GC - 0 1")
+            .unwrap()
+            .current()
+            .castling();
+        assert_eq!(6, castling.rook_file(colors::WHITE, sides::KING_SIDE));
+        assert_eq!(2, castling.rook_file(colors::WHITE, sides::QUEEN_SIDE));
+
+        // only moves of the castling rooks clear rights
+        let mut board = Board::from_fen_str("4k3/8/8/8/8/8/8/R1R1K1RR w CG - 0 1").unwrap();
+        board.make_move(Move::new(squares::H1, squares::H2, flags::MOV_QUIET));
+        assert_eq!(
+            "4k3/8/8/8/8/8/7R/R1R1K1R1 b KC - 1 1",
+            board.to_fen_string()
+        );
+        board.make_move(Move::new(squares::E8, squares::D8, flags::MOV_QUIET));
+        board.make_move(Move::new(squares::G1, squares::G2, flags::MOV_QUIET));
+        assert_eq!("3k4/8/8/8/8/8/6RR/R1R1K3 b C - 3 2", board.to_fen_string());
+    }
+
+    #[test]
+    fn it_counts_chess960_perft() {
+        let pospath = Path::new("tests/chess960.epd");
+        let posfile = match File::open(pospath) {
+            Err(why) => panic!("Could not open {}: {}", pospath.display(), why),
+            Ok(file) => file,
+        };
+
+        for line in BufReader::new(posfile).lines().map(|l| l.unwrap()) {
+            if line.trim().is_empty() || line.trim().starts_with('#') {
+                continue;
+            }
+            let epd = line.parse::<Epd>().unwrap();
+            let mut board = Board::new();
+            board.set_position(&epd.position);
+            for (depth, nodes) in epd.perft().into_iter().filter(|&(d, _)| d <= 4) {
+                assert_eq!(
+                    nodes,
+                    board.perft(depth).nodes,
+                    "{} at depth {}",
+                    line,
+                    depth
+                );
+            }
+        }
+    }
+
     #[test]
     fn it_makes_moves() {
         if let Ok(mut board) =
@@ -260,12 +621,75 @@ mod tests {
             assert_eq!(last_move.dest(), squares::D6);
         }
 
-        let mut board = Board::from_fen_str("8/3p4/8/4P3/8/8/8/8 b - - 0 1").unwrap();
+        let mut board = Board::from_fen_str("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1").unwrap();
         board.input_move(squares::D7, squares::D5, None).unwrap();
         board.input_move(squares::E5, squares::D6, None).unwrap();
         assert_eq!(0, board.current().occupied()[squares::D5 as usize]);
     }
 
+    #[test]
+    fn it_rejects_illegal_moves() {
+        let board = Board::startpos();
+        let mov = board.parse_uci_move("e2e4").unwrap();
+        assert_eq!((squares::E2, squares::E4), (mov.orig(), mov.dest()));
+        for illegal in &["e2e5", "e3e4", "e7e5", "e1e2"] {
+            assert!(matches!(
+                board.parse_uci_move(illegal),
+                Err(crate::error::Error::IllegalMove(_))
+            ));
+        }
+        assert!(matches!(
+            board.parse_uci_move("e2"),
+            Err(crate::error::Error::Lan(_))
+        ));
+
+        // pinned pieces, promotions and castling through check
+        let illegal = [
+            ("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "e2d3"),
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8"),
+            ("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1q"),
+            ("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1", "e1g1"),
+            ("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1", "e1h1"),
+        ];
+        for (fen, mov) in illegal.iter() {
+            let mut board = Board::from_fen_str(fen).unwrap();
+            assert!(
+                board.parse_uci_move(mov).is_err(),
+                "{} in {}",
+                mov,
+                fen
+            );
+            assert!(board.input_lan_move(mov).is_err());
+            assert_eq!(*fen, board.to_fen_string());
+            assert!(board.history().is_empty());
+        }
+
+        let board = Board::from_fen_str("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mov = board.parse_uci_move("a7a8n").unwrap();
+        assert_eq!(piece_types::KNIGHT, mov.prom_piece_code());
+        let mut board = Board::from_fen_str("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1").unwrap();
+        assert!(board
+            .parse_uci_move("e1c1")
+            .unwrap()
+            .is_queen_castle());
+        assert!(board
+            .parse_uci_move("e1a1")
+            .unwrap()
+            .is_queen_castle());
+        board.set_chess960(true);
+        assert!(board
+            .parse_uci_move("e1a1")
+            .unwrap()
+            .is_queen_castle());
+        assert!(board.parse_uci_move("e1c1").is_err());
+        assert!(board.input_lan_move("e1c1").is_err());
+
+        board
+            .input_move(squares::E3, squares::E4, None)
+            .unwrap_err();
+        assert_eq!(0, board.history().len());
+    }
+
     // TODO: move to UCI tests
     // #[test]
     fn it_unmakes_moves() {
@@ -315,6 +739,50 @@ mod tests {
         }
     }
 
+    fn check_hashes(board: &mut Board, depth: u32) {
+        let fen_board = Board::from_fen_str(&board.to_fen_string()).unwrap();
+        assert_eq!(fen_board.current().hash(), board.current().hash());
+        assert_eq!(fen_board.current().pawn_hash(), board.current().pawn_hash());
+        if depth == 0 {
+            return;
+        }
+        for mov in board.generate_moves() {
+            board.make_move(mov);
+            if !board.is_in_check(1 ^ board.current().to_move()) {
+                check_hashes(board, depth - 1);
+            }
+            board.unmake_move();
+        }
+    }
+
+    #[test]
+    fn it_updates_hashes_incrementally() {
+        let mut board = Board::from_fen_str(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        check_hashes(&mut board, 3);
+
+        let mut board = Board::from_fen_str("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+        check_hashes(&mut board, 3);
+    }
+
+    #[test]
+    fn it_detects_repetitions() {
+        let mut board = Board::startpos();
+        for (orig, dest) in &[
+            (squares::G1, squares::F3),
+            (squares::G8, squares::F6),
+            (squares::F3, squares::G1),
+            (squares::F6, squares::G8),
+        ] {
+            assert!(!board.is_repetition());
+            board.input_move(*orig, *dest, None).unwrap();
+        }
+        assert!(board.is_repetition());
+        assert!(board.is_draw());
+    }
+
     #[test]
     fn it_unwinds_its_move_stack() {
         {
@@ -0,0 +1,341 @@
+//! Polyglot opening books
+//!
+//! A book is a file of 16-byte big-endian entries sorted by key: the `u64`
+//! Polyglot key of a position, a `u16` move, a `u16` weight and a `u32` used
+//! for learning. Keys are made with the fixed Polyglot tables of
+//! `Zobrist::polyglot`, counting the en passant file only when a pawn can
+//! actually capture there.
+//!
+//! Moves store the destination square in bits 0 to 5, the origin in bits 6
+//! to 11 and the promotion piece, from knight 1 to queen 4, in bits 12 to
+//! 14. Castling is written as the king taking its own rook.
+
+use crate::engine::bitboards::*;
+use crate::engine::{Board, Position, ZobKey, Zobrist};
+use crate::primitives::*;
+
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const ENTRY_SIZE: usize = 16;
+
+lazy_static! {
+    static ref POLYGLOT_TABLES: Zobrist = Zobrist::polyglot();
+}
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    WrongSize(usize),
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookError::Io(e) => write!(f, "{}", e),
+            BookError::WrongSize(size) => write!(
+                f,
+                "{} bytes are not a whole number of {}-byte entries",
+                size, ENTRY_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for BookError {}
+
+/// Polyglot key of `pos`
+pub fn polyglot_key(pos: &Position) -> ZobKey {
+    let z = &*POLYGLOT_TABLES;
+    let mut key = 0;
+    for square in 0..64 {
+        let (piece, color) = pos.get_piece_and_color(square);
+        if piece != 0 {
+            key ^= z.piece(piece, color, square);
+        }
+    }
+    key ^= z.castling_rights(pos.castling());
+
+    let us = pos.to_move();
+    if let Some(ep) = pos.en_passant() {
+        if BB_PAWN_ATTACKS_2[1 ^ us as usize][ep as usize] & pos.bb_pawns(us) != 0 {
+            key ^= z.en_passant(Some(ep));
+        }
+    }
+    if us == colors::WHITE {
+        key ^= z.black_to_move;
+    }
+    key
+}
+
+/// A single book entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: ZobKey,
+    pub mov: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn from_bytes(bytes: &[u8]) -> BookEntry {
+        let mut key = [0; 8];
+        key.copy_from_slice(&bytes[..8]);
+        BookEntry {
+            key: u64::from_be_bytes(key),
+            mov: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        }
+    }
+
+    /// The bytes of this entry in a book file
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mov.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+
+    /// The legal move of `board` this entry stands for, if any
+    pub fn decode(&self, board: &mut Board) -> Option<Move> {
+        let dest = Square::from(self.mov & 0x3f);
+        let orig = Square::from((self.mov >> 6) & 0x3f);
+        let promotion = match (self.mov >> 12) & 0x7 {
+            0 => None,
+            p @ 1..=4 => Some(piece_types::KNIGHT + p as Piece - 1),
+            _ => return None,
+        };
+
+        let pos = board.current();
+        let (piece, color) = pos.get_piece_and_color(orig);
+        let castling =
+            piece == piece_types::KING && pos.check_piece(piece_types::ROOK, color, dest);
+
+        board.legal_moves().into_iter().find(|mov| {
+            if castling {
+                mov.orig() == orig
+                    && ((mov.is_king_castle() && dest > orig)
+                        || (mov.is_queen_castle() && dest < orig))
+            } else {
+                mov.orig() == orig
+                    && mov.dest() == dest
+                    && mov.is_promotion() == promotion.is_some()
+                    && promotion.is_none_or(|p| mov.prom_piece_code() == p)
+            }
+        })
+    }
+}
+
+/// Encodes `mov` in `pos` the way Polyglot books store it
+pub fn encode_move(pos: &Position, mov: Move) -> u16 {
+    let dest = if mov.is_king_castle() || mov.is_queen_castle() {
+        // the rook may start on any file in Chess960
+        let side = if mov.is_king_castle() {
+            sides::KING_SIDE
+        } else {
+            sides::QUEEN_SIDE
+        };
+        (mov.orig() & !7) + pos.castling().rook_file(pos.to_move(), side)
+    } else {
+        mov.dest()
+    };
+    let promotion = if mov.is_promotion() {
+        mov.prom_piece_code() - piece_types::KNIGHT + 1
+    } else {
+        0
+    };
+    (promotion as u16) << 12 | (mov.orig() as u16) << 6 | dest as u16
+}
+
+/// A Polyglot opening book, held in memory
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Book> {
+        Ok(Self::from_bytes(&fs::read(path).map_err(BookError::Io)?)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::WrongSize(bytes.len()));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // books should be sorted already, but lookups rely on it
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All entries for the position with Polyglot key `key`
+    pub fn entries(&self, key: ZobKey) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// The legal book moves of the current position with their weights
+    pub fn moves(&self, board: &mut Board) -> Vec<(Move, u16)> {
+        self.entries(polyglot_key(board.current()))
+            .iter()
+            .filter_map(|entry| entry.decode(board).map(|mov| (mov, entry.weight)))
+            .collect()
+    }
+
+    /// A book move for the current position, picked at random with
+    /// probabilities proportional to the weights. Moves of weight 0 are
+    /// never picked.
+    pub fn pick<R: Rng>(&self, board: &mut Board, rng: &mut R) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u32 = moves.iter().map(|&(_, weight)| u32::from(weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut choice = rng.gen_range(0, total);
+        for (mov, weight) in moves {
+            if choice < u32::from(weight) {
+                return Some(mov);
+            }
+            choice -= u32::from(weight);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::FenInterface;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    #[test]
+    fn it_computes_polyglot_keys() {
+        // the reference keys from the Polyglot format description
+        let keys = [
+            ("", 0x463b_9618_1691_fc9c),
+            ("e2e4", 0x823c_9b50_fd11_4196),
+            ("e2e4 d7d5", 0x0756_b944_61c5_0fb0),
+            ("e2e4 d7d5 e4e5", 0x662f_afb9_65db_29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a4_8b5a_8e47_ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a_607c_a3f2_42c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fd_d303_c946_bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c81_23ea_7b06_7637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f_9b82_9b27_9560),
+        ];
+        for &(moves, key) in keys.iter() {
+            let mut board = Board::startpos();
+            for mov in moves.split_whitespace() {
+                board.input_lan_move(mov).unwrap();
+            }
+            assert_eq!(key, polyglot_key(board.current()), "after {:?}", moves);
+        }
+    }
+
+    #[test]
+    fn it_decodes_castling_and_promotions() {
+        let entry = |promotion: u16, orig: Square, dest: Square| BookEntry {
+            key: 0,
+            mov: promotion << 12 | (orig as u16) << 6 | dest as u16,
+            weight: 1,
+            learn: 0,
+        };
+        let mut board = Board::from_fen_str("r3k3/1P6/8/8/8/8/8/R3K2R w KQq - 0 1").unwrap();
+
+        let short = entry(0, squares::E1, squares::H1);
+        let mov = short.decode(&mut board).unwrap();
+        assert!(mov.is_king_castle());
+        assert_eq!(short.mov, encode_move(board.current(), mov));
+
+        let long = entry(0, squares::E1, squares::A1);
+        let mov = long.decode(&mut board).unwrap();
+        assert!(mov.is_queen_castle());
+        assert_eq!(long.mov, encode_move(board.current(), mov));
+
+        let promotion = entry(4, squares::B7, squares::A8);
+        let mov = promotion.decode(&mut board).unwrap();
+        assert!(mov.is_capture());
+        assert_eq!(piece_types::QUEEN, mov.prom_piece_code());
+        assert_eq!(promotion.mov, encode_move(board.current(), mov));
+
+        // castling needs the rights, not just the king and rook
+        let mut board = Board::from_fen_str("r3k3/8/8/8/8/8/8/R3K2R b KQ - 0 1").unwrap();
+        assert_eq!(None, entry(0, squares::E8, squares::A8).decode(&mut board));
+
+        // Chess960 castles take the rook wherever it started
+        let mut board = Board::from_fen_str("1r4kr/8/8/8/8/8/8/1R4KR b HBhb - 0 1").unwrap();
+        let long = entry(0, squares::G8, squares::B8);
+        let mov = long.decode(&mut board).unwrap();
+        assert!(mov.is_queen_castle());
+        assert_eq!(long.mov, encode_move(board.current(), mov));
+        let short = entry(0, squares::G8, squares::H8);
+        let mov = short.decode(&mut board).unwrap();
+        assert!(mov.is_king_castle());
+        assert_eq!(short.mov, encode_move(board.current(), mov));
+    }
+
+    #[test]
+    fn it_picks_weighted_book_moves() {
+        let mut board = Board::startpos();
+        let key = polyglot_key(board.current());
+        let entry = |orig: Square, dest: Square, weight: u16| BookEntry {
+            key,
+            mov: (orig as u16) << 6 | dest as u16,
+            weight,
+            learn: 0,
+        };
+        let mut bytes = Vec::new();
+        // an unrelated position, a move which isn't legal and one of weight 0
+        bytes.extend_from_slice(
+            &BookEntry {
+                key: key + 1,
+                ..entry(squares::A2, squares::A3, 9)
+            }
+            .to_bytes(),
+        );
+        bytes.extend_from_slice(&entry(squares::E2, squares::E4, 3).to_bytes());
+        bytes.extend_from_slice(&entry(squares::D2, squares::D4, 1).to_bytes());
+        bytes.extend_from_slice(&entry(squares::E2, squares::E5, 5).to_bytes());
+        bytes.extend_from_slice(&entry(squares::G1, squares::F3, 0).to_bytes());
+        let book = Book::from_bytes(&bytes).unwrap();
+        assert_eq!(5, book.len());
+        assert_eq!(4, book.entries(key).len());
+        assert_eq!(3, book.moves(&mut board).len());
+
+        let mut rng = Pcg64::seed_from_u64(0);
+        let mut counts = [0; 2];
+        for _ in 0..1000 {
+            match book.pick(&mut board, &mut rng).unwrap() {
+                mov if mov.orig() == squares::E2 => counts[0] += 1,
+                mov => {
+                    assert_eq!(squares::D2, mov.orig());
+                    counts[1] += 1
+                }
+            }
+        }
+        assert!(counts[0] > 2 * counts[1] && counts[0] < 4 * counts[1]);
+
+        assert!(Book::from_bytes(&bytes[1..]).is_err());
+        let mut board = Board::from_fen_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(None, book.pick(&mut board, &mut rng));
+    }
+}
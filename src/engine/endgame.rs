@@ -0,0 +1,593 @@
+//! Knowledge about specific endings
+//!
+//! Some endings are evaluated by dedicated functions instead of the general
+//! evaluation, looked up by the material signature of the position: KPK
+//! (with a bitbase), KBNK, KRKP and a lone king against a major piece
+//! (KXK). Others are recognized as drawish and have their endgame score
+//! scaled down, see `scale_factor`.
+
+use crate::engine::bitboards::*;
+use crate::engine::eval::PIECE_VALUES;
+use crate::engine::{scores, Bitboard, BitboardPrimitives, EvalParams, Position, Score};
+use crate::primitives::*;
+
+/// Piece counts of both sides, four bits per colored piece type
+pub type MaterialKey = u64;
+
+/// Bonus for a won ending, sure to outweigh any material difference
+pub const KNOWN_WIN: Score = 10_000;
+
+/// Endgame scale factors, in 64ths of the endgame score
+pub const SCALE_NORMAL: i32 = 64;
+pub const SCALE_DRAW: i32 = 0;
+/// Opposite-colored bishops with nothing but pawns besides
+pub const SCALE_OCB_PURE: i32 = 16;
+/// Opposite-colored bishops with more pieces on the board
+pub const SCALE_OCB: i32 = 46;
+
+#[inline]
+fn key_shift(color: Color, piece: Piece) -> u32 {
+    4 * (u32::from(color) * 5 + u32::from(piece - piece_types::PAWN))
+}
+
+/// Material signature of `pos`
+pub fn material_key(pos: &Position) -> MaterialKey {
+    let mut key = 0;
+    for color in colors::WHITE..=colors::BLACK {
+        for piece in piece_types::PAWN..piece_types::KING {
+            let count = pos.bb()[Position::bb_idx(color, piece)].count() as MaterialKey;
+            key += count.min(15) << key_shift(color, piece);
+        }
+    }
+    key
+}
+
+/// Material signature of an ending written like `KBNK`, the pieces of
+/// `strong` first
+pub fn signature(code: &str, strong: Color) -> MaterialKey {
+    let weak_start = code[1..].find('K').expect("two kings") + 1;
+    let sides = [
+        (&code[..weak_start], strong),
+        (&code[weak_start..], 1 ^ strong),
+    ];
+    let mut key = 0;
+    for &(side, color) in sides.iter() {
+        for c in side.chars().skip(1) {
+            let piece = match c {
+                'P' => piece_types::PAWN,
+                'N' => piece_types::KNIGHT,
+                'B' => piece_types::BISHOP,
+                'R' => piece_types::ROOK,
+                'Q' => piece_types::QUEEN,
+                _ => panic!("invalid piece in {}", code),
+            };
+            key += 1 << key_shift(color, piece);
+        }
+    }
+    key
+}
+
+type EvalFn = fn(&Position, Color) -> Score;
+
+/// Dedicated evaluation for one ending with a fixed stronger side
+pub struct Endgame {
+    pub name: &'static str,
+    pub strong: Color,
+    eval: EvalFn,
+}
+
+impl Endgame {
+    /// Evaluation from the side to move's point of view
+    pub fn evaluate(&self, pos: &Position) -> Score {
+        let score = (self.eval)(pos, self.strong);
+        if pos.to_move() == self.strong {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+lazy_static! {
+    static ref ENDGAMES: Vec<(MaterialKey, Endgame)> = {
+        let endgames: [(&'static str, EvalFn); 3] = [("KPK", kpk), ("KBNK", kbnk), ("KRKP", krkp)];
+        let mut table = Vec::new();
+        for &(name, eval) in endgames.iter() {
+            for strong in colors::WHITE..=colors::BLACK {
+                table.push((signature(name, strong), Endgame { name, strong, eval }));
+            }
+        }
+        table
+    };
+}
+
+static KXK: [Endgame; 2] = [
+    Endgame {
+        name: "KXK",
+        strong: colors::WHITE,
+        eval: kxk,
+    },
+    Endgame {
+        name: "KXK",
+        strong: colors::BLACK,
+        eval: kxk,
+    },
+];
+
+/// The dedicated evaluation for `pos`, if there is one
+pub fn find(pos: &Position) -> Option<&'static Endgame> {
+    let key = material_key(pos);
+    if let Some((_, endgame)) = ENDGAMES.iter().find(|(k, _)| *k == key) {
+        return Some(endgame);
+    }
+    for strong in colors::WHITE..=colors::BLACK {
+        let weak = 1 ^ strong;
+        if pos.bb_own(weak) == pos.bb_king(weak)
+            && pos.bb_rooks(strong) | pos.bb_queens(strong) != 0
+        {
+            return Some(&KXK[strong as usize]);
+        }
+    }
+    None
+}
+
+/// Bonus for driving a king towards the edge of the board
+#[inline]
+fn push_to_edge(square: Square) -> Score {
+    let rank = (square >> 3) as Score;
+    let file = (square & 7) as Score;
+    let center_distance = rank.min(7 - rank) + file.min(7 - file);
+    20 * (6 - center_distance)
+}
+
+/// Bonus for bringing two kings close together
+#[inline]
+fn push_close(a: Square, b: Square) -> Score {
+    20 * (7 - a.distance(b) as Score)
+}
+
+/// Non-pawn material of `color`
+fn piece_material(pos: &Position, color: Color) -> Score {
+    (piece_types::KNIGHT..piece_types::KING)
+        .map(|piece| {
+            PIECE_VALUES[piece as usize] * pos.bb()[Position::bb_idx(color, piece)].count() as Score
+        })
+        .sum()
+}
+
+/// Mating a lone king: drive it to the edge and follow with our own king
+fn kxk(pos: &Position, strong: Color) -> Score {
+    let weak = 1 ^ strong;
+    let strong_king = pos.bb_king(strong).scan();
+    let weak_king = pos.bb_king(weak).scan();
+    let score = piece_material(pos, strong)
+        + PIECE_VALUES[piece_types::PAWN as usize] * pos.bb_pawns(strong).count() as Score
+        + push_to_edge(weak_king)
+        + push_close(strong_king, weak_king);
+    (score + KNOWN_WIN).min(scores::TB_BOUND - 1)
+}
+
+/// Bishop and knight: the king can only be mated in a corner of the
+/// bishop's color
+fn kbnk(pos: &Position, strong: Color) -> Score {
+    let strong_king = pos.bb_king(strong).scan();
+    let weak_king = pos.bb_king(1 ^ strong).scan();
+    let corners = if pos.bb_bishops(strong) & BB_DARK_SQUARES != 0 {
+        [squares::A1, squares::H8]
+    } else {
+        [squares::A8, squares::H1]
+    };
+    let corner_distance = corners
+        .iter()
+        .map(|&corner| weak_king.distance(corner))
+        .min()
+        .unwrap() as Score;
+    KNOWN_WIN
+        + piece_material(pos, strong)
+        + push_close(strong_king, weak_king)
+        + 25 * (7 - corner_distance)
+}
+
+/// Squares as seen from `strong`, so that its pawns move up the board
+#[inline]
+fn relative_square(strong: Color, square: Square) -> Square {
+    if strong == colors::WHITE {
+        square
+    } else {
+        square ^ 56
+    }
+}
+
+fn kpk(pos: &Position, strong: Color) -> Score {
+    let strong_king = relative_square(strong, pos.bb_king(strong).scan());
+    let weak_king = relative_square(strong, pos.bb_king(1 ^ strong).scan());
+    let pawn = relative_square(strong, pos.bb_pawns(strong).scan());
+    let to_move = if pos.to_move() == strong {
+        colors::WHITE
+    } else {
+        colors::BLACK
+    };
+    if !kpk::probe(to_move, strong_king, pawn, weak_king) {
+        return scores::DRAW;
+    }
+    KNOWN_WIN + PIECE_VALUES[piece_types::PAWN as usize] + (pawn >> 3) as Score
+}
+
+/// Rook against pawn, after the rules of thumb in Stockfish's endgame.cpp
+fn krkp(pos: &Position, strong: Color) -> Score {
+    let weak = 1 ^ strong;
+    // seen from the side of the pawn, moving up the board
+    let strong_king = relative_square(weak, pos.bb_king(strong).scan());
+    let weak_king = relative_square(weak, pos.bb_king(weak).scan());
+    let rook = relative_square(weak, pos.bb_rooks(strong).scan());
+    let pawn = relative_square(weak, pos.bb_pawns(weak).scan());
+    let queening = pawn | 56;
+    let rook_value = PIECE_VALUES[piece_types::ROOK as usize];
+    let weak_to_move = (pos.to_move() == weak) as u32;
+    let strong_to_move = 1 - weak_to_move;
+
+    if BB_FORWARD_FILE[colors::WHITE as usize][pawn as usize] & BB_SQUARES[strong_king as usize]
+        != 0
+    {
+        // the stronger king is in front of the pawn
+        rook_value - strong_king.distance(pawn) as Score
+    } else if weak_king.distance(pawn) >= 3 + weak_to_move && weak_king.distance(rook) >= 3 {
+        // the weaker king is too far away to support the pawn
+        rook_value - strong_king.distance(pawn) as Score
+    } else if weak_king >> 3 >= 5
+        && weak_king.distance(pawn) == 1
+        && strong_king >> 3 <= 4
+        && strong_king.distance(pawn) > 2 + strong_to_move
+    {
+        // the pawn is far advanced and supported, the stronger king far off
+        80 - 8 * strong_king.distance(pawn) as Score
+    } else {
+        let stop = pawn + 8;
+        200 - 8
+            * (strong_king.distance(stop) as Score
+                - weak_king.distance(stop) as Score
+                - pawn.distance(queening) as Score)
+    }
+}
+
+/// Pawns of `pawns` that are all on the a or all on the h file, with the
+/// square they queen on as seen by `color`
+fn rook_file_queening_square(color: Color, pawns: Bitboard) -> Option<Square> {
+    let file = if pawns & !BB_FILE_A == 0 {
+        0
+    } else if pawns & !BB_FILE_H == 0 {
+        7
+    } else {
+        return None;
+    };
+    Some(relative_square(color, 56 + file))
+}
+
+/// Factor in 64ths by which the endgame score of `strong`, the side ahead,
+/// is scaled down in drawish endings
+pub fn scale_factor(pos: &Position, params: &EvalParams, strong: Color) -> i32 {
+    let weak = 1 ^ strong;
+    let strong_pawns = pos.bb_pawns(strong);
+    let strong_pieces = pos.bb_own(strong) & !strong_pawns & !pos.bb_king(strong);
+    let weak_pieces = pos.bb_own(weak) & !pos.bb_pawns(weak) & !pos.bb_king(weak);
+
+    // rook pawns with the defending king in the corner, unless a bishop
+    // can drive it out
+    if strong_pawns != 0 && weak_pieces == 0 {
+        if let Some(queening) = rook_file_queening_square(strong, strong_pawns) {
+            let weak_king = pos.bb_king(weak).scan();
+            let bishops = pos.bb_bishops(strong);
+            let wrong_bishop = strong_pieces == bishops
+                && bishops.count() == 1
+                && (bishops & BB_DARK_SQUARES == 0)
+                    != (BB_SQUARES[queening as usize] & BB_DARK_SQUARES == 0);
+            if (strong_pieces == 0 || wrong_bishop) && weak_king.distance(queening) <= 1 {
+                return SCALE_DRAW;
+            }
+        }
+    }
+
+    let (strong_bishops, weak_bishops) = (pos.bb_bishops(strong), pos.bb_bishops(weak));
+    if strong_bishops.count() == 1
+        && weak_bishops.count() == 1
+        && (strong_bishops & BB_DARK_SQUARES == 0) != (weak_bishops & BB_DARK_SQUARES == 0)
+    {
+        return if strong_pieces == strong_bishops && weak_pieces == weak_bishops {
+            params.scale_ocb_pure
+        } else {
+            params.scale_ocb
+        };
+    }
+    SCALE_NORMAL
+}
+
+/// King and pawn against king bitbase
+///
+/// Generated on first use by retrograde analysis: positions where the pawn
+/// queens safely are wins, stalemates and lost pawns draws, and the rest is
+/// resolved by repeatedly looking one move ahead until nothing changes.
+/// Positions are stored with white as the stronger side and the pawn on
+/// files a to d.
+mod kpk {
+    use crate::engine::bitboards::*;
+    use crate::engine::BitboardPrimitives;
+    use crate::primitives::*;
+
+    /// 2 sides to move * 24 pawn squares * 64 * 64 king squares
+    const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+    const INVALID: u8 = 0;
+    const UNKNOWN: u8 = 1;
+    const DRAW: u8 = 2;
+    const WIN: u8 = 4;
+
+    lazy_static! {
+        static ref BITBASE: Vec<u64> = generate();
+    }
+
+    #[inline]
+    fn index(to_move: Color, weak_king: Square, strong_king: Square, pawn: Square) -> usize {
+        strong_king as usize
+            | (weak_king as usize) << 6
+            | (to_move as usize) << 12
+            | ((pawn & 7) as usize) << 13
+            | (6 - (pawn >> 3) as usize) << 15
+    }
+
+    /// Whether white wins with `to_move` to move, the pawn on any file
+    pub fn probe(to_move: Color, strong_king: Square, pawn: Square, weak_king: Square) -> bool {
+        // mirror the pawn onto files a to d
+        let (strong_king, pawn, weak_king) = if pawn & 7 >= 4 {
+            (strong_king ^ 7, pawn ^ 7, weak_king ^ 7)
+        } else {
+            (strong_king, pawn, weak_king)
+        };
+        let idx = index(to_move, weak_king, strong_king, pawn);
+        BITBASE[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    struct Entry {
+        to_move: Color,
+        kings: [Square; 2],
+        pawn: Square,
+        result: u8,
+    }
+
+    impl Entry {
+        fn new(idx: usize) -> Entry {
+            let strong_king = (idx & 0x3f) as Square;
+            let weak_king = ((idx >> 6) & 0x3f) as Square;
+            let to_move = ((idx >> 12) & 1) as Color;
+            let pawn = (((idx >> 13) & 3) + ((6 - (idx >> 15)) << 3)) as Square;
+            let kings = [strong_king, weak_king];
+            let push = pawn + 8;
+
+            let result = if strong_king.distance(weak_king) <= 1
+                || strong_king == pawn
+                || weak_king == pawn
+                || (to_move == colors::WHITE
+                    && BB_PAWN_ATTACKS_2[colors::WHITE as usize][pawn as usize]
+                        & BB_SQUARES[weak_king as usize]
+                        != 0)
+            {
+                INVALID
+            } else if to_move == colors::WHITE
+                && pawn >> 3 == 6
+                && strong_king != push
+                && (weak_king.distance(push) > 1
+                    || BB_KING_ATTACKS[strong_king as usize] & BB_SQUARES[push as usize] != 0)
+            {
+                // the pawn queens and can't be taken
+                WIN
+            } else if to_move == colors::BLACK
+                && (BB_KING_ATTACKS[weak_king as usize]
+                    & !(BB_KING_ATTACKS[strong_king as usize]
+                        | BB_PAWN_ATTACKS_2[colors::WHITE as usize][pawn as usize])
+                    == 0
+                    || BB_KING_ATTACKS[weak_king as usize]
+                        & BB_SQUARES[pawn as usize]
+                        & !BB_KING_ATTACKS[strong_king as usize]
+                        != 0)
+            {
+                // stalemate, or the pawn falls
+                DRAW
+            } else {
+                UNKNOWN
+            };
+            Entry {
+                to_move,
+                kings,
+                pawn,
+                result,
+            }
+        }
+
+        /// White wins if any move wins, black draws if any move draws
+        fn classify(&self, db: &[Entry]) -> u8 {
+            let us = self.to_move;
+            let them = 1 ^ us;
+            let (good, bad) = if us == colors::WHITE {
+                (WIN, DRAW)
+            } else {
+                (DRAW, WIN)
+            };
+
+            let [strong_king, weak_king] = self.kings;
+            let mut r = INVALID;
+            let mut moves = BB_KING_ATTACKS[self.kings[us as usize] as usize];
+            for to in moves.iter() {
+                r |= if us == colors::WHITE {
+                    db[index(them, weak_king, to, self.pawn)].result
+                } else {
+                    db[index(them, to, strong_king, self.pawn)].result
+                };
+            }
+
+            if us == colors::WHITE {
+                let push = self.pawn + 8;
+                if self.pawn >> 3 < 6 {
+                    r |= db[index(them, weak_king, strong_king, push)].result;
+                }
+                if self.pawn >> 3 == 1 && push != strong_king && push != weak_king {
+                    r |= db[index(them, weak_king, strong_king, push + 8)].result;
+                }
+            }
+
+            if r & good != 0 {
+                good
+            } else if r & UNKNOWN != 0 {
+                UNKNOWN
+            } else {
+                bad
+            }
+        }
+    }
+
+    fn generate() -> Vec<u64> {
+        let mut db: Vec<Entry> = (0..MAX_INDEX).map(Entry::new).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..MAX_INDEX {
+                if db[idx].result == UNKNOWN {
+                    let result = db[idx].classify(&db);
+                    if result != UNKNOWN {
+                        db[idx].result = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut bitbase = vec![0u64; MAX_INDEX / 64];
+        for (idx, entry) in db.iter().enumerate() {
+            if entry.result == WIN {
+                bitbase[idx / 64] |= 1 << (idx % 64);
+            }
+        }
+        bitbase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Evaluation, DEFAULT_PARAMS};
+    use crate::interfaces::FenInterface;
+
+    fn endgame(fen: &str) -> Option<&'static str> {
+        find(&Position::from_fen_str(fen).unwrap()).map(|e| e.name)
+    }
+
+    #[test]
+    fn it_computes_material_signatures() {
+        let pos = Position::from_fen_str("8/8/8/4k3/8/8/8/2BNK3 w - - 0 1").unwrap();
+        assert_eq!(signature("KBNK", colors::WHITE), material_key(&pos));
+        assert_ne!(signature("KBNK", colors::BLACK), material_key(&pos));
+        assert_eq!(
+            signature("KRPKBPP", colors::BLACK),
+            signature("KBPPKRP", colors::WHITE)
+        );
+    }
+
+    #[test]
+    fn it_selects_endgames_by_material() {
+        assert_eq!(Some("KPK"), endgame("8/8/8/4k3/8/8/3P4/4K3 w - - 0 1"));
+        assert_eq!(Some("KPK"), endgame("8/3p4/8/4k3/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(Some("KBNK"), endgame("8/8/8/4k3/8/8/8/2bnK3 w - - 0 1"));
+        assert_eq!(Some("KRKP"), endgame("8/8/8/4k3/3p4/8/8/R3K3 w - - 0 1"));
+        assert_eq!(Some("KXK"), endgame("8/8/8/4k3/8/8/8/R2QK3 b - - 0 1"));
+        assert_eq!(None, endgame("8/8/8/4k3/8/8/8/2BBK3 w - - 0 1"));
+        assert_eq!(
+            None,
+            endgame("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn it_knows_kpk() {
+        // with the opposition, black holds
+        let draw = Position::from_fen_str("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(scores::DRAW, draw.evaluate());
+        let win = Position::from_fen_str("8/3k4/8/3K4/3P4/8/8/8 b - - 0 1").unwrap();
+        assert!(win.evaluate() <= -KNOWN_WIN);
+
+        // the same for black, on the other wing
+        let draw = Position::from_fen_str("8/8/8/5p2/5k2/8/5K2/8 b - - 0 1").unwrap();
+        assert_eq!(scores::DRAW, draw.evaluate());
+        let win = Position::from_fen_str("8/8/8/5p2/5k2/8/5K2/8 w - - 0 1").unwrap();
+        assert!(win.evaluate() <= -KNOWN_WIN);
+
+        // a king in front of the rook pawn holds regardless
+        let pos = Position::from_fen_str("k7/8/8/8/8/8/P7/7K w - - 0 1").unwrap();
+        assert_eq!(scores::DRAW, pos.evaluate());
+        // and a king on the sixth wins regardless
+        let pos = Position::from_fen_str("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+        assert!(pos.evaluate() >= KNOWN_WIN);
+    }
+
+    #[test]
+    fn it_drives_kings_into_the_right_corner() {
+        // dark-squared bishop, mates on a1 or h8
+        let right = Position::from_fen_str("7k/8/5K2/8/8/8/8/1N2B3 w - - 0 1").unwrap();
+        let wrong = Position::from_fen_str("k7/8/2K5/8/8/8/8/1N2B3 w - - 0 1").unwrap();
+        assert!(right.evaluate() > wrong.evaluate());
+        assert!(wrong.evaluate() >= KNOWN_WIN);
+
+        let far = Position::from_fen_str("8/8/8/3k4/8/8/8/K3Q3 w - - 0 1").unwrap();
+        let edge = Position::from_fen_str("3k4/8/3K4/8/8/8/8/3Q4 w - - 0 1").unwrap();
+        assert!(edge.evaluate() > far.evaluate());
+    }
+
+    #[test]
+    fn it_knows_krkp() {
+        // the white king stops the pawn
+        let pos = Position::from_fen_str("8/8/8/8/8/3k4/3p4/3K3R w - - 0 1").unwrap();
+        assert!(pos.evaluate() > 300);
+        // the pawn about to queen with its king in support, ours far away
+        let pos = Position::from_fen_str("K7/8/8/8/8/8/2kp4/7R w - - 0 1").unwrap();
+        assert!(pos.evaluate() < 100);
+    }
+
+    #[test]
+    fn it_scales_drawish_endings() {
+        // dark-squared bishop and a8 is light
+        let pos = Position::from_fen_str("k7/8/8/8/8/8/P7/2B4K w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_DRAW,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        let pos = Position::from_fen_str("k7/8/8/8/8/8/P7/1B5K w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_NORMAL,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        // the same for black
+        let pos = Position::from_fen_str("3b3k/7p/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_DRAW,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::BLACK)
+        );
+
+        let pos = Position::from_fen_str("4k3/5p2/4b3/3P4/2PB4/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_OCB_PURE,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        let pos = Position::from_fen_str("r3k3/5p2/4b3/3P4/2PB4/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_OCB,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        let params = EvalParams {
+            scale_ocb: 32,
+            ..EvalParams::default()
+        };
+        assert_eq!(32, scale_factor(&pos, &params, colors::WHITE));
+        let pos = Position::from_fen_str("4k3/5p2/3b4/3P4/2PB4/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            SCALE_NORMAL,
+            scale_factor(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+    }
+}
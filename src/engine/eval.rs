@@ -0,0 +1,757 @@
+use crate::engine::bitboards::*;
+use crate::engine::endgame::{self, SCALE_NORMAL};
+use crate::engine::params::DEFAULT_PARAMS;
+use crate::engine::{
+    Bitboard, BitboardPrimitives, EvalParams, PawnEntry, PawnHashTable, Position, Score,
+};
+use crate::primitives::*;
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+/// Piece values indexed by piece type, used where a single number is needed
+/// (e.g. move ordering)
+pub const PIECE_VALUES: [Score; 8] = [0, 0, 100, 320, 330, 500, 900, 0];
+
+/// A pair of middlegame and endgame scores, blended by the game phase
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    pub mg: Score,
+    pub eg: Score,
+}
+
+impl TaperedScore {
+    #[inline]
+    pub const fn new(mg: Score, eg: Score) -> TaperedScore {
+        TaperedScore { mg, eg }
+    }
+
+    /// Interpolates between middlegame and endgame by `phase`, which runs
+    /// from `MAX_PHASE` (all pieces on board) down to 0 (bare kings and pawns)
+    #[inline]
+    pub fn taper(self, phase: i32) -> Score {
+        let phase = phase.min(MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = Self;
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        TaperedScore::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = Self;
+    #[inline]
+    fn sub(self, other: Self) -> Self {
+        TaperedScore::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl SubAssign for TaperedScore {
+    #[inline]
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = Self;
+    #[inline]
+    fn neg(self) -> Self {
+        TaperedScore::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<Score> for TaperedScore {
+    type Output = Self;
+    #[inline]
+    fn mul(self, rhs: Score) -> Self {
+        TaperedScore::new(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+/// Game phase contribution per piece type
+pub const PHASE_WEIGHTS: [i32; 8] = [0, 0, 0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE: i32 = 24;
+
+pub const MATERIAL: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(82, 94),
+    TaperedScore::new(337, 281),
+    TaperedScore::new(365, 297),
+    TaperedScore::new(477, 512),
+    TaperedScore::new(1025, 936),
+    TaperedScore::new(0, 0),
+];
+
+// Piece-square tables, written from white's point of view with a8 in the
+// upper left corner, i.e. white pieces index with `square ^ 56`.
+
+#[rustfmt::skip]
+const PST_PAWN_MG: [Score; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PST_PAWN_EG: [Score; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PST_KNIGHT_MG: [Score; 64] = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+
+#[rustfmt::skip]
+const PST_KNIGHT_EG: [Score; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const PST_BISHOP_MG: [Score; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const PST_BISHOP_EG: [Score; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const PST_ROOK_MG: [Score; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const PST_ROOK_EG: [Score; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const PST_QUEEN_MG: [Score; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const PST_QUEEN_EG: [Score; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const PST_KING_MG: [Score; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const PST_KING_EG: [Score; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+/// Piece-square tables indexed by piece type
+pub const PST_MG: [&[Score; 64]; 6] = [
+    &PST_PAWN_MG,
+    &PST_KNIGHT_MG,
+    &PST_BISHOP_MG,
+    &PST_ROOK_MG,
+    &PST_QUEEN_MG,
+    &PST_KING_MG,
+];
+
+pub const PST_EG: [&[Score; 64]; 6] = [
+    &PST_PAWN_EG,
+    &PST_KNIGHT_EG,
+    &PST_BISHOP_EG,
+    &PST_ROOK_EG,
+    &PST_QUEEN_EG,
+    &PST_KING_EG,
+];
+
+/// Bonus per reachable square beyond `MOBILITY_BASE`, indexed by piece type
+pub const MOBILITY: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(4, 4),
+    TaperedScore::new(5, 5),
+    TaperedScore::new(2, 4),
+    TaperedScore::new(1, 2),
+    TaperedScore::new(0, 0),
+];
+
+/// Number of reachable squares considered average for each piece type
+pub const MOBILITY_BASE: [Score; 8] = [0, 0, 0, 4, 7, 7, 14, 0];
+
+/// Bonus per own pawn directly in front of the king and one rank further
+pub const PAWN_SHIELD: [TaperedScore; 2] = [TaperedScore::new(12, 0), TaperedScore::new(6, 0)];
+
+/// Penalty per file next to the king without own pawns, and without any pawns
+pub const KING_SEMI_OPEN_FILE: TaperedScore = TaperedScore::new(-12, 0);
+pub const KING_OPEN_FILE: TaperedScore = TaperedScore::new(-20, 0);
+
+/// Attack units per king zone square hit, indexed by piece type
+pub const KING_ATTACK_WEIGHTS: [Score; 8] = [0, 0, 0, 2, 2, 3, 5, 0];
+
+/// Upper bound for the middlegame king attack penalty
+pub const KING_ATTACK_MAX: Score = 500;
+
+/// Squares attacked by a knight, bishop, rook or queen on `square`
+#[inline]
+fn piece_attacks(piece: Piece, square: Square, occupied: Bitboard) -> Bitboard {
+    match piece {
+        piece_types::KNIGHT => BB_KNIGHT_ATTACKS[square as usize],
+        piece_types::BISHOP => bishop_attacks(square, occupied),
+        piece_types::ROOK => rank_attacks(square, occupied) | file_attacks(square, occupied),
+        piece_types::QUEEN => {
+            bishop_attacks(square, occupied)
+                | rank_attacks(square, occupied)
+                | file_attacks(square, occupied)
+        }
+        _ => 0,
+    }
+}
+
+/// Squares attacked by the pawns of `color`
+#[inline]
+fn pawn_attacks(pos: &Position, color: Color) -> Bitboard {
+    let pawns = pos.bb_pawns(color);
+    if color == colors::WHITE {
+        north_east_one(pawns) | north_west_one(pawns)
+    } else {
+        south_east_one(pawns) | south_west_one(pawns)
+    }
+}
+
+pub trait Evaluation {
+    /// Static evaluation from the side to move's point of view, using the
+    /// compiled-in parameters
+    fn evaluate(&self) -> Score;
+
+    /// Same as `evaluate`, using `params`
+    fn evaluate_with(&self, params: &EvalParams) -> Score;
+
+    /// Same as `evaluate_with`, looking up pawn structure in `pawns`, which
+    /// must only ever be used with the same `params`
+    fn evaluate_cached(&self, params: &EvalParams, pawns: &mut PawnHashTable) -> Score;
+
+    /// Game phase computed from non-pawn material, `MAX_PHASE` at the start
+    fn phase(&self) -> i32;
+
+    /// Breakdown of the static evaluation into its terms, using the
+    /// compiled-in parameters
+    fn eval_trace(&self) -> EvalTrace;
+
+    /// Same as `eval_trace`, using `params`
+    fn eval_trace_with(&self, params: &EvalParams) -> EvalTrace;
+}
+
+/// Names of the evaluation terms, in the order `color_terms` returns them
+pub const TERM_NAMES: [&str; 5] = [
+    "Material",
+    "Piece squares",
+    "Pawns",
+    "Mobility",
+    "King safety",
+];
+
+/// Per-term scores of a position, see `Evaluation::eval_trace`
+#[derive(Clone, Debug)]
+pub struct EvalTrace {
+    /// Term name with its score for white and black
+    pub terms: Vec<(&'static str, [TaperedScore; 2])>,
+    pub phase: i32,
+    /// Endgame scale factor in 64ths, see `endgame::scale_factor`
+    pub scale: i32,
+    /// Name of the ending if it has a dedicated evaluation, which then
+    /// replaces the terms
+    pub endgame: Option<&'static str>,
+    /// Final tapered score from white's point of view
+    pub score: Score,
+}
+
+impl EvalTrace {
+    /// Sum of all terms, white minus black
+    pub fn total(&self) -> TaperedScore {
+        self.terms
+            .iter()
+            .fold(TaperedScore::default(), |acc, (_, [white, black])| {
+                acc + *white - *black
+            })
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cell = |score: TaperedScore| format!(" {:>5} {:>5} ", score.mg, score.eg);
+        let rule = "--------------+-------------+-------------+-------------";
+        writeln!(f, "         Term |    White    |    Black    |    Total")?;
+        writeln!(f, "              |   MG    EG  |   MG    EG  |   MG    EG")?;
+        writeln!(f, "{}", rule)?;
+        for (name, [white, black]) in self.terms.iter() {
+            let row = format!(
+                "{:>13} |{}|{}|{}",
+                name,
+                cell(*white),
+                cell(*black),
+                cell(*white - *black)
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        writeln!(f, "{}", rule)?;
+        let row = format!(
+            "{:>13} |{:13}|{:13}|{}",
+            "Total",
+            "",
+            "",
+            cell(self.total())
+        );
+        writeln!(f, "{}", row.trim_end())?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {} / {}", self.phase, MAX_PHASE)?;
+        if self.scale != SCALE_NORMAL {
+            writeln!(f, "Endgame scale: {} / {}", self.scale, SCALE_NORMAL)?;
+        }
+        if let Some(name) = self.endgame {
+            writeln!(f, "Known ending: {}", name)?;
+        }
+        write!(f, "Final evaluation: {} cp (white side)", self.score)
+    }
+}
+
+fn material(pos: &Position, params: &EvalParams, color: Color) -> TaperedScore {
+    let bb = pos.bb();
+    let mut score = TaperedScore::default();
+    for piece in piece_types::PAWN..piece_types::KING {
+        score +=
+            params.material[piece as usize] * bb[Position::bb_idx(color, piece)].count() as Score;
+    }
+    score
+}
+
+fn piece_squares(pos: &Position, params: &EvalParams, color: Color) -> TaperedScore {
+    let bb = pos.bb();
+    let mut score = TaperedScore::default();
+    for piece in piece_types::PAWN..=piece_types::KING {
+        let mut pieces = bb[Position::bb_idx(color, piece)];
+        for square in pieces.iter() {
+            score += params.pst(piece, color, square);
+        }
+    }
+    score
+}
+
+fn mobility(pos: &Position, params: &EvalParams, color: Color) -> TaperedScore {
+    let occupied = pos.bb_own(color) | pos.bb_opponent(color);
+    let available = !pos.bb_own(color) & !pawn_attacks(pos, 1 ^ color);
+    let mut score = TaperedScore::default();
+    for piece in piece_types::KNIGHT..piece_types::KING {
+        let mut pieces = pos.bb()[Position::bb_idx(color, piece)];
+        for square in pieces.iter() {
+            let count = (piece_attacks(piece, square, occupied) & available).count() as Score;
+            score +=
+                params.mobility[piece as usize] * (count - params.mobility_base[piece as usize]);
+        }
+    }
+    score
+}
+
+fn king_safety(pos: &Position, params: &EvalParams, color: Color) -> TaperedScore {
+    // an empty or hand-edited board may lack kings
+    if pos.bb_king(color) == 0 {
+        return TaperedScore::default();
+    }
+    let king = pos.bb_king(color).scan();
+    let own_pawns = pos.bb_pawns(color);
+    let all_pawns = own_pawns | pos.bb_pawns(1 ^ color);
+    let mut score = TaperedScore::default();
+
+    // pawn shield on the king's file and its neighbours
+    let front = BB_PASSED_PAWN_MASK[color as usize][king as usize];
+    let rank = (king >> 3) as usize;
+    let shield_ranks = if color == colors::WHITE {
+        [rank + 1, rank + 2]
+    } else {
+        [rank.wrapping_sub(1), rank.wrapping_sub(2)]
+    };
+    for (&shield_rank, &bonus) in shield_ranks.iter().zip(params.pawn_shield.iter()) {
+        if shield_rank < 8 {
+            score += bonus * (front & own_pawns & BB_RANKS[shield_rank]).count() as Score;
+        }
+    }
+
+    let file = (king & 7) as usize;
+    for &file_bb in BB_FILES[file.saturating_sub(1)..=(file + 1).min(7)].iter() {
+        if file_bb & all_pawns == 0 {
+            score += params.king_open_file;
+        } else if file_bb & own_pawns == 0 {
+            score += params.king_semi_open_file;
+        }
+    }
+
+    // attack units from enemy pieces hitting the squares around the king
+    let zone = BB_KING_ATTACKS[king as usize] | BB_SQUARES[king as usize];
+    let occupied = pos.bb_own(color) | pos.bb_opponent(color);
+    let mut attackers = 0;
+    let mut units = 0;
+    for piece in piece_types::KNIGHT..piece_types::KING {
+        let mut pieces = pos.bb()[Position::bb_idx(1 ^ color, piece)];
+        for square in pieces.iter() {
+            let hits = (piece_attacks(piece, square, occupied) & zone).count() as Score;
+            if hits > 0 {
+                attackers += 1;
+                units += params.king_attack_weights[piece as usize] * hits;
+            }
+        }
+    }
+    // a lone attacker rarely gets anywhere
+    if attackers >= 2 {
+        let penalty = (units * units / 4).min(params.king_attack_max);
+        score -= TaperedScore::new(penalty, penalty / 4);
+    }
+    score
+}
+
+/// All evaluation terms for one side, named by `TERM_NAMES`
+fn color_terms(
+    pos: &Position,
+    params: &EvalParams,
+    pawns: &PawnEntry,
+    color: Color,
+) -> [TaperedScore; 5] {
+    [
+        material(pos, params, color),
+        piece_squares(pos, params, color),
+        pawns.evaluate(pos, params, color),
+        mobility(pos, params, color),
+        king_safety(pos, params, color),
+    ]
+}
+
+/// Tapers `score`, white minus black, after scaling down its endgame part
+/// in drawish endings. Returns the result with the scale factor.
+fn scale_and_taper(pos: &Position, params: &EvalParams, score: TaperedScore) -> (Score, i32) {
+    let strong = if score.eg >= 0 {
+        colors::WHITE
+    } else {
+        colors::BLACK
+    };
+    let scale = endgame::scale_factor(pos, params, strong);
+    let score = TaperedScore::new(score.mg, score.eg * scale / SCALE_NORMAL);
+    (score.taper(pos.phase()), scale)
+}
+
+fn evaluate_entry(pos: &Position, params: &EvalParams, pawns: &PawnEntry) -> Score {
+    if let Some(endgame) = endgame::find(pos) {
+        return endgame.evaluate(pos);
+    }
+
+    let mut score = TaperedScore::default();
+    for &(color, sign) in &[(colors::WHITE, 1), (colors::BLACK, -1)] {
+        for &term in color_terms(pos, params, pawns, color).iter() {
+            score += term * sign;
+        }
+    }
+
+    let (score, _) = scale_and_taper(pos, params, score);
+    if pos.to_move() == colors::WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+impl Evaluation for Position {
+    fn evaluate(&self) -> Score {
+        self.evaluate_with(&DEFAULT_PARAMS)
+    }
+
+    fn evaluate_with(&self, params: &EvalParams) -> Score {
+        evaluate_entry(self, params, &PawnEntry::new(self, params))
+    }
+
+    fn evaluate_cached(&self, params: &EvalParams, pawns: &mut PawnHashTable) -> Score {
+        evaluate_entry(self, params, pawns.probe(self, params))
+    }
+
+    fn phase(&self) -> i32 {
+        let bb = self.bb();
+        let mut phase = 0;
+        for piece in piece_types::KNIGHT..piece_types::KING {
+            let count = bb[Position::bb_idx(colors::WHITE, piece)].count()
+                + bb[Position::bb_idx(colors::BLACK, piece)].count();
+            phase += PHASE_WEIGHTS[piece as usize] * count as i32;
+        }
+        phase.min(MAX_PHASE)
+    }
+
+    fn eval_trace(&self) -> EvalTrace {
+        self.eval_trace_with(&DEFAULT_PARAMS)
+    }
+
+    fn eval_trace_with(&self, params: &EvalParams) -> EvalTrace {
+        let pawns = PawnEntry::new(self, params);
+        let white = color_terms(self, params, &pawns, colors::WHITE);
+        let black = color_terms(self, params, &pawns, colors::BLACK);
+        let mut trace = EvalTrace {
+            terms: TERM_NAMES
+                .iter()
+                .zip(white.iter().zip(black.iter()))
+                .map(|(&name, (&white, &black))| (name, [white, black]))
+                .collect(),
+            phase: self.phase(),
+            scale: SCALE_NORMAL,
+            endgame: None,
+            score: 0,
+        };
+        let (score, scale) = scale_and_taper(self, params, trace.total());
+        trace.score = score;
+        trace.scale = scale;
+        if let Some(endgame) = endgame::find(self) {
+            trace.endgame = Some(endgame.name);
+            let score = endgame.evaluate(self);
+            trace.score = if self.to_move() == colors::WHITE {
+                score
+            } else {
+                -score
+            };
+        }
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::FenInterface;
+
+    #[test]
+    fn it_computes_the_game_phase() {
+        let pos =
+            Position::from_fen_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(MAX_PHASE, pos.phase());
+
+        let pos = Position::from_fen_str("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").unwrap();
+        assert_eq!(0, pos.phase());
+
+        let pos = Position::from_fen_str("3rk3/8/8/8/8/8/8/2N1K3 w - - 0 1").unwrap();
+        assert_eq!(3, pos.phase());
+    }
+
+    #[test]
+    fn it_tapers_between_middlegame_and_endgame() {
+        let score = TaperedScore::new(100, -20);
+        assert_eq!(100, score.taper(MAX_PHASE));
+        assert_eq!(-20, score.taper(0));
+        assert_eq!(40, score.taper(MAX_PHASE / 2));
+    }
+
+    #[test]
+    fn it_evaluates_symmetrically() {
+        let fens = [
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+                "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3",
+            ),
+            (
+                "4k3/8/8/3p4/8/8/5N2/4K3 w - - 0 1",
+                "4k3/5n2/8/8/3P4/8/8/4K3 b - - 0 1",
+            ),
+        ];
+        for (fen, mirrored) in fens.iter() {
+            let pos = Position::from_fen_str(fen).unwrap();
+            let mirrored = Position::from_fen_str(mirrored).unwrap();
+            assert_eq!(pos.evaluate(), mirrored.evaluate());
+        }
+
+        let start =
+            Position::from_fen_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(0, start.evaluate());
+    }
+
+    #[test]
+    fn it_scores_mobility() {
+        // the knight on e4 reaches 8 squares, 4 more than `MOBILITY_BASE`
+        let pos = Position::from_fen_str("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            MOBILITY[3] * 4,
+            mobility(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        // d6 is lost to the pawn on c7
+        let pos = Position::from_fen_str("4k3/2p5/8/8/4N3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            MOBILITY[3] * 3,
+            mobility(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+
+        // own pieces block squares, enemy pieces can be captured
+        let pos = Position::from_fen_str("4k3/8/8/8/8/8/8/R3K2r w - - 0 1").unwrap();
+        assert_eq!(
+            MOBILITY[5] * 3,
+            mobility(&pos, &DEFAULT_PARAMS, colors::WHITE)
+        );
+    }
+
+    #[test]
+    fn it_scores_king_safety() {
+        let sheltered = Position::from_fen_str("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let exposed = Position::from_fen_str("6k1/5ppp/8/8/8/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(
+            PAWN_SHIELD[0] * 3,
+            king_safety(&sheltered, &DEFAULT_PARAMS, colors::WHITE)
+        );
+        assert_eq!(
+            KING_SEMI_OPEN_FILE * 3,
+            king_safety(&exposed, &DEFAULT_PARAMS, colors::WHITE)
+        );
+
+        // queen and knight both hit the black king zone, a lone queen is ignored
+        let attacked = Position::from_fen_str("6k1/5ppp/8/5N1Q/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        let quiet = Position::from_fen_str("6k1/5ppp/8/8/8/8/5PPP/QN4K1 b - - 0 1").unwrap();
+        assert_eq!(
+            king_safety(&quiet, &DEFAULT_PARAMS, colors::BLACK) - TaperedScore::new(36, 9),
+            king_safety(&attacked, &DEFAULT_PARAMS, colors::BLACK)
+        );
+    }
+
+    #[test]
+    fn it_traces_the_evaluation() {
+        let fens = [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "8/5k2/8/1pP5/1P6/8/6K1/8 b - - 0 1",
+        ];
+        for fen in fens.iter() {
+            let pos = Position::from_fen_str(fen).unwrap();
+            let trace = pos.eval_trace();
+            assert_eq!(TERM_NAMES.len(), trace.terms.len());
+            let score = if pos.to_move() == colors::WHITE {
+                trace.score
+            } else {
+                -trace.score
+            };
+            assert_eq!(pos.evaluate(), score);
+        }
+
+        let pos = Position::from_fen_str("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let trace = pos.eval_trace_with(&DEFAULT_PARAMS);
+        assert_eq!(
+            ("Material", [MATERIAL[6], TaperedScore::default()]),
+            trace.terms[0]
+        );
+        assert!(trace.to_string().contains("Final evaluation"));
+    }
+
+    #[test]
+    fn it_evaluates_the_same_with_pawn_cache() {
+        let mut pawns = PawnHashTable::new(1);
+        let fens = [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "8/5k2/8/1pP5/1P6/8/6K1/8 b - - 0 1",
+            // again, now served from the cache
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        ];
+        for fen in fens.iter() {
+            let pos = Position::from_fen_str(fen).unwrap();
+            assert_eq!(
+                pos.evaluate(),
+                pos.evaluate_cached(&DEFAULT_PARAMS, &mut pawns)
+            );
+        }
+    }
+}
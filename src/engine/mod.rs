@@ -1,13 +1,34 @@
 mod bitboard;
 mod board;
+mod book;
+pub mod endgame;
+mod eval;
 mod move_generator;
+mod nnue;
+mod params;
+mod pawns;
 mod position;
+mod score;
 mod search;
+mod syzygy;
+mod tablebase;
+mod tt;
+mod unmove_generator;
 mod zobrist;
 
 pub use bitboard::{bitboards, Bitboard, BitboardIter, BitboardPrimitives};
 pub use board::Board;
+pub use book::{encode_move, polyglot_key, Book, BookEntry, BookError};
+pub use eval::{EvalTrace, Evaluation, TaperedScore};
 pub use move_generator::MoveGenerator;
-pub use position::Position;
-pub use search::{PerftContext, Search};
+pub use nnue::{Accumulator, Network, NetworkError};
+pub use params::{EvalParams, ParamsError, DEFAULT_PARAMS};
+pub use pawns::{PawnEntry, PawnHashTable};
+pub use position::{Position, PositionError};
+pub use score::{mate_in, mated_in, scores, Score, ScorePrimitives, MAX_PLY};
+pub use search::{PerftContext, Search, SearchInfo, SearchLimits, Searcher};
+pub use syzygy::{Syzygy, Wdl, TB_PIECES};
+pub use tablebase::{Dtm, Material, Table, TablebaseError, Tablebases, MAX_PIECES};
+pub use tt::{Bound, TTEntry, TranspositionTable};
+pub use unmove_generator::UnmoveGenerator;
 pub use zobrist::{ZobKey, ZobTables, Zobrist};
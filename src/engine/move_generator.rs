@@ -1,4 +1,4 @@
-use crate::engine::{bitboards, Bitboard, BitboardPrimitives, Board};
+use crate::engine::{bitboards, Bitboard, BitboardPrimitives, Board, Position};
 // use crate::board::Board;
 use crate::common::*;
 use crate::primitives::*;
@@ -18,85 +18,43 @@ pub trait MoveGenerator {
     fn gen_black_pawn_moves(&self, moves: &mut Vec<Move>);
     fn gen_knight_moves(&self, moves: &mut Vec<Move>, color: Color);
     fn gen_king_moves(&self, moves: &mut Vec<Move>, color: Color);
-    fn gen_wking_castle(&self, moves: &mut Vec<Move>);
-    fn gen_bking_castle(&self, moves: &mut Vec<Move>);
+    fn gen_castles(&self, moves: &mut Vec<Move>, color: Color);
     fn gen_bishop_moves(&self, moves: &mut Vec<Move>, color: Color);
     fn gen_rook_moves(&self, moves: &mut Vec<Move>, color: Color);
 }
 
-impl MoveGenerator for Board {
+/// The squares from `a` to `b` on one rank, both included
+#[inline]
+fn rank_span(a: Square, b: Square) -> Bitboard {
+    let (low, high) = (a.min(b), a.max(b));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+
+impl MoveGenerator for Position {
     fn is_mate(&mut self, _color: Color) -> bool {
-        let moves = self.generate_moves();
-        for mov in moves.iter() {
-            self.make_move(*mov);
-            if !self.is_in_check(1 ^ self.current().to_move()) {
-                self.unmake_move();
-                return false;
-            } else {
-                self.unmake_move();
-            }
-        }
-        true
+        self.legal_moves().is_empty()
     }
 
     #[inline]
     fn is_in_check(&self, color: Color) -> bool {
-        let kingpos = self.current().bb_king(color).scan();
-        self.is_attacked(color, kingpos)
+        Position::is_in_check(self, color)
     }
 
+    #[inline]
     fn is_attacked(&self, color: Color, target: Square) -> bool {
-        let pos = self.current();
-        let occupied = pos.bb_own(color) | pos.bb_opponent(color);
-
-        debug_assert!(target < 64);
-
-        // by knights
-        if (bitboards::BB_KNIGHT_ATTACKS[target as usize] & pos.bb_knights(1 ^ color)) > 0 {
-            return true;
-        }
-
-        // by pawns
-        if bitboards::BB_PAWN_ATTACKS[color as usize][target as usize] & pos.bb_pawns(1 ^ color) > 0
-        {
-            return true;
-        }
-
-        // by king?!?
-        if (bitboards::BB_KING_ATTACKS[target as usize] & pos.bb_king(1 ^ color)) > 0 {
-            return true;
-        }
-
-        // by bishops or queens
-        if bitboards::bishop_attacks(target, occupied)
-            & (pos.bb_bishops(1 ^ color) | pos.bb_queens(1 ^ color))
-            > 0
-        {
-            return true;
-        }
-
-        // by rooks or queens
-        if (bitboards::rank_attacks(target, occupied) | bitboards::file_attacks(target, occupied))
-            & (pos.bb_rooks(1 ^ color) | pos.bb_queens(1 ^ color))
-            > 0
-        {
-            return true;
-        }
-
-        false
+        Position::is_attacked(self, color, target)
     }
 
     fn generate_moves(&self) -> Vec<Move> {
         let mut moves = Vec::with_capacity(512);
-        let to_move = self.current().to_move();
+        let to_move = self.to_move();
 
         if to_move == colors::WHITE {
             self.gen_white_pawn_moves(&mut moves);
-            self.gen_wking_castle(&mut moves);
         } else {
             self.gen_black_pawn_moves(&mut moves);
-            self.gen_bking_castle(&mut moves);
         }
+        self.gen_castles(&mut moves, to_move);
 
         self.gen_knight_moves(&mut moves, to_move);
         self.gen_bishop_moves(&mut moves, to_move);
@@ -107,7 +65,7 @@ impl MoveGenerator for Board {
     }
 
     fn gen_white_pawn_moves(&self, moves: &mut Vec<Move>) {
-        let pos = self.current();
+        let pos = self;
         let pawns = pos.bb_pawns(colors::WHITE);
 
         let (ep_square, ep_bb) = match pos.en_passant() {
@@ -169,7 +127,7 @@ impl MoveGenerator for Board {
     }
 
     fn gen_black_pawn_moves(&self, moves: &mut Vec<Move>) {
-        let pos = self.current();
+        let pos = self;
         let pawns = pos.bb_pawns(colors::BLACK);
 
         let (ep_square, ep_bb) = match pos.en_passant() {
@@ -230,7 +188,7 @@ impl MoveGenerator for Board {
     }
 
     fn gen_knight_moves(&self, moves: &mut Vec<Move>, color: Color) {
-        let pos = self.current();
+        let pos = self;
         let mut knights = pos.bb_knights(color);
 
         for from in knights.iter() {
@@ -250,65 +208,58 @@ impl MoveGenerator for Board {
         }
     }
 
-    fn gen_wking_castle(&self, moves: &mut Vec<Move>) {
-        let pos = self.current();
-        let occ = pos.bb_own(colors::WHITE) | pos.bb_opponent(colors::WHITE);
-
-        if self.is_attacked(colors::WHITE, squares::E1) {
+    /// Castles with the king and rooks on any files, as in Chess960. The
+    /// squares both pieces pass through, and their destinations, must be
+    /// empty but for the two of them, and the king may not be in check or
+    /// pass an attacked square. Whether it lands on one is left to the
+    /// legality check all moves get.
+    fn gen_castles(&self, moves: &mut Vec<Move>, color: Color) {
+        let pos = self;
+        let castling = pos.castling();
+        if !castling.get(color, sides::KING_SIDE) && !castling.get(color, sides::QUEEN_SIDE) {
             return;
         }
-
-        let qlear = pos.castling().get(colors::WHITE, sides::QUEEN_SIDE) //pos.castling()[colors::WHITE as usize].test_bit(1)
-            && occ.extract_bits(squares::B1, 3) == 0
-            && !self.is_attacked(colors::WHITE, squares::C1)
-            && !self.is_attacked(colors::WHITE, squares::D1);
-
-        let klear = pos.castling().get(colors::WHITE, sides::KING_SIDE) //pos.castling()[colors::WHITE as usize].test_bit(0)
-            && occ.extract_bits(squares::F1, 2) == 0
-            && !self.is_attacked(colors::WHITE, squares::F1);
-
-        if qlear {
-            moves.push(Move::new(squares::E1, squares::C1, flags::MOV_Q_CASTLE));
-        }
-        if klear {
-            moves.push(Move::new(squares::E1, squares::G1, flags::MOV_K_CASTLE));
-        }
-    }
-
-    fn gen_bking_castle(&self, moves: &mut Vec<Move>) {
-        let pos = self.current();
-        let occ = pos.bb_own(colors::BLACK) | pos.bb_opponent(colors::BLACK);
-
-        if self.is_attacked(colors::BLACK, squares::E8) {
+        let king = pos.bb_king(color).scan();
+        if self.is_attacked(color, king) {
             return;
         }
+        let back_rank = 56 * Square::from(color);
+        let occupied = pos.bb_own(color) | pos.bb_opponent(color);
 
-        let qlear = pos.castling().get(colors::BLACK, sides::QUEEN_SIDE) //pos.castling()[colors::BLACK as usize].test_bit(1)
-            && occ.extract_bits(squares::B8, 3) == 0
-            && !self.is_attacked(colors::BLACK, squares::C8)
-            && !self.is_attacked(colors::BLACK, squares::D8);
-
-        let klear = pos.castling().get(colors::BLACK, sides::KING_SIDE) //pos.castling()[colors::BLACK as usize].test_bit(0)
-            && occ.extract_bits(squares::F8, 2) == 0
-            && !self.is_attacked(colors::BLACK, squares::F8);
-
-        if qlear {
-            moves.push(Move::new(squares::E8, squares::C8, flags::MOV_Q_CASTLE));
-        }
-        if klear {
-            moves.push(Move::new(squares::E8, squares::G8, flags::MOV_K_CASTLE));
+        for &side in [sides::KING_SIDE, sides::QUEEN_SIDE].iter() {
+            if !castling.get(color, side) {
+                continue;
+            }
+            let rook = back_rank + castling.rook_file(color, side);
+            let (king_dest, rook_dest, flag) = if side == sides::KING_SIDE {
+                (back_rank + 6, back_rank + 5, flags::MOV_K_CASTLE)
+            } else {
+                (back_rank + 2, back_rank + 3, flags::MOV_Q_CASTLE)
+            };
+            let pieces = Bitboard::bit_at(king) | Bitboard::bit_at(rook);
+            let path = rank_span(king, king_dest) | rank_span(rook, rook_dest);
+            if pos.get_piece_and_color(rook) != (piece_types::ROOK, color)
+                || occupied & path & !pieces != 0
+            {
+                continue;
+            }
+            let (low, high) = (king.min(king_dest), king.max(king_dest));
+            if (low + 1..high).any(|sq| self.is_attacked(color, sq)) {
+                continue;
+            }
+            moves.push(Move::new(king, king_dest, flag));
         }
     }
 
     fn gen_king_moves(&self, moves: &mut Vec<Move>, color: Color) {
-        let pos = self.current();
+        let pos = self;
 
         let from = pos.bb_king(color).scan();
 
         #[cfg(feature = "sanity_checks")]
         {
             if from > 63 {
-                self.panic_dump();
+                error!("{}", self);
             }
         }
 
@@ -328,7 +279,7 @@ impl MoveGenerator for Board {
     }
 
     fn gen_bishop_moves(&self, moves: &mut Vec<Move>, color: Color) {
-        let pos = self.current();
+        let pos = self;
         let mut bishops = pos.bb_bishops(color) | pos.bb_queens(color);
         let occupied = pos.bb_own(color) | pos.bb_opponent(color);
 
@@ -354,7 +305,7 @@ impl MoveGenerator for Board {
     }
 
     fn gen_rook_moves(&self, moves: &mut Vec<Move>, color: Color) {
-        let pos = self.current();
+        let pos = self;
         let mut rooks = pos.bb_rooks(color) | pos.bb_queens(color);
         let occupied = pos.bb_own(color) | pos.bb_opponent(color);
 
@@ -379,6 +330,72 @@ impl MoveGenerator for Board {
     }
 }
 
+impl MoveGenerator for Board {
+    fn is_mate(&mut self, _color: Color) -> bool {
+        let moves = self.generate_moves();
+        for mov in moves.iter() {
+            self.make_move(*mov);
+            if !self.is_in_check(1 ^ self.current().to_move()) {
+                self.unmake_move();
+                return false;
+            } else {
+                self.unmake_move();
+            }
+        }
+        true
+    }
+
+    #[inline]
+    fn is_in_check(&self, color: Color) -> bool {
+        self.current().is_in_check(color)
+    }
+
+    #[inline]
+    fn is_attacked(&self, color: Color, target: Square) -> bool {
+        self.current().is_attacked(color, target)
+    }
+
+    #[inline]
+    fn generate_moves(&self) -> Vec<Move> {
+        self.current().generate_moves()
+    }
+
+    #[inline]
+    fn gen_white_pawn_moves(&self, moves: &mut Vec<Move>) {
+        self.current().gen_white_pawn_moves(moves)
+    }
+
+    #[inline]
+    fn gen_black_pawn_moves(&self, moves: &mut Vec<Move>) {
+        self.current().gen_black_pawn_moves(moves)
+    }
+
+    #[inline]
+    fn gen_knight_moves(&self, moves: &mut Vec<Move>, color: Color) {
+        self.current().gen_knight_moves(moves, color)
+    }
+
+    #[inline]
+    fn gen_king_moves(&self, moves: &mut Vec<Move>, color: Color) {
+        self.current().gen_king_moves(moves, color)
+    }
+
+    #[inline]
+    fn gen_castles(&self, moves: &mut Vec<Move>, color: Color) {
+        self.current().gen_castles(moves, color)
+    }
+
+    #[inline]
+    fn gen_bishop_moves(&self, moves: &mut Vec<Move>, color: Color) {
+        self.current().gen_bishop_moves(moves, color)
+    }
+
+    #[inline]
+    fn gen_rook_moves(&self, moves: &mut Vec<Move>, color: Color) {
+        self.current().gen_rook_moves(moves, color)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::engine::{Board, MoveGenerator};
@@ -458,7 +475,7 @@ mod tests {
         assert_eq!(2, moves.len());
         moves.clear();
 
-        MoveGenerator::gen_wking_castle(&board, &mut moves);
+        MoveGenerator::gen_castles(&board, &mut moves, colors::WHITE);
         assert_eq!(1, moves.len());
         moves.clear();
     }
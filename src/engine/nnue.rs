@@ -0,0 +1,581 @@
+//! Efficiently updatable neural network evaluation
+//!
+//! A small network with the plain 768-input layout: one input per colored
+//! piece type and square, seen from each side's perspective, feeding a
+//! hidden layer of `HIDDEN` neurons per perspective and a single output.
+//!
+//! The hidden layer lives in an `Accumulator`, which a `Position` carries
+//! on the heap while a network is set. `Position::set_piece`, `remove_piece`,
+//! `quiet_move_piece` and the other piece primitives update it along with
+//! the hash keys, so a full evaluation only costs the clipped ReLU and the
+//! output layer.
+//!
+//! Network files are little-endian: the magic `DRNN`, a `u32` version,
+//! `u32` input and hidden sizes, then `i16` feature weights (input major),
+//! feature biases, output weights (side to move first) and the output bias.
+
+use crate::engine::{scores, BitboardPrimitives, Position, Score};
+use crate::primitives::*;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 128;
+
+/// Clipped ReLU ceiling, the quantization of the hidden layer
+const QA: i32 = 255;
+/// Quantization of the output weights
+const QB: i32 = 64;
+/// Converts the network output to centipawns
+const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 4] = b"DRNN";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+const FILE_SIZE: usize = HEADER_SIZE + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(io::Error),
+    InvalidHeader,
+    WrongSize { expected: usize, found: usize },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Io(e) => write!(f, "{}", e),
+            NetworkError::InvalidHeader => write!(
+                f,
+                "not a version {} network with {} inputs and {} hidden neurons",
+                VERSION, INPUTS, HIDDEN
+            ),
+            NetworkError::WrongSize { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+/// Quantized network weights
+#[derive(Clone, PartialEq, Eq)]
+pub struct Network {
+    /// `INPUTS` rows of `HIDDEN` weights
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    /// `HIDDEN` weights for the side to move, then `HIDDEN` for the other side
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Network({}x2 -> 1)", HIDDEN)
+    }
+}
+
+impl Network {
+    /// Creates a network from its raw parts, panicking on wrong sizes
+    pub fn new(
+        feature_weights: Vec<i16>,
+        feature_bias: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i16,
+    ) -> Network {
+        assert_eq!(INPUTS * HIDDEN, feature_weights.len());
+        assert_eq!(HIDDEN, feature_bias.len());
+        assert_eq!(2 * HIDDEN, output_weights.len());
+        Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Network> {
+        Ok(Self::from_bytes(
+            &fs::read(path).map_err(NetworkError::Io)?,
+        )?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NetworkError> {
+        let header_u32 = |i: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[4 + 4 * i..8 + 4 * i]);
+            u32::from_le_bytes(word) as usize
+        };
+        if bytes.len() < HEADER_SIZE
+            || &bytes[..4] != MAGIC
+            || header_u32(0) != VERSION as usize
+            || header_u32(1) != INPUTS
+            || header_u32(2) != HIDDEN
+        {
+            return Err(NetworkError::InvalidHeader);
+        }
+        if bytes.len() != FILE_SIZE {
+            return Err(NetworkError::WrongSize {
+                expected: FILE_SIZE,
+                found: bytes.len(),
+            });
+        }
+
+        let mut values = bytes[HEADER_SIZE..]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut take = |n: usize| values.by_ref().take(n).collect::<Vec<i16>>();
+        let feature_weights = take(INPUTS * HIDDEN);
+        let feature_bias = take(HIDDEN);
+        let output_weights = take(2 * HIDDEN);
+        let output_bias = take(1)[0];
+        Ok(Network::new(
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FILE_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        for &word in &[VERSION, INPUTS as u32, HIDDEN as u32] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        let values = self
+            .feature_weights
+            .iter()
+            .chain(self.feature_bias.iter())
+            .chain(self.output_weights.iter())
+            .chain(Some(&self.output_bias));
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[inline]
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN..(feature + 1) * HIDDEN]
+    }
+
+    /// Evaluation from the side to move's point of view
+    pub fn evaluate(&self, acc: &Accumulator, to_move: Color) -> Score {
+        let us = &acc.values[to_move as usize];
+        let them = &acc.values[1 ^ to_move as usize];
+        // any weights are valid input, so the sum may leave the i32 range
+        let sum = i64::from(simd::crelu_dot(us, &self.output_weights[..HIDDEN]))
+            + i64::from(simd::crelu_dot(them, &self.output_weights[HIDDEN..]))
+            + i64::from(self.output_bias);
+        let bound = i64::from(scores::TB_BOUND - 1);
+        // keep clear of tablebase and mate scores
+        (sum * i64::from(SCALE) / i64::from(QA * QB)).clamp(-bound, bound) as Score
+    }
+}
+
+/// Input index of `piece` of `color` on `square`, seen by `perspective`
+#[inline]
+fn feature(perspective: Color, piece: Piece, color: Color, square: Square) -> usize {
+    let (color, square) = if perspective == colors::WHITE {
+        (color, square)
+    } else {
+        (1 ^ color, square ^ 56)
+    };
+    (usize::from(color) * 6 + usize::from(piece - piece_types::PAWN)) * 64 + square as usize
+}
+
+/// Hidden layer values for both perspectives, indexed by color, of the
+/// network they were computed with
+pub struct Accumulator {
+    network: Arc<Network>,
+    pub values: [[i16; HIDDEN]; 2],
+}
+
+impl Clone for Accumulator {
+    fn clone(&self) -> Self {
+        Accumulator {
+            network: Arc::clone(&self.network),
+            values: self.values,
+        }
+    }
+
+    /// Skips the reference count when both share the network, as on every
+    /// `Board::make_move`
+    fn clone_from(&mut self, source: &Self) {
+        if !Arc::ptr_eq(&self.network, &source.network) {
+            self.network = Arc::clone(&source.network);
+        }
+        self.values = source.values;
+    }
+}
+
+impl Accumulator {
+    /// Computes both perspectives of `pos` from scratch
+    pub fn new(network: Arc<Network>, pos: &Position) -> Accumulator {
+        let mut acc = Accumulator {
+            values: [[0; HIDDEN]; 2],
+            network,
+        };
+        acc.refresh(pos);
+        acc
+    }
+
+    /// Recomputes both perspectives from scratch
+    pub fn refresh(&mut self, pos: &Position) {
+        for values in self.values.iter_mut() {
+            values.copy_from_slice(&self.network.feature_bias);
+        }
+        for color in colors::WHITE..=colors::BLACK {
+            for piece in piece_types::PAWN..=piece_types::KING {
+                let mut pieces = pos.bb()[Position::bb_idx(color, piece)];
+                for square in pieces.iter() {
+                    self.add(piece, color, square);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Evaluation from the side to move's point of view
+    #[inline]
+    pub fn evaluate(&self, to_move: Color) -> Score {
+        self.network.evaluate(self, to_move)
+    }
+
+    #[inline]
+    pub fn add(&mut self, piece: Piece, color: Color, square: Square) {
+        let net = &*self.network;
+        for (perspective, values) in self.values.iter_mut().enumerate() {
+            let idx = feature(perspective as Color, piece, color, square);
+            simd::add_assign(values, net.weights(idx));
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, piece: Piece, color: Color, square: Square) {
+        let net = &*self.network;
+        for (perspective, values) in self.values.iter_mut().enumerate() {
+            let idx = feature(perspective as Color, piece, color, square);
+            simd::sub_assign(values, net.weights(idx));
+        }
+    }
+}
+
+/// Vector helpers over `i16` slices whose length is a multiple of 16, using
+/// AVX2 when the build targets it and SSE2 on any other x86_64
+mod simd {
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    #[inline]
+    pub fn add_assign(values: &mut [i16], weights: &[i16]) {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            for i in (0..values.len()).step_by(16) {
+                let v = values.as_mut_ptr().add(i) as *mut __m256i;
+                let w = weights.as_ptr().add(i) as *const __m256i;
+                _mm256_storeu_si256(
+                    v,
+                    _mm256_add_epi16(_mm256_loadu_si256(v), _mm256_loadu_si256(w)),
+                );
+            }
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    #[inline]
+    pub fn sub_assign(values: &mut [i16], weights: &[i16]) {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            for i in (0..values.len()).step_by(16) {
+                let v = values.as_mut_ptr().add(i) as *mut __m256i;
+                let w = weights.as_ptr().add(i) as *const __m256i;
+                _mm256_storeu_si256(
+                    v,
+                    _mm256_sub_epi16(_mm256_loadu_si256(v), _mm256_loadu_si256(w)),
+                );
+            }
+        }
+    }
+
+    /// Sum of `clamp(values, 0, QA) * weights`
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    #[inline]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            let zero = _mm256_setzero_si256();
+            let ceiling = _mm256_set1_epi16(super::QA as i16);
+            let mut sum = _mm256_setzero_si256();
+            for i in (0..values.len()).step_by(16) {
+                let v = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+                let v = _mm256_min_epi16(_mm256_max_epi16(v, zero), ceiling);
+                let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+                sum = _mm256_add_epi32(sum, _mm256_madd_epi16(v, w));
+            }
+            let sum = _mm_add_epi32(
+                _mm256_castsi256_si128(sum),
+                _mm256_extracti128_si256(sum, 1),
+            );
+            hsum_epi32(sum)
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(target_feature = "avx2")))]
+    #[inline]
+    pub fn add_assign(values: &mut [i16], weights: &[i16]) {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            for i in (0..values.len()).step_by(8) {
+                let v = values.as_mut_ptr().add(i) as *mut __m128i;
+                let w = weights.as_ptr().add(i) as *const __m128i;
+                _mm_storeu_si128(v, _mm_add_epi16(_mm_loadu_si128(v), _mm_loadu_si128(w)));
+            }
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(target_feature = "avx2")))]
+    #[inline]
+    pub fn sub_assign(values: &mut [i16], weights: &[i16]) {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            for i in (0..values.len()).step_by(8) {
+                let v = values.as_mut_ptr().add(i) as *mut __m128i;
+                let w = weights.as_ptr().add(i) as *const __m128i;
+                _mm_storeu_si128(v, _mm_sub_epi16(_mm_loadu_si128(v), _mm_loadu_si128(w)));
+            }
+        }
+    }
+
+    /// Sum of `clamp(values, 0, QA) * weights`
+    #[cfg(all(target_arch = "x86_64", not(target_feature = "avx2")))]
+    #[inline]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        debug_assert_eq!(values.len(), weights.len());
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let ceiling = _mm_set1_epi16(super::QA as i16);
+            let mut sum = _mm_setzero_si128();
+            for i in (0..values.len()).step_by(8) {
+                let v = _mm_loadu_si128(values.as_ptr().add(i) as *const __m128i);
+                let v = _mm_min_epi16(_mm_max_epi16(v, zero), ceiling);
+                let w = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+                sum = _mm_add_epi32(sum, _mm_madd_epi16(v, w));
+            }
+            hsum_epi32(sum)
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    unsafe fn hsum_epi32(sum: __m128i) -> i32 {
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub use super::scalar::*;
+}
+
+/// Plain versions of the `simd` helpers
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+mod scalar {
+    #[inline]
+    pub fn add_assign(values: &mut [i16], weights: &[i16]) {
+        for (v, w) in values.iter_mut().zip(weights) {
+            *v = v.wrapping_add(*w);
+        }
+    }
+
+    #[inline]
+    pub fn sub_assign(values: &mut [i16], weights: &[i16]) {
+        for (v, w) in values.iter_mut().zip(weights) {
+            *v = v.wrapping_sub(*w);
+        }
+    }
+
+    #[inline]
+    pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+        values
+            .iter()
+            .zip(weights)
+            .map(|(&v, &w)| i32::from(v).clamp(0, super::QA) * i32::from(w))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Board, MoveGenerator};
+    use crate::interfaces::FenInterface;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64;
+    use std::sync::Arc;
+
+    fn random_network() -> Network {
+        let mut rng = Pcg64::seed_from_u64(1);
+        let mut values = |n: usize, range: i16| {
+            (0..n)
+                .map(|_| rng.gen_range(-range, range))
+                .collect::<Vec<i16>>()
+        };
+        let feature_weights = values(INPUTS * HIDDEN, 64);
+        let feature_bias = values(HIDDEN, 64);
+        let output_weights = values(2 * HIDDEN, 64);
+        Network::new(feature_weights, feature_bias, output_weights, 100)
+    }
+
+    fn check_accumulators(board: &mut Board, net: &Arc<Network>, depth: u32) {
+        let fresh = Accumulator::new(Arc::clone(net), board.current());
+        assert_eq!(fresh.values, board.accumulator().unwrap().values);
+        if depth == 0 {
+            return;
+        }
+        for mov in board.generate_moves() {
+            board.make_move(mov);
+            if !board.is_in_check(1 ^ board.current().to_move()) {
+                check_accumulators(board, net, depth - 1);
+            }
+            board.unmake_move();
+        }
+    }
+
+    #[test]
+    fn it_updates_accumulators_incrementally() {
+        let net = Arc::new(random_network());
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        for fen in fens.iter() {
+            let mut board = Board::from_fen_str(fen).unwrap();
+            board.set_network(Some(Arc::clone(&net)));
+            check_accumulators(&mut board, &net, 2);
+        }
+
+        // past the initial size of the position stack
+        let mut board = Board::startpos();
+        board.set_network(Some(Arc::clone(&net)));
+        for _ in 0..20 {
+            for mov in &["g1f3", "g8f6", "f3g1", "f6g8"] {
+                board.input_lan_move(mov).unwrap();
+            }
+        }
+        check_accumulators(&mut board, &net, 1);
+        board.set_network(None);
+        assert!(board.accumulator().is_none());
+    }
+
+    #[test]
+    fn it_updates_accumulators_in_the_piece_primitives() {
+        let net = Arc::new(random_network());
+        let mut pos = Position::from_fen_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        pos.set_network(Some(Arc::clone(&net)));
+        pos.set_piece(piece_types::QUEEN, colors::BLACK, 35);
+        pos.quiet_move_piece(piece_types::PAWN, colors::WHITE, 12, 20);
+        pos.remove_piece(piece_types::KING, colors::BLACK, 60);
+        pos.capture_move_piece(piece_types::QUEEN, colors::BLACK, piece_types::PAWN, 35, 20);
+        pos.replace_piece(
+            piece_types::QUEEN,
+            colors::BLACK,
+            piece_types::KNIGHT,
+            colors::WHITE,
+            20,
+        );
+        let fresh = Accumulator::new(net, &pos);
+        assert_eq!(fresh.values, pos.accumulator().unwrap().values);
+    }
+
+    #[test]
+    fn it_matches_the_scalar_reference() {
+        let net = Arc::new(random_network());
+        let pos = Position::from_fen_str(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let acc = Accumulator::new(Arc::clone(&net), &pos);
+        for perspective in 0..2 {
+            for half in 0..2 {
+                let weights = &net.output_weights[half * HIDDEN..(half + 1) * HIDDEN];
+                assert_eq!(
+                    scalar::crelu_dot(&acc.values[perspective], weights),
+                    simd::crelu_dot(&acc.values[perspective], weights)
+                );
+            }
+        }
+
+        let mut simd_values = acc.values[0];
+        let mut scalar_values = acc.values[0];
+        simd::add_assign(&mut simd_values, net.weights(100));
+        scalar::add_assign(&mut scalar_values, net.weights(100));
+        simd::sub_assign(&mut simd_values, net.weights(7));
+        scalar::sub_assign(&mut scalar_values, net.weights(7));
+        assert_eq!(scalar_values[..], simd_values[..]);
+    }
+
+    #[test]
+    fn it_evaluates_mirrored_positions_alike() {
+        let net = Arc::new(random_network());
+        let mut board =
+            Board::from_fen_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let mut mirrored =
+            Board::from_fen_str("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+                .unwrap();
+        board.set_network(Some(Arc::clone(&net)));
+        mirrored.set_network(Some(net));
+        assert!(board.evaluate_network().is_some());
+        assert_eq!(board.evaluate_network(), mirrored.evaluate_network());
+    }
+
+    #[test]
+    fn it_clamps_extreme_outputs() {
+        let pos = Position::from_fen_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for &weight in &[i16::MAX, i16::MIN] {
+            let net = Arc::new(Network::new(
+                vec![0; INPUTS * HIDDEN],
+                vec![i16::MAX; HIDDEN],
+                vec![weight; 2 * HIDDEN],
+                weight,
+            ));
+            let acc = Accumulator::new(net, &pos);
+            let bound = scores::TB_BOUND - 1;
+            let expected = if weight > 0 { bound } else { -bound };
+            assert_eq!(expected, acc.evaluate(colors::WHITE));
+        }
+    }
+
+    #[test]
+    fn it_reads_back_written_networks() {
+        let net = random_network();
+        let bytes = net.to_bytes();
+        assert_eq!(FILE_SIZE, bytes.len());
+        assert_eq!(net, Network::from_bytes(&bytes).unwrap());
+
+        match Network::from_bytes(&bytes[..bytes.len() - 2]) {
+            Err(NetworkError::WrongSize { expected, found }) => {
+                assert_eq!((FILE_SIZE, FILE_SIZE - 2), (expected, found))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match Network::from_bytes(b"NNUE") {
+            Err(NetworkError::InvalidHeader) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
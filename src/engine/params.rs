@@ -0,0 +1,484 @@
+use crate::engine::endgame::{SCALE_OCB, SCALE_OCB_PURE};
+use crate::engine::eval::*;
+use crate::engine::pawns::*;
+use crate::engine::{Score, TaperedScore};
+use crate::primitives::*;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+
+/// Names of the piece-square tables in `EvalParams::pst`
+const PST_NAMES: [&str; 6] = [
+    "pst_pawn",
+    "pst_knight",
+    "pst_bishop",
+    "pst_rook",
+    "pst_queen",
+    "pst_king",
+];
+
+/// A parameter of `EvalParams`, by its index in `fields` or `plain_fields`
+#[derive(Clone, Copy)]
+enum Field {
+    Tapered(usize),
+    Plain(usize),
+}
+
+lazy_static! {
+    /// The compiled-in parameter set
+    pub static ref DEFAULT_PARAMS: EvalParams = EvalParams::default();
+}
+
+/// All tunable evaluation weights
+///
+/// The defaults are the constants in `engine::eval`, `engine::pawns` and
+/// `engine::endgame`. Arrays indexed by piece type or relative rank keep
+/// their unused slots so they can be indexed the same way as the constants.
+/// Most weights are tapered scores, the rest plain numbers, see
+/// `plain_fields`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    pub material: [TaperedScore; 8],
+    /// Piece-square tables by piece type, a8 first (white indexes with `square ^ 56`)
+    pub pst: [[TaperedScore; 64]; 6],
+    pub doubled: TaperedScore,
+    pub isolated: TaperedScore,
+    pub backward: TaperedScore,
+    pub connected: [TaperedScore; 8],
+    pub passed: [TaperedScore; 8],
+    pub passed_king_distance: [TaperedScore; 8],
+    pub mobility: [TaperedScore; 8],
+    pub pawn_shield: [TaperedScore; 2],
+    pub king_semi_open_file: TaperedScore,
+    pub king_open_file: TaperedScore,
+    pub mobility_base: [Score; 8],
+    pub king_attack_weights: [Score; 8],
+    pub king_attack_max: Score,
+    /// Endgame scale factors for opposite-colored bishops, see
+    /// `endgame::scale_factor`
+    pub scale_ocb_pure: i32,
+    pub scale_ocb: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let mut pst = [[TaperedScore::default(); 64]; 6];
+        for (piece, table) in pst.iter_mut().enumerate() {
+            for (idx, score) in table.iter_mut().enumerate() {
+                *score = TaperedScore::new(PST_MG[piece][idx], PST_EG[piece][idx]);
+            }
+        }
+        EvalParams {
+            material: MATERIAL,
+            pst,
+            doubled: DOUBLED,
+            isolated: ISOLATED,
+            backward: BACKWARD,
+            connected: CONNECTED,
+            passed: PASSED,
+            passed_king_distance: PASSED_KING_DISTANCE,
+            mobility: MOBILITY,
+            pawn_shield: PAWN_SHIELD,
+            king_semi_open_file: KING_SEMI_OPEN_FILE,
+            king_open_file: KING_OPEN_FILE,
+            mobility_base: MOBILITY_BASE,
+            king_attack_weights: KING_ATTACK_WEIGHTS,
+            king_attack_max: KING_ATTACK_MAX,
+            scale_ocb_pure: SCALE_OCB_PURE,
+            scale_ocb: SCALE_OCB,
+        }
+    }
+}
+
+impl EvalParams {
+    /// Piece-square table entry for `piece` of `color` on `square`
+    #[inline]
+    pub fn pst(&self, piece: Piece, color: Color, square: Square) -> TaperedScore {
+        let idx = if color == colors::WHITE {
+            square ^ 56
+        } else {
+            square
+        } as usize;
+        self.pst[usize::from(piece - piece_types::PAWN)][idx]
+    }
+
+    /// All parameters by name, in a fixed order
+    pub fn fields(&self) -> Vec<(&'static str, &[TaperedScore])> {
+        let mut fields: Vec<(&'static str, &[TaperedScore])> = vec![("material", &self.material)];
+        for (&name, table) in PST_NAMES.iter().zip(self.pst.iter()) {
+            fields.push((name, table));
+        }
+        fields.extend_from_slice(&[
+            ("doubled", slice::from_ref(&self.doubled)),
+            ("isolated", slice::from_ref(&self.isolated)),
+            ("backward", slice::from_ref(&self.backward)),
+            ("connected", &self.connected),
+            ("passed", &self.passed),
+            ("passed_king_distance", &self.passed_king_distance),
+            ("mobility", &self.mobility),
+            ("pawn_shield", &self.pawn_shield),
+            (
+                "king_semi_open_file",
+                slice::from_ref(&self.king_semi_open_file),
+            ),
+            ("king_open_file", slice::from_ref(&self.king_open_file)),
+        ]);
+        fields
+    }
+
+    /// Parameters which aren't tapered, like square counts and scale
+    /// factors, by name in a fixed order
+    pub fn plain_fields(&self) -> Vec<(&'static str, &[Score])> {
+        vec![
+            ("mobility_base", &self.mobility_base),
+            ("king_attack_weights", &self.king_attack_weights),
+            ("king_attack_max", slice::from_ref(&self.king_attack_max)),
+            ("scale_ocb_pure", slice::from_ref(&self.scale_ocb_pure)),
+            ("scale_ocb", slice::from_ref(&self.scale_ocb)),
+        ]
+    }
+
+    /// Mutable version of `plain_fields`, in the same order
+    pub fn plain_fields_mut(&mut self) -> Vec<(&'static str, &mut [Score])> {
+        vec![
+            ("mobility_base", &mut self.mobility_base),
+            ("king_attack_weights", &mut self.king_attack_weights),
+            (
+                "king_attack_max",
+                slice::from_mut(&mut self.king_attack_max),
+            ),
+            ("scale_ocb_pure", slice::from_mut(&mut self.scale_ocb_pure)),
+            ("scale_ocb", slice::from_mut(&mut self.scale_ocb)),
+        ]
+    }
+
+    /// Mutable version of `fields`, in the same order
+    pub fn fields_mut(&mut self) -> Vec<(&'static str, &mut [TaperedScore])> {
+        let mut fields: Vec<(&'static str, &mut [TaperedScore])> =
+            vec![("material", &mut self.material)];
+        for (&name, table) in PST_NAMES.iter().zip(self.pst.iter_mut()) {
+            fields.push((name, table));
+        }
+        fields.push(("doubled", slice::from_mut(&mut self.doubled)));
+        fields.push(("isolated", slice::from_mut(&mut self.isolated)));
+        fields.push(("backward", slice::from_mut(&mut self.backward)));
+        fields.push(("connected", &mut self.connected));
+        fields.push(("passed", &mut self.passed));
+        fields.push(("passed_king_distance", &mut self.passed_king_distance));
+        fields.push(("mobility", &mut self.mobility));
+        fields.push(("pawn_shield", &mut self.pawn_shield));
+        fields.push((
+            "king_semi_open_file",
+            slice::from_mut(&mut self.king_semi_open_file),
+        ));
+        fields.push(("king_open_file", slice::from_mut(&mut self.king_open_file)));
+        fields
+    }
+
+    fn find_field(&self, name: &str) -> Option<Field> {
+        self.fields()
+            .iter()
+            .position(|&(field, _)| field == name)
+            .map(Field::Tapered)
+            .or_else(|| {
+                self.plain_fields()
+                    .iter()
+                    .position(|&(field, _)| field == name)
+                    .map(Field::Plain)
+            })
+    }
+
+    /// Name and number of values of `field`
+    fn field_info(&self, field: Field) -> (&'static str, usize) {
+        match field {
+            Field::Tapered(idx) => {
+                let (name, scores) = self.fields()[idx];
+                (name, scores.len())
+            }
+            Field::Plain(idx) => {
+                let (name, values) = self.plain_fields()[idx];
+                (name, values.len())
+            }
+        }
+    }
+
+    /// All parameters flattened to middlegame/endgame pairs, followed by
+    /// the plain ones, for tuning
+    pub fn values(&self) -> Vec<Score> {
+        let plain = self.plain_fields();
+        self.fields()
+            .iter()
+            .flat_map(|(_, scores)| scores.iter())
+            .flat_map(|score| iter::once(score.mg).chain(iter::once(score.eg)))
+            .chain(plain.iter().flat_map(|(_, values)| values.iter().copied()))
+            .collect()
+    }
+
+    /// Reads a parameter file as written by the tuner, see `FromStr`
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<EvalParams> {
+        Ok(fs::read_to_string(path)
+            .map_err(ParamsError::Io)?
+            .parse::<EvalParams>()?)
+    }
+
+    /// Inverse of `values`
+    pub fn set_values(&mut self, values: &[Score]) {
+        let mut values = values.iter();
+        for (_, scores) in self.fields_mut() {
+            for score in scores.iter_mut() {
+                score.mg = *values.next().expect("too few parameter values");
+                score.eg = *values.next().expect("too few parameter values");
+            }
+        }
+        for (_, plain) in self.plain_fields_mut() {
+            for value in plain.iter_mut() {
+                *value = *values.next().expect("too few parameter values");
+            }
+        }
+    }
+}
+
+/// Writes one `name:` line per parameter followed by `mg,eg` pairs, or
+/// single numbers for the plain parameters, eight to a line
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tapered = self.fields().into_iter().map(|(name, scores)| {
+            let values: Vec<String> = scores
+                .iter()
+                .map(|score| format!(" {:>4},{:<4}", score.mg, score.eg))
+                .collect();
+            (name, values)
+        });
+        let plain = self.plain_fields().into_iter().map(|(name, values)| {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| format!(" {:>4}", value))
+                .collect();
+            (name, values)
+        });
+        for (name, values) in tapered.chain(plain) {
+            write!(f, "{}:", name)?;
+            if values.len() > 1 {
+                writeln!(f)?;
+            }
+            for row in values.chunks(8) {
+                writeln!(f, "{}", row.concat().trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(io::Error),
+    UnknownField {
+        line: usize,
+        name: String,
+    },
+    InvalidValue {
+        line: usize,
+        expected: &'static str,
+        value: String,
+    },
+    MissingField {
+        line: usize,
+    },
+    WrongCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::Io(e) => write!(f, "{}", e),
+            ParamsError::UnknownField { line, name } => {
+                write!(f, "line {}: unknown parameter {}", line, name)
+            }
+            ParamsError::InvalidValue {
+                line,
+                expected,
+                value,
+            } => write!(f, "line {}: expected {} but got {}", line, expected, value),
+            ParamsError::MissingField { line } => {
+                write!(f, "line {}: value without a parameter name", line)
+            }
+            ParamsError::WrongCount {
+                name,
+                expected,
+                found,
+            } => write!(f, "{} needs {} values, got {}", name, expected, found),
+        }
+    }
+}
+
+impl Error for ParamsError {}
+
+/// Parses the format written by `Display`: a `name:` token starts a
+/// parameter, followed by its `mg,eg` pairs or plain numbers, `#` starts a
+/// comment. Parameters missing from the input keep their compiled-in
+/// defaults, so a file may only list the ones it changes.
+impl FromStr for EvalParams {
+    type Err = ParamsError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::default();
+        // parameter being read and number of values read so far
+        let mut current: Option<(Field, usize)> = None;
+
+        let check_count = |params: &EvalParams, current: Option<(Field, usize)>| {
+            if let Some((field, found)) = current {
+                let (name, expected) = params.field_info(field);
+                if found != expected {
+                    return Err(ParamsError::WrongCount {
+                        name: name.to_string(),
+                        expected,
+                        found,
+                    });
+                }
+            }
+            Ok(())
+        };
+
+        for (num, line) in input.lines().enumerate() {
+            let line_no = num + 1;
+            let line = line.split('#').next().unwrap_or("");
+            for token in line.split_whitespace() {
+                if token.ends_with(':') {
+                    check_count(&params, current)?;
+                    let name = token.trim_end_matches(':');
+                    let field =
+                        params
+                            .find_field(name)
+                            .ok_or_else(|| ParamsError::UnknownField {
+                                line: line_no,
+                                name: name.to_string(),
+                            })?;
+                    current = Some((field, 0));
+                    continue;
+                }
+
+                let (field, found) = current.ok_or(ParamsError::MissingField { line: line_no })?;
+                let invalid = |expected| ParamsError::InvalidValue {
+                    line: line_no,
+                    expected,
+                    value: token.to_string(),
+                };
+                match field {
+                    Field::Tapered(idx) => {
+                        let mut parts = token.split(',');
+                        let (mg, eg) = match (parts.next(), parts.next(), parts.next()) {
+                            (Some(mg), Some(eg), None) => (
+                                mg.parse::<Score>().map_err(|_| invalid("mg,eg"))?,
+                                eg.parse::<Score>().map_err(|_| invalid("mg,eg"))?,
+                            ),
+                            _ => return Err(invalid("mg,eg")),
+                        };
+                        if let Some(score) = params.fields_mut()[idx].1.get_mut(found) {
+                            *score = TaperedScore::new(mg, eg);
+                        }
+                    }
+                    Field::Plain(idx) => {
+                        let value = token.parse::<Score>().map_err(|_| invalid("a number"))?;
+                        if let Some(plain) = params.plain_fields_mut()[idx].1.get_mut(found) {
+                            *plain = value;
+                        }
+                    }
+                }
+                current = Some((field, found + 1));
+            }
+        }
+        check_count(&params, current)?;
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_flattens_parameters() {
+        let mut params = EvalParams::default();
+        let mut values = params.values();
+        assert_eq!(
+            2 * (8 + 6 * 64 + 3 + 8 * 4 + 2 + 2) + 8 + 8 + 3,
+            values.len()
+        );
+        assert_eq!(&[0, 0, 0, 0, 82, 94], &values[..6]);
+        assert_eq!(Some(&SCALE_OCB), values.last());
+
+        values[4] = 100;
+        values[2 * (8 + 6 * 64 + 3 + 8 * 4 + 2 + 2) + 3] = 5;
+        params.set_values(&values);
+        assert_eq!(TaperedScore::new(100, 94), params.material[2]);
+        assert_eq!(5, params.mobility_base[piece_types::KNIGHT as usize]);
+        assert_eq!(values, params.values());
+    }
+
+    #[test]
+    fn it_reads_back_written_parameters() {
+        let mut params = EvalParams::default();
+        params.material[3] = TaperedScore::new(300, 310);
+        params.pst[5][63] = TaperedScore::new(-1, -2);
+        params.king_open_file = TaperedScore::new(-30, 5);
+        params.mobility_base[piece_types::QUEEN as usize] = 12;
+        params.king_attack_max = 450;
+        let parsed: EvalParams = params.to_string().parse().unwrap();
+        assert_eq!(params, parsed);
+    }
+
+    #[test]
+    fn it_keeps_defaults_for_missing_parameters() {
+        let parsed: EvalParams = "# only doubled pawns\ndoubled: -20,-40\nscale_ocb: 40\n"
+            .parse()
+            .unwrap();
+        assert_eq!(TaperedScore::new(-20, -40), parsed.doubled);
+        assert_eq!(40, parsed.scale_ocb);
+        assert_eq!(EvalParams::default().pst, parsed.pst);
+    }
+
+    #[test]
+    fn it_rejects_invalid_parameter_files() {
+        let err = "doubled: -20,-40\nbogus: 1,2"
+            .parse::<EvalParams>()
+            .unwrap_err();
+        assert_eq!("line 2: unknown parameter bogus", err.to_string());
+
+        let err = "doubled: -20".parse::<EvalParams>().unwrap_err();
+        assert_eq!("line 1: expected mg,eg but got -20", err.to_string());
+
+        let err = "king_attack_max: 1,2".parse::<EvalParams>().unwrap_err();
+        assert_eq!("line 1: expected a number but got 1,2", err.to_string());
+
+        let err = "1,2".parse::<EvalParams>().unwrap_err();
+        assert_eq!("line 1: value without a parameter name", err.to_string());
+
+        let err = "pawn_shield: 1,2\ndoubled: 1,2"
+            .parse::<EvalParams>()
+            .unwrap_err();
+        assert_eq!("pawn_shield needs 2 values, got 1", err.to_string());
+    }
+
+    #[test]
+    fn it_looks_up_piece_squares_from_both_sides() {
+        let params = EvalParams::default();
+        assert_eq!(
+            params.pst(piece_types::KNIGHT, colors::WHITE, squares::G1),
+            params.pst(piece_types::KNIGHT, colors::BLACK, squares::G8)
+        );
+        assert_eq!(
+            TaperedScore::new(PST_MG[0][8], PST_EG[0][8]),
+            params.pst(piece_types::PAWN, colors::WHITE, squares::A7)
+        );
+    }
+}
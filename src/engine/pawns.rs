@@ -0,0 +1,293 @@
+use crate::engine::bitboards::*;
+use crate::engine::{
+    Bitboard, BitboardPrimitives, EvalParams, Position, Score, TaperedScore, ZobKey,
+};
+use crate::primitives::*;
+
+use std::mem;
+
+pub const DOUBLED: TaperedScore = TaperedScore::new(-10, -20);
+pub const ISOLATED: TaperedScore = TaperedScore::new(-5, -15);
+pub const BACKWARD: TaperedScore = TaperedScore::new(-9, -10);
+
+/// Bonus for pawns standing next to or defended by a friendly pawn,
+/// indexed by relative rank
+pub const CONNECTED: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(4, 2),
+    TaperedScore::new(6, 4),
+    TaperedScore::new(12, 10),
+    TaperedScore::new(24, 22),
+    TaperedScore::new(40, 45),
+    TaperedScore::new(0, 0),
+];
+
+/// Bonus for passed pawns, indexed by relative rank
+pub const PASSED: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(2, 8),
+    TaperedScore::new(5, 12),
+    TaperedScore::new(10, 20),
+    TaperedScore::new(25, 40),
+    TaperedScore::new(50, 80),
+    TaperedScore::new(90, 130),
+    TaperedScore::new(0, 0),
+];
+
+/// Weight of the king distance term for passed pawns, indexed by relative
+/// rank. The bonus grows with the enemy king's distance to the square in
+/// front of the pawn and shrinks with our own king's.
+pub const PASSED_KING_DISTANCE: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 1),
+    TaperedScore::new(0, 2),
+    TaperedScore::new(0, 3),
+    TaperedScore::new(0, 4),
+    TaperedScore::new(0, 0),
+];
+
+/// Rank of `square` as seen from `color`'s side of the board
+#[inline]
+pub fn relative_rank(color: Color, square: Square) -> usize {
+    let rank = (square >> 3) as usize;
+    if color == colors::WHITE {
+        rank
+    } else {
+        7 - rank
+    }
+}
+
+/// The square directly in front of a pawn on `square`
+#[inline]
+fn front_square(color: Color, square: Square) -> Square {
+    if color == colors::WHITE {
+        square + 8
+    } else {
+        square - 8
+    }
+}
+
+/// Pawn structure evaluation for one position, independent of the
+/// placement of all other pieces and therefore cacheable by pawn key
+#[derive(Clone, Copy, Debug)]
+pub struct PawnEntry {
+    pub key: ZobKey,
+    /// Structure score per color
+    pub score: [TaperedScore; 2],
+    /// Passed pawns per color
+    pub passed: [Bitboard; 2],
+}
+
+impl PawnEntry {
+    pub fn new(pos: &Position, params: &EvalParams) -> PawnEntry {
+        let mut entry = PawnEntry {
+            key: pos.pawn_hash(),
+            score: [TaperedScore::default(); 2],
+            passed: [0; 2],
+        };
+        for color in colors::WHITE..=colors::BLACK {
+            let (score, passed) = pawn_structure(pos, params, color);
+            entry.score[color as usize] = score;
+            entry.passed[color as usize] = passed;
+        }
+        entry
+    }
+
+    /// Structure score plus the king-dependent passed pawn bonus for `color`
+    pub fn evaluate(&self, pos: &Position, params: &EvalParams, color: Color) -> TaperedScore {
+        self.score[color as usize]
+            + passed_king_distance(pos, params, color, self.passed[color as usize])
+    }
+}
+
+fn pawn_structure(pos: &Position, params: &EvalParams, color: Color) -> (TaperedScore, Bitboard) {
+    let bb = pos.bb();
+    let own = bb[Position::bb_idx(color, piece_types::PAWN)];
+    let enemy = bb[Position::bb_idx(1 ^ color, piece_types::PAWN)];
+
+    let mut score = TaperedScore::default();
+    let mut passed = 0;
+    let mut pawns = own;
+    for square in pawns.iter() {
+        let file = (square & 7) as usize;
+        let rank = (square >> 3) as usize;
+        let relative = relative_rank(color, square);
+        let adjacent = own & BB_ADJACENT_FILES[file];
+
+        let doubled = BB_FORWARD_FILE[color as usize][square as usize] & own != 0;
+        if doubled {
+            score += params.doubled;
+        }
+
+        if adjacent == 0 {
+            score += params.isolated;
+        } else if adjacent & !BB_FORWARD_RANKS[color as usize][rank] == 0
+            && BB_PAWN_ATTACKS_2[color as usize][front_square(color, square) as usize] & enemy != 0
+        {
+            // no friendly pawn can defend the stop square, which is held by
+            // an enemy pawn
+            score += params.backward;
+        }
+
+        let phalanx = adjacent & BB_RANKS[rank] != 0;
+        let supported = BB_PAWN_ATTACKS_2[1 ^ color as usize][square as usize] & own != 0;
+        if phalanx || supported {
+            score += params.connected[relative];
+        }
+
+        // only the frontmost of doubled pawns counts as passed
+        if !doubled && BB_PASSED_PAWN_MASK[color as usize][square as usize] & enemy == 0 {
+            score += params.passed[relative];
+            passed.set(square);
+        }
+    }
+    (score, passed)
+}
+
+fn passed_king_distance(
+    pos: &Position,
+    params: &EvalParams,
+    color: Color,
+    mut passed: Bitboard,
+) -> TaperedScore {
+    let bb = pos.bb();
+    let own_king = bb[Position::bb_idx(color, piece_types::KING)].scan();
+    let enemy_king = bb[Position::bb_idx(1 ^ color, piece_types::KING)].scan();
+
+    let mut bonus = TaperedScore::default();
+    for square in passed.iter() {
+        let block = front_square(color, square);
+        let weight = params.passed_king_distance[relative_rank(color, square)];
+        bonus += weight
+            * (5 * enemy_king.distance(block) as Score - 2 * own_king.distance(block) as Score);
+    }
+    bonus
+}
+
+/// Cache of `PawnEntry`s indexed by the pawn-only Zobrist key
+pub struct PawnHashTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl Default for PawnHashTable {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl PawnHashTable {
+    /// Creates a table using roughly `size_mb` megabytes
+    pub fn new(size_mb: usize) -> PawnHashTable {
+        let wanted = (size_mb.max(1) << 20) / mem::size_of::<Option<PawnEntry>>();
+        let len = 1 << (63 - (wanted as u64).leading_zeros());
+        PawnHashTable {
+            entries: vec![None; len],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    /// Returns the cached entry for `pos`, evaluating and storing it on a miss
+    pub fn probe(&mut self, pos: &Position, params: &EvalParams) -> &PawnEntry {
+        let key = pos.pawn_hash();
+        let idx = (key as usize) & (self.entries.len() - 1);
+        let slot = &mut self.entries[idx];
+        match slot {
+            Some(entry) if entry.key == key => {}
+            _ => *slot = Some(PawnEntry::new(pos, params)),
+        }
+        slot.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::params::DEFAULT_PARAMS;
+    use crate::engine::Board;
+    use crate::interfaces::FenInterface;
+
+    #[test]
+    fn it_builds_pawn_masks() {
+        assert_eq!(BB_FILE_B | BB_FILE_D, BB_ADJACENT_FILES[2]);
+        assert_eq!(BB_FILE_B, BB_ADJACENT_FILES[0]);
+        assert_eq!(BB_RANK_7 | BB_RANK_8, BB_FORWARD_RANKS[0][5]);
+        assert_eq!(BB_RANK_1 | BB_RANK_2, BB_FORWARD_RANKS[1][2]);
+        assert_eq!(
+            BB_E5 | BB_E6 | BB_E7 | BB_E8,
+            BB_FORWARD_FILE[0][squares::E4 as usize]
+        );
+        assert_eq!(
+            BB_G6
+                | BB_H6
+                | BB_G5
+                | BB_H5
+                | BB_G4
+                | BB_H4
+                | BB_G3
+                | BB_H3
+                | BB_G2
+                | BB_H2
+                | BB_G1
+                | BB_H1,
+            BB_PASSED_PAWN_MASK[1][squares::H7 as usize]
+        );
+    }
+
+    #[test]
+    fn it_detects_pawn_structure() {
+        // doubled c-pawns, passers on c4, e4 and d5, d5 supported twice
+        let pos = Position::from_fen_str("4k3/p7/8/3P4/2P1P3/2P5/8/4K3 w - - 0 1").unwrap();
+        let entry = PawnEntry::new(&pos, &DEFAULT_PARAMS);
+        assert_eq!(BB_C4 | BB_E4 | BB_D5, entry.passed[colors::WHITE as usize]);
+        assert_eq!(BB_A7, entry.passed[colors::BLACK as usize]);
+        assert_eq!(
+            DOUBLED + PASSED[3] * 2 + CONNECTED[4] + PASSED[4],
+            entry.score[colors::WHITE as usize]
+        );
+        assert_eq!(ISOLATED + PASSED[1], entry.score[colors::BLACK as usize]);
+
+        // e3 can't advance without being taken by d5 and has no pawn behind
+        let pos = Position::from_fen_str("4k3/8/8/3p4/3P4/4P3/8/4K3 w - - 0 1").unwrap();
+        let entry = PawnEntry::new(&pos, &DEFAULT_PARAMS);
+        assert_eq!(0, entry.passed[colors::WHITE as usize]);
+        assert_eq!(BACKWARD + CONNECTED[3], entry.score[colors::WHITE as usize]);
+        assert_eq!(ISOLATED, entry.score[colors::BLACK as usize]);
+    }
+
+    #[test]
+    fn it_rewards_king_proximity_to_passers() {
+        let near = Position::from_fen_str("8/8/1k6/4P3/4K3/8/8/8 w - - 0 1").unwrap();
+        let far = Position::from_fen_str("8/8/4k3/4P3/4K3/8/8/8 w - - 0 1").unwrap();
+        let near =
+            PawnEntry::new(&near, &DEFAULT_PARAMS).evaluate(&near, &DEFAULT_PARAMS, colors::WHITE);
+        let far =
+            PawnEntry::new(&far, &DEFAULT_PARAMS).evaluate(&far, &DEFAULT_PARAMS, colors::WHITE);
+        assert!(near.eg > far.eg);
+    }
+
+    #[test]
+    fn it_caches_pawn_entries() {
+        let mut table = PawnHashTable::new(1);
+        let mut board =
+            Board::from_fen_str("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let key = board.current().pawn_hash();
+        assert_eq!(key, table.probe(board.current(), &DEFAULT_PARAMS).key);
+
+        // a knight move leaves the pawn key and the cached entry untouched
+        board.input_move(squares::B1, squares::C3, None).unwrap();
+        assert_eq!(key, board.current().pawn_hash());
+        assert_eq!(
+            PawnEntry::new(board.current(), &DEFAULT_PARAMS).score,
+            table.probe(board.current(), &DEFAULT_PARAMS).score
+        );
+    }
+}
@@ -5,18 +5,74 @@ use crate::common::BitTwiddling;
 // use crate::primitives::r#move::Move;
 // use crate::primitives::piece::{self, Piece, PiecePrimitives};
 // use crate::primitives::square::{self, Square, SquarePrimitives};
-use crate::engine::{ZobKey, ZobTables, Zobrist};
+use crate::engine::{Accumulator, MoveGenerator, Network, Score, ZobKey, ZobTables};
 
+use std::error;
 use std::fmt;
+use std::sync::Arc;
 
 pub type PositionStack = Vec<Position>;
 
+/// What makes a position invalid, see `Position::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    /// A square on which the bitboards don't match the `occupied` array
+    Inconsistent(Square),
+    /// A square set in the bitboards of both colors
+    OverlappingColors(Square),
+    /// The number of kings of a color, if not one
+    KingCount(Color, u32),
+    PawnOnBackRank(Square),
+    /// A castling right without its king or rook on the back rank
+    CastlingRights(Color, CastlingSide),
+    /// An en passant square no pawn can just have passed
+    EnPassant(Square),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color_name = |color: &Color| {
+            if *color == colors::WHITE {
+                "white"
+            } else {
+                "black"
+            }
+        };
+        match self {
+            PositionError::Inconsistent(sq) => {
+                write!(f, "bitboards and pieces differ on {}", sq.to_san_string())
+            }
+            PositionError::OverlappingColors(sq) => {
+                write!(f, "both colors on {}", sq.to_san_string())
+            }
+            PositionError::KingCount(color, kings) => {
+                write!(f, "{} {} kings instead of 1", kings, color_name(color))
+            }
+            PositionError::PawnOnBackRank(sq) => write!(f, "pawn on {}", sq.to_san_string()),
+            PositionError::CastlingRights(color, side) => write!(
+                f,
+                "{} may castle {} without king and rook at home",
+                color_name(color),
+                if *side == sides::KING_SIDE {
+                    "king side"
+                } else {
+                    "queen side"
+                }
+            ),
+            PositionError::EnPassant(sq) => {
+                write!(f, "no pawn can have just passed {}", sq.to_san_string())
+            }
+        }
+    }
+}
+
+impl error::Error for PositionError {}
+
 /// Represents a chess position
 ///
 /// Uses 16 bitboards ((2 colors + 6 pieces) * (unflipped + flipped)) plus an occupancy array
 ///
-/// 208 Byte
-#[derive(Clone, Copy)]
+/// 224 Byte
 pub struct Position {
     // 8 * 8 * 2 = 128 Byte
     // pub bb: [[Bitboard; 8]; 2],
@@ -38,6 +94,29 @@ pub struct Position {
     fullmoves: u32,
     // 8 Byte
     zobrist: ZobKey,
+    // 8 Byte
+    pawn_zobrist: ZobKey,
+    // 8 Byte, boxed to keep positions small without a network
+    accumulator: Option<Box<Accumulator>>,
+}
+
+impl Clone for Position {
+    fn clone(&self) -> Self {
+        Position {
+            accumulator: self.accumulator.clone(),
+            ..*self
+        }
+    }
+
+    /// Reuses the boxed accumulator, so `Board::make_move` doesn't allocate
+    fn clone_from(&mut self, source: &Self) {
+        let accumulator = self.accumulator.take();
+        *self = Position {
+            accumulator,
+            ..*source
+        };
+        self.accumulator.clone_from(&source.accumulator);
+    }
 }
 
 impl fmt::Debug for Position {
@@ -114,6 +193,8 @@ impl Position {
             halfmoves: 0,
             fullmoves: 1,
             zobrist: 0,
+            pawn_zobrist: 0,
+            accumulator: None,
         }
     }
 
@@ -153,41 +234,90 @@ impl Position {
         panic!();
     }
 
-    #[cfg(feature = "sanity_checks")]
-    fn sanity_check(&self) -> bool {
-        // disjoint color bbs?
-        if self.bb_own(color::WHITE) & self.bb_opponent(color::WHITE) > 0 {
-            self.panic_helper()
+    /// Checks the bitboards agree with each other and the `occupied` array,
+    /// each side has one king, there are no pawns on the back ranks, and
+    /// castling rights and the en passant square fit the pieces
+    pub fn validate(&self) -> Result<(), PositionError> {
+        let (white, black) = (self.bb_own(colors::WHITE), self.bb_own(colors::BLACK));
+        if white & black != 0 {
+            return Err(PositionError::OverlappingColors((white & black).scan()));
         }
 
-        for i in 2..8 {
-            // piece bbs not in color bbs?
-            if !(self.bb_own(color::WHITE) | self.bb_opponent(color::WHITE)) & self.bb[0][i] > 0 {
-                self.panic_helper()
-            }
-            // same bit set in multiple color bbs?
-            for j in i + 1..8 {
-                if self.bb[0][i] & self.bb[0][j] > 0 {
-                    self.panic_helper()
+        for sq in 0..64 {
+            let piece = self.occupied[sq as usize];
+            let bit = Bitboard::bit_at(sq);
+            for idx in 0..14 {
+                let expected = if idx < 2 {
+                    piece.code() != piece_types::EMPTY && usize::from(piece.color()) == idx
+                } else {
+                    piece.code() != piece_types::EMPTY
+                        && Self::bb_idx(piece.color(), piece.code()) == idx
+                };
+                if (self.bb[idx] & bit != 0) != expected {
+                    return Err(PositionError::Inconsistent(sq));
                 }
             }
         }
 
-        // wrong number of kings?
-        if self.bb_king(color::WHITE).count() != 1 || self.bb_king(color::BLACK).count() != 1 {
-            self.panic_helper()
+        for &color in [colors::WHITE, colors::BLACK].iter() {
+            let kings = self.bb_king(color).count();
+            if kings != 1 {
+                return Err(PositionError::KingCount(color, kings));
+            }
         }
 
-        // too many pawns?
-        if self.bb_pawns(color::WHITE).count() > 8 || self.bb_pawns(color::BLACK).count() > 8 {
-            self.panic_helper()
+        let pawns = self.bb_pawns(colors::WHITE) | self.bb_pawns(colors::BLACK);
+        if pawns & bitboards::BB_BACKRANKS != 0 {
+            return Err(PositionError::PawnOnBackRank(
+                (pawns & bitboards::BB_BACKRANKS).scan(),
+            ));
         }
 
-        true
+        for &color in [colors::WHITE, colors::BLACK].iter() {
+            let back_rank = 56 * Square::from(color);
+            let king = self.bb_king(color).scan();
+            for &side in [sides::KING_SIDE, sides::QUEEN_SIDE].iter() {
+                if !self.castling.get(color, side) {
+                    continue;
+                }
+                let rook = back_rank + self.castling.rook_file(color, side);
+                let rook_beside_king = if side == sides::KING_SIDE {
+                    rook > king
+                } else {
+                    rook < king
+                };
+                if king & !7 != back_rank
+                    || !rook_beside_king
+                    || self.get_piece_and_color(rook) != (piece_types::ROOK, color)
+                {
+                    return Err(PositionError::CastlingRights(color, side));
+                }
+            }
+        }
+
+        if let Some(sq) = self.en_passant {
+            // the pawn has to stand in front of the square, coming from
+            // behind it
+            let (behind, ahead, rank) = if self.to_move == colors::WHITE {
+                (sq + 8, sq.wrapping_sub(8), 5)
+            } else {
+                (sq.wrapping_sub(8), sq + 8, 2)
+            };
+            if sq / 8 != rank
+                || self.occupied[sq as usize] != piece_types::EMPTY
+                || self.occupied[behind as usize] != piece_types::EMPTY
+                || self.get_piece_and_color(ahead) != (piece_types::PAWN, 1 ^ self.to_move)
+            {
+                return Err(PositionError::EnPassant(sq));
+            }
+        }
+
+        Ok(())
     }
 
+    /// Index into `bb()` of the bitboard for `piece` of `color`
     #[inline]
-    fn bb_idx(color: Color, piece: Piece) -> usize {
+    pub fn bb_idx(color: Color, piece: Piece) -> usize {
         usize::from((color * 6) + piece)
     }
 
@@ -248,6 +378,71 @@ impl Position {
         !(self.bb_own(colors::WHITE) | self.bb_opponent(colors::WHITE))
     }
 
+    /// The legal moves of the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        // the network plays no part in legality, and unboxed copies are cheap
+        let mut base = self.clone();
+        base.accumulator = None;
+        let mut next = base.clone();
+        self.generate_moves()
+            .into_iter()
+            .filter(|&mov| {
+                next.clone_from(&base);
+                next.make_move(mov);
+                !next.is_in_check(self.to_move())
+            })
+            .collect()
+    }
+
+    /// Whether the king of `color` is attacked
+    #[inline]
+    pub fn is_in_check(&self, color: Color) -> bool {
+        let kingpos = self.bb_king(color).scan();
+        self.is_attacked(color, kingpos)
+    }
+
+    /// Whether `target` is attacked by the pieces not of `color`
+    pub fn is_attacked(&self, color: Color, target: Square) -> bool {
+        let occupied = self.bb_own(color) | self.bb_opponent(color);
+
+        debug_assert!(target < 64);
+
+        // by knights
+        if (bitboards::BB_KNIGHT_ATTACKS[target as usize] & self.bb_knights(1 ^ color)) > 0 {
+            return true;
+        }
+
+        // by pawns
+        if bitboards::BB_PAWN_ATTACKS[color as usize][target as usize] & self.bb_pawns(1 ^ color)
+            > 0
+        {
+            return true;
+        }
+
+        // by king?!?
+        if (bitboards::BB_KING_ATTACKS[target as usize] & self.bb_king(1 ^ color)) > 0 {
+            return true;
+        }
+
+        // by bishops or queens
+        if bitboards::bishop_attacks(target, occupied)
+            & (self.bb_bishops(1 ^ color) | self.bb_queens(1 ^ color))
+            > 0
+        {
+            return true;
+        }
+
+        // by rooks or queens
+        if (bitboards::rank_attacks(target, occupied) | bitboards::file_attacks(target, occupied))
+            & (self.bb_rooks(1 ^ color) | self.bb_queens(1 ^ color))
+            > 0
+        {
+            return true;
+        }
+
+        false
+    }
+
     #[inline]
     pub fn to_move(&self) -> Color {
         self.to_move
@@ -278,18 +473,43 @@ impl Position {
         self.fullmoves
     }
 
+    /// Zobrist key of this position, maintained incrementally
+    #[inline]
+    pub fn hash(&self) -> ZobKey {
+        self.zobrist
+    }
+
+    /// Zobrist key of the pawns only, for the pawn hash table
+    #[inline]
+    pub fn pawn_hash(&self) -> ZobKey {
+        self.pawn_zobrist
+    }
+
+    /// Adds or removes `piece` on `square` from the hash keys
+    #[inline]
+    fn toggle_hash(&mut self, piece: Piece, color: Color, square: Square) {
+        let key = ZobTables.piece(piece, color, square);
+        self.zobrist ^= key;
+        if piece == piece_types::PAWN {
+            self.pawn_zobrist ^= key;
+        }
+    }
+
     pub fn set_to_move(&mut self, to_move: Color) {
-        if self.to_move == colors::WHITE && to_move == colors::BLACK {
+        if self.to_move != to_move {
             self.zobrist ^= ZobTables.black_to_move;
         }
         self.to_move = to_move;
     }
 
     pub fn set_castling(&mut self, castling: Castling) {
+        self.zobrist ^=
+            ZobTables.castling_rights(self.castling) ^ ZobTables.castling_rights(castling);
         self.castling = castling;
     }
 
     pub fn set_en_passant(&mut self, ep_target: Option<Square>) {
+        self.zobrist ^= ZobTables.en_passant(self.en_passant) ^ ZobTables.en_passant(ep_target);
         self.en_passant = ep_target;
     }
 
@@ -309,6 +529,33 @@ impl Position {
         )
     }
 
+    /// Evaluates with `network` from now on, computing its hidden layer
+    /// from scratch. From then on, the piece primitives below update it
+    /// incrementally.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.accumulator = network.map(|net| Box::new(Accumulator::new(net, self)));
+    }
+
+    #[inline]
+    pub fn network(&self) -> Option<&Network> {
+        self.accumulator.as_ref().map(|acc| &**acc.network())
+    }
+
+    /// Hidden layer of the network, if one is set
+    #[inline]
+    pub fn accumulator(&self) -> Option<&Accumulator> {
+        self.accumulator.as_deref()
+    }
+
+    /// Evaluation by the network from the side to move's point of view, if
+    /// one is set
+    #[inline]
+    pub fn evaluate_network(&self) -> Option<Score> {
+        self.accumulator
+            .as_ref()
+            .map(|acc| acc.evaluate(self.to_move))
+    }
+
     #[inline]
     pub fn check_piece(&self, piece: Piece, color: Color, square: Square) -> bool {
         (piece, color) == self.get_piece_and_color(square)
@@ -321,6 +568,10 @@ impl Position {
         // self.bb[piece as usize].set(to);
 
         self.occupied[to as usize] = Piece::new(piece, color);
+        self.toggle_hash(piece, color, to);
+        if let Some(acc) = &mut self.accumulator {
+            acc.add(piece, color, to);
+        }
     }
 
     #[inline]
@@ -329,6 +580,10 @@ impl Position {
         self.bb[Self::bb_idx(color, piece)].clear(from);
 
         self.occupied[from as usize] = 0;
+        self.toggle_hash(piece, color, from);
+        if let Some(acc) = &mut self.accumulator {
+            acc.remove(piece, color, from);
+        }
     }
 
     #[inline]
@@ -343,6 +598,12 @@ impl Position {
 
         self.occupied[to as usize] = self.occupied[from as usize];
         self.occupied[from as usize] = 0;
+        self.toggle_hash(piece, color, from);
+        self.toggle_hash(piece, color, to);
+        if let Some(acc) = &mut self.accumulator {
+            acc.remove(piece, color, from);
+            acc.add(piece, color, to);
+        }
     }
 
     #[inline]
@@ -368,6 +629,14 @@ impl Position {
 
         self.occupied[to as usize] = self.occupied[from as usize];
         self.occupied[from as usize] = 0;
+        self.toggle_hash(piece, color, from);
+        self.toggle_hash(piece, color, to);
+        self.toggle_hash(captured_piece, captured_color, to);
+        if let Some(acc) = &mut self.accumulator {
+            acc.remove(piece, color, from);
+            acc.remove(captured_piece, captured_color, to);
+            acc.add(piece, color, to);
+        }
     }
 
     #[inline]
@@ -381,10 +650,27 @@ impl Position {
     ) {
         self.bb[old_color as usize].clear(square);
         self.bb[Self::bb_idx(old_color, old_piece)].clear(square);
+        self.toggle_hash(old_piece, old_color, square);
+        if let Some(acc) = &mut self.accumulator {
+            acc.remove(old_piece, old_color, square);
+        }
 
         self.set_piece(new_piece, new_color, square);
     }
 
+    /// Clears the castling right of `color` with the rook on `square`
+    fn clear_castling_rook(&mut self, color: Color, square: Square) {
+        if square >> 3 != 7 * Square::from(color) {
+            return;
+        }
+        for &side in [sides::KING_SIDE, sides::QUEEN_SIDE].iter() {
+            if self.castling.get(color, side) && self.castling.rook_file(color, side) == square & 7
+            {
+                self.castling.clear(color, side);
+            }
+        }
+    }
+
     pub fn make_move(&mut self, mov: Move) {
         let orig_square = mov.orig();
         let dest_square = mov.dest();
@@ -401,7 +687,10 @@ impl Position {
             }
         }
 
+        let orig_castling = self.castling;
+
         // reset en passant
+        self.zobrist ^= ZobTables.en_passant(self.en_passant);
         self.en_passant = None;
 
         // promotions change pieces
@@ -429,61 +718,40 @@ impl Position {
             let new_ep_square =
                 (i64::from(dest_square) - [8i64, -8i64][orig_color as usize]) as Square;
             self.en_passant = Some(new_ep_square);
+            self.zobrist ^= ZobTables.en_passant(self.en_passant);
             self.quiet_move_piece(orig_piece, orig_color, orig_square, dest_square);
-        } else if mov.is_king_castle() {
-            self.quiet_move_piece(orig_piece, orig_color, orig_square, dest_square);
-            self.quiet_move_piece(
-                piece_types::ROOK,
-                orig_color,
-                dest_square + 1,
-                dest_square - 1,
-            );
-        } else if mov.is_queen_castle() {
-            self.quiet_move_piece(orig_piece, orig_color, orig_square, dest_square);
-            self.quiet_move_piece(
-                piece_types::ROOK,
-                orig_color,
-                dest_square - 2,
-                dest_square + 1,
-            );
+        } else if mov.is_king_castle() || mov.is_queen_castle() {
+            // king and rook may start on any file, even on each other's
+            // destination
+            let (side, rook_dest) = if mov.is_king_castle() {
+                (sides::KING_SIDE, dest_square - 1)
+            } else {
+                (sides::QUEEN_SIDE, dest_square + 1)
+            };
+            let rook_square = (orig_square & !7) + self.castling.rook_file(orig_color, side);
+            self.remove_piece(orig_piece, orig_color, orig_square);
+            self.remove_piece(piece_types::ROOK, orig_color, rook_square);
+            self.set_piece(orig_piece, orig_color, dest_square);
+            self.set_piece(piece_types::ROOK, orig_color, rook_dest);
         } else {
             panic!("shouldn't come here")
         }
 
         // clear castling rights on king or rook move
-        // let orig_bb = BB_SQUARES[orig_square as usize];
-        let orig_bb = Bitboard::bit_at(orig_square);
         if piece_types::KING == orig_piece {
             self.castling.clear_color(self.to_move);
-        // self.castling[self.to_move as usize].clear_bit(0);
-        // self.castling[self.to_move as usize].clear_bit(1);
-        } else if orig_piece == piece_types::ROOK
-            && (orig_bb & bitboards::BB_ROOK_HOMES[self.to_move as usize] > 0)
-        {
-            if orig_bb & bitboards::BB_FILE_A > 0 {
-                self.castling.clear(self.to_move, sides::QUEEN_SIDE);
-            // self.castling[self.to_move as usize].clear_bit(1);
-            } else {
-                self.castling.clear(self.to_move, sides::KING_SIDE);
-                // self.castling[self.to_move as usize].clear_bit(0);
-            }
+        } else if orig_piece == piece_types::ROOK {
+            self.clear_castling_rook(self.to_move, orig_square);
         }
 
         // clear castling rights on rook capture at home square
-        // let dest_bb = BB_SQUARES[dest_square as usize];
-        let dest_bb = Bitboard::bit_at(dest_square);
-        if dest_piece == piece_types::ROOK
-            && (dest_bb & bitboards::BB_ROOK_HOMES[1 ^ self.to_move as usize] > 0)
-        {
-            if dest_bb & bitboards::BB_FILE_A > 0 {
-                self.castling.clear(1 ^ self.to_move, sides::QUEEN_SIDE);
-            // self.castling[(1 ^ self.to_move) as usize].clear_bit(1);
-            } else {
-                self.castling.clear(1 ^ self.to_move, sides::KING_SIDE);
-                // self.castling[(1 ^ self.to_move) as usize].clear_bit(0);
-            }
+        if dest_piece == piece_types::ROOK {
+            self.clear_castling_rook(1 ^ self.to_move, dest_square);
         }
 
+        self.zobrist ^=
+            ZobTables.castling_rights(orig_castling) ^ ZobTables.castling_rights(self.castling);
+
         // Full move clock needs to be incremented after black moves
         // piece_types::WHITE == 0 and piece_types::BLACK == 1, so we use that to save an if :-)
         self.fullmoves += u32::from(self.to_move);
@@ -497,79 +765,16 @@ impl Position {
 
         // flip to move
         self.to_move ^= 1;
+        self.zobrist ^= ZobTables.black_to_move;
 
         #[cfg(feature = "sanity_checks")]
-        self.sanity_check();
-    }
-
-    pub fn input_move(
-        &mut self,
-        orig: Square,
-        dest: Square,
-        promote_to: Option<Piece>,
-    ) -> Result<Move, &'static str> {
-        let (mut is_capture, mut is_promotion, mut is_special_0, mut is_special_1) =
-            (false, false, false, false);
-        let (piece, _color) = self.get_piece_and_color(orig);
-        let (dest_piece, _dest_color) = self.get_piece_and_color(dest);
-        if 0 == piece {
-            return Err("No piece at given square");
-        };
-
-        // let (cap_piece, _) = self.get_piece_and_color(dest);
-        // is_capture = 0 != cap_piece;
-
-        // set special flags for double pawn push
-        if piece == piece_types::PAWN && ((orig + 16 == dest) || (dest + 16 == orig)) {
-            is_special_0 = true;
-            is_special_1 = false;
-        }
-
-        // set flags for en passant capture
-        if piece == piece_types::PAWN && Some(dest) == self.en_passant {
-            is_capture = true;
-            is_special_0 = true;
-            is_special_1 = false;
-        }
-
-        // set flags for capture
-        if dest_piece >= 2 {
-            is_capture = true;
-        }
-
-        // set flags for promotion
-        if let Some(promoted_piece) = promote_to {
-            is_special_0 = false;
-            is_special_1 = false;
-            is_promotion = true;
-            if (piece_types::BISHOP == promoted_piece) || (piece_types::QUEEN == promoted_piece) {
-                is_special_0 = true;
-            }
-            if (piece_types::ROOK == promoted_piece) || (piece_types::QUEEN == promoted_piece) {
-                is_special_1 = true;
-            }
-        }
-
-        // set flags for castling
-        if piece == piece_types::KING {
-            if 2 == dest.wrapping_sub(orig) {
-                // King castle
-                is_special_0 = false;
-                is_special_1 = true;
-            } else if 2 == orig.wrapping_sub(dest) {
-                // Queen castle
-                is_special_0 = true;
-                is_special_1 = true;
+        {
+            if let Err(e) = self.validate() {
+                eprintln!("invalid position: {}", e);
+                eprintln!("offending move: {:?}", mov);
+                self.panic_helper();
             }
         }
-
-        let mov = Move::new(
-            orig,
-            dest,
-            Move::make_flags(is_capture, is_promotion, is_special_0, is_special_1),
-        );
-        self.make_move(mov);
-        Ok(mov)
     }
 }
 
@@ -577,6 +782,7 @@ impl Position {
 mod tests {
     use super::*;
     use crate::engine::bitboards as bb;
+    use crate::interfaces::FenInterface;
 
     #[test]
     fn it_sets_pieces() {
@@ -672,6 +878,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_validates_positions() {
+        let startpos =
+            Position::from_fen_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(Ok(()), startpos.validate());
+        assert_eq!(
+            Err(PositionError::KingCount(colors::WHITE, 0)),
+            Position::new().validate()
+        );
+
+        let mut pos = startpos.clone();
+        pos.occupied[squares::E4 as usize] = Piece::new(piece_types::PAWN, colors::WHITE);
+        assert_eq!(
+            Err(PositionError::Inconsistent(squares::E4)),
+            pos.validate()
+        );
+
+        let mut pos = startpos.clone();
+        pos.bb[colors::BLACK as usize] |= Bitboard::bit_at(squares::E1);
+        assert_eq!(
+            Err(PositionError::OverlappingColors(squares::E1)),
+            pos.validate()
+        );
+
+        let mut pos = Position::from_fen_str("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        pos.set_piece(piece_types::PAWN, colors::BLACK, squares::H1);
+        assert_eq!(
+            Err(PositionError::PawnOnBackRank(squares::H1)),
+            pos.validate()
+        );
+
+        let mut pos = startpos.clone();
+        pos.remove_piece(piece_types::ROOK, colors::WHITE, squares::H1);
+        assert_eq!(
+            Err(PositionError::CastlingRights(
+                colors::WHITE,
+                sides::KING_SIDE
+            )),
+            pos.validate()
+        );
+
+        let mut pos = startpos.clone();
+        pos.set_en_passant(Some(squares::E3));
+        assert_eq!(Err(PositionError::EnPassant(squares::E3)), pos.validate());
+        pos.make_move(Move::new(squares::E2, squares::E4, flags::MOV_DPP));
+        assert_eq!(Ok(()), pos.validate());
+    }
+
     // #[test]
     // fn it_makes_moves() {
     //     if let Ok(mut position) = Position::from_fen(String::from(
@@ -0,0 +1,147 @@
+pub type Score = i32;
+
+/// Maximum search depth in plies
+pub const MAX_PLY: usize = 128;
+
+pub mod scores {
+    use super::*;
+
+    pub const DRAW: Score = 0;
+    pub const INFINITE: Score = 32_001;
+
+    /// Score for delivering mate on the board, mates found deeper in the
+    /// tree score `MATE - ply`
+    pub const MATE: Score = 32_000;
+
+    /// Every score beyond this bound is a mate score
+    pub const MATE_BOUND: Score = MATE - MAX_PLY as Score;
+
+    /// Score for a tablebase win, wins found deeper in the tree score
+    /// `TB_WIN - ply`
+    pub const TB_WIN: Score = MATE_BOUND - MAX_PLY as Score;
+
+    /// Every score beyond this bound is a tablebase or mate score, static
+    /// evaluations stay below it
+    pub const TB_BOUND: Score = TB_WIN - MAX_PLY as Score;
+}
+
+/// Score for the side to move delivering mate `ply` plies from the root
+#[inline]
+pub fn mate_in(ply: usize) -> Score {
+    scores::MATE - ply as Score
+}
+
+/// Score for the side to move getting mated `ply` plies from the root
+#[inline]
+pub fn mated_in(ply: usize) -> Score {
+    -scores::MATE + ply as Score
+}
+
+pub trait ScorePrimitives: Copy {
+    fn is_mate(self) -> bool;
+    fn to_tt(self, ply: usize) -> Self;
+    fn to_search(self, ply: usize) -> Self;
+    fn mate_moves(self) -> Option<i32>;
+    fn to_uci_string(self) -> String;
+}
+
+impl ScorePrimitives for Score {
+    #[inline]
+    fn is_mate(self) -> bool {
+        self.abs() >= scores::MATE_BOUND
+    }
+
+    /// Converts a mate or tablebase score from distance-to-root into
+    /// distance-to-node, so it stays valid when the entry is hit at a
+    /// different ply
+    #[inline]
+    fn to_tt(self, ply: usize) -> Self {
+        if self >= scores::TB_BOUND {
+            self + ply as Score
+        } else if self <= -scores::TB_BOUND {
+            self - ply as Score
+        } else {
+            self
+        }
+    }
+
+    /// Inverse of `to_tt`
+    #[inline]
+    fn to_search(self, ply: usize) -> Self {
+        if self >= scores::TB_BOUND {
+            self - ply as Score
+        } else if self <= -scores::TB_BOUND {
+            self + ply as Score
+        } else {
+            self
+        }
+    }
+
+    /// Full moves until mate, negative if the side to move gets mated
+    fn mate_moves(self) -> Option<i32> {
+        if !self.is_mate() {
+            None
+        } else if self > 0 {
+            Some((scores::MATE - self + 1) / 2)
+        } else {
+            Some(-(scores::MATE + self) / 2)
+        }
+    }
+
+    fn to_uci_string(self) -> String {
+        match self.mate_moves() {
+            Some(moves) => format!("mate {}", moves),
+            None => format!("cp {}", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_mate_scores() {
+        assert_eq!(Some(1), mate_in(1).mate_moves());
+        assert_eq!(Some(2), mate_in(3).mate_moves());
+        assert_eq!(Some(0), mated_in(0).mate_moves());
+        assert_eq!(Some(-1), mated_in(2).mate_moves());
+        assert_eq!(None, 150.mate_moves());
+        assert_eq!("mate 3", mate_in(5).to_uci_string());
+        assert_eq!("mate -2", mated_in(4).to_uci_string());
+        assert_eq!("cp -35", (-35).to_uci_string());
+    }
+
+    #[test]
+    fn it_adjusts_mate_scores_for_ply() {
+        // mate in 5 plies from the root, found at ply 3, is a mate in 2 from there
+        let score = mate_in(5);
+        assert_eq!(mate_in(2), score.to_tt(3));
+        assert_eq!(score, score.to_tt(3).to_search(3));
+        // same entry read back at ply 7
+        assert_eq!(mate_in(9), score.to_tt(3).to_search(7));
+
+        let score = mated_in(6);
+        assert_eq!(mated_in(2), score.to_tt(4));
+        assert_eq!(score, score.to_tt(4).to_search(4));
+
+        assert_eq!(42, 42.to_tt(10));
+        assert_eq!(-42, (-42).to_search(10));
+    }
+
+    #[test]
+    fn it_adjusts_tablebase_scores_for_ply() {
+        // a tablebase win reached at ply 4, stored there and hit at ply 9
+        let score = scores::TB_WIN - 4;
+        assert_eq!(scores::TB_WIN, score.to_tt(4));
+        assert_eq!(scores::TB_WIN - 9, score.to_tt(4).to_search(9));
+        assert!(!score.is_mate());
+
+        let score = -scores::TB_WIN + 6;
+        assert_eq!(-scores::TB_WIN, score.to_tt(6));
+        assert_eq!(score, score.to_tt(6).to_search(6));
+
+        let eval = scores::TB_BOUND - 1;
+        assert_eq!(eval, eval.to_tt(10).to_search(20));
+    }
+}
@@ -1,12 +1,19 @@
+use crate::engine::eval::{Evaluation, PIECE_VALUES};
 use crate::engine::Board;
 use crate::engine::MoveGenerator;
+use crate::engine::{mate_in, mated_in, scores, Score, ScorePrimitives, MAX_PLY};
+use crate::engine::{Bound, EvalParams, PawnHashTable, TranspositionTable};
+use crate::engine::{Syzygy, Tablebases, Wdl};
+use crate::primitives::*;
 
 use quanta::Clock;
 use rayon::prelude::*;
 
+use std::cmp::Reverse;
 use std::fmt;
 use std::iter::Sum;
 use std::ops::{Add, AddAssign};
+use std::sync::Arc;
 // use std::sync::mpsc::{self, channel};
 // use std::sync::{Arc, Mutex};
 // use std::thread;
@@ -323,6 +330,458 @@ impl Search for Board {
     }
 }
 
+/// Depth used when `go` comes without any limit, as the search can't be
+/// interrupted by a `stop` command yet
+pub const DEFAULT_DEPTH: u32 = 8;
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// Time budget in milliseconds
+    pub movetime: Option<u64>,
+    /// Stop as soon as a mate in this many moves is proven
+    pub mate: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchInfo {
+    pub depth: u32,
+    pub score: Score,
+    pub nodes: u64,
+    pub elapsed: u64,
+    pub tb_hits: u64,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    #[inline]
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().copied()
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = self.elapsed / 1_000_000;
+        write!(
+            f,
+            "depth {} score {} nodes {} nps {} tbhits {} time {} pv",
+            self.depth,
+            self.score.to_uci_string(),
+            self.nodes,
+            self.nodes * 1_000_000_000 / self.elapsed.max(1),
+            self.tb_hits,
+            millis
+        )?;
+        for mov in &self.pv {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterative deepening alpha-beta searcher
+pub struct Searcher {
+    tt: TranspositionTable,
+    params: EvalParams,
+    pawns: PawnHashTable,
+    limits: SearchLimits,
+    clock: Clock,
+    start: u64,
+    nodes: u64,
+    root_depth: u32,
+    stopped: bool,
+    pv: Vec<Vec<Move>>,
+    tablebases: Option<Arc<Tablebases>>,
+    syzygy: Option<Arc<Syzygy>>,
+    tb_hits: u64,
+    /// Root moves keeping the tablebase result, all moves if empty
+    root_moves: Vec<Move>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl Searcher {
+    /// Creates a searcher with a transposition table of `tt_size_mb` megabytes
+    pub fn new(tt_size_mb: usize) -> Searcher {
+        Searcher {
+            tt: TranspositionTable::new(tt_size_mb),
+            params: EvalParams::default(),
+            pawns: PawnHashTable::default(),
+            limits: SearchLimits::default(),
+            clock: Clock::new(),
+            start: 0,
+            nodes: 0,
+            root_depth: 0,
+            stopped: false,
+            pv: (0..=MAX_PLY).map(|_| Vec::with_capacity(MAX_PLY)).collect(),
+            tablebases: None,
+            syzygy: None,
+            tb_hits: 0,
+            root_moves: Vec::new(),
+        }
+    }
+
+    /// Forgets everything learned in previous searches
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.pawns.clear();
+    }
+
+    #[inline]
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    #[inline]
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    /// Evaluates with `params` from now on
+    pub fn set_params(&mut self, params: EvalParams) {
+        self.params = params;
+        // cached pawn scores were computed with the old parameters
+        self.pawns.clear();
+    }
+
+    /// Probes `tablebases` for positions below the root from now on
+    pub fn set_tablebases(&mut self, tablebases: Option<Arc<Tablebases>>) {
+        self.tablebases = tablebases;
+        // stored scores may disagree with the tables
+        self.tt.clear();
+    }
+
+    /// Probes the Syzygy tables in `syzygy` from now on
+    pub fn set_syzygy(&mut self, syzygy: Option<Arc<Syzygy>>) {
+        self.syzygy = syzygy;
+        self.tt.clear();
+    }
+
+    /// WDL score of a position right after a capture or pawn move, if it is
+    /// in the Syzygy tables
+    fn probe_syzygy(&mut self, board: &mut Board, ply: usize) -> Option<Score> {
+        if board.current().halfmoves() != 0 {
+            return None;
+        }
+        let wdl = self.syzygy.as_ref()?.probe_wdl(board)?;
+        self.tb_hits += 1;
+        Some(match wdl {
+            Wdl::Win => scores::TB_WIN - ply as Score,
+            Wdl::CursedWin => scores::DRAW + 1,
+            Wdl::Draw => scores::DRAW,
+            Wdl::BlessedLoss => scores::DRAW - 1,
+            Wdl::Loss => -scores::TB_WIN + ply as Score,
+        })
+    }
+
+    /// Static evaluation, using the board's network if one is set
+    #[inline]
+    fn evaluate(&mut self, board: &Board) -> Score {
+        match board.evaluate_network() {
+            Some(score) => score,
+            None => board
+                .current()
+                .evaluate_cached(&self.params, &mut self.pawns),
+        }
+    }
+
+    /// Searches the current position of `board` until one of `limits` is
+    /// hit, calling `report` after each completed iteration.
+    pub fn search<F>(
+        &mut self,
+        board: &mut Board,
+        limits: SearchLimits,
+        mut report: F,
+    ) -> SearchInfo
+    where
+        F: FnMut(&SearchInfo),
+    {
+        self.limits = limits;
+        self.start = self.clock.now();
+        self.nodes = 0;
+        self.tb_hits = 0;
+        self.stopped = false;
+
+        // in the tables, only moves keeping the result are searched
+        self.root_moves.clear();
+        if let Some(syzygy) = self.syzygy.clone() {
+            if let Some((_, moves)) = syzygy.root_moves(board) {
+                self.tb_hits += 1;
+                self.root_moves = moves;
+            }
+        }
+
+        let mut max_depth = limits.depth.unwrap_or(MAX_PLY as u32 - 1);
+        if let Some(mate) = limits.mate {
+            // a mate in n moves takes 2n - 1 plies
+            max_depth = max_depth.min((2 * mate).saturating_sub(1).max(1));
+        } else if limits.depth.is_none() && limits.nodes.is_none() && limits.movetime.is_none() {
+            max_depth = DEFAULT_DEPTH;
+        }
+
+        let mut best = SearchInfo::default();
+        for depth in 1..=max_depth {
+            self.root_depth = depth;
+            let score = self.negamax(board, depth as i32, 0, -scores::INFINITE, scores::INFINITE);
+            if self.stopped {
+                break;
+            }
+
+            best = SearchInfo {
+                depth,
+                score,
+                nodes: self.nodes,
+                elapsed: self.clock.now() - self.start,
+                tb_hits: self.tb_hits,
+                pv: self.pv[0].clone(),
+            };
+            report(&best);
+
+            if let (Some(mate), Some(moves)) = (limits.mate, score.mate_moves()) {
+                if moves > 0 && moves <= mate as i32 {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    fn check_limits(&mut self) {
+        // always finish the first iteration so there is a move to play
+        if self.root_depth <= 1 {
+            return;
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                self.stopped = true;
+            }
+        }
+        if let Some(movetime) = self.limits.movetime {
+            if self.clock.now() - self.start >= movetime * 1_000_000 {
+                self.stopped = true;
+            }
+        }
+    }
+
+    fn update_pv(&mut self, ply: usize, mov: Move) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(mov);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+
+    /// Orders the hash move first, then captures by MVV-LVA, then quiets
+    fn order_moves(board: &Board, moves: &mut [Move], hash_move: Option<Move>) {
+        let occupied = board.current().occupied();
+        moves.sort_by_key(|mov| {
+            if Some(*mov) == hash_move {
+                return Reverse(Score::MAX);
+            }
+            let mut key = 0;
+            if mov.is_capture() {
+                let victim = if mov.is_capture_en_passant() {
+                    piece_types::PAWN
+                } else {
+                    occupied[mov.dest() as usize].code()
+                };
+                let attacker = occupied[mov.orig() as usize].code();
+                key += 10_000 + 10 * PIECE_VALUES[victim as usize]
+                    - PIECE_VALUES[attacker as usize] / 10;
+            }
+            if mov.is_promotion() {
+                key += PIECE_VALUES[mov.prom_piece_code() as usize];
+            }
+            Reverse(key)
+        });
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: Score,
+        mut beta: Score,
+    ) -> Score {
+        self.pv[ply].clear();
+        self.nodes += 1;
+        if self.nodes & 2047 == 0 {
+            self.check_limits();
+        }
+        if self.stopped {
+            return scores::DRAW;
+        }
+
+        if ply > 0 {
+            if board.is_draw() {
+                return scores::DRAW;
+            }
+
+            if let Some(dtm) = self
+                .tablebases
+                .as_ref()
+                .and_then(|tb| tb.probe(board.current()))
+            {
+                return dtm.to_score(ply);
+            }
+            if let Some(score) = self.probe_syzygy(board, ply) {
+                return score;
+            }
+
+            // mate distance pruning
+            alpha = alpha.max(mated_in(ply));
+            beta = beta.min(mate_in(ply + 1));
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(board);
+        }
+
+        let to_move = board.current().to_move();
+        let in_check = board.is_in_check(to_move);
+        // check extension, this also keeps mates at the horizon visible
+        if in_check {
+            depth += 1;
+        }
+
+        if depth <= 0 {
+            return self.quiesce(board, ply, alpha, beta);
+        }
+
+        let key = board.current().hash();
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(key, ply) {
+            hash_move = entry.mov;
+            if ply > 0 && entry.depth >= depth as u32 {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut moves = board.generate_moves();
+        Self::order_moves(board, &mut moves, hash_move);
+
+        let orig_alpha = alpha;
+        let mut best_score = -scores::INFINITE;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+
+        for mov in moves {
+            if ply == 0 && !self.root_moves.is_empty() && !self.root_moves.contains(&mov) {
+                continue;
+            }
+            board.make_move(mov);
+            if board.is_in_check(to_move) {
+                board.unmake_move();
+                continue;
+            }
+            legal_moves += 1;
+            let score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha);
+            board.unmake_move();
+
+            if self.stopped {
+                return scores::DRAW;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mov);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mov);
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if legal_moves == 0 {
+            return if in_check {
+                mated_in(ply)
+            } else {
+                scores::DRAW
+            };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > orig_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(key, best_move, best_score, depth as u32, bound, ply);
+
+        best_score
+    }
+
+    fn quiesce(&mut self, board: &mut Board, ply: usize, mut alpha: Score, beta: Score) -> Score {
+        self.pv[ply].clear();
+        self.nodes += 1;
+        if self.nodes & 2047 == 0 {
+            self.check_limits();
+        }
+        if self.stopped {
+            return scores::DRAW;
+        }
+
+        let stand_pat = self.evaluate(board);
+        if ply >= MAX_PLY - 1 || stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let to_move = board.current().to_move();
+        let mut moves: Vec<Move> = board
+            .generate_moves()
+            .into_iter()
+            .filter(|mov| mov.is_capture() || mov.is_promotion())
+            .collect();
+        Self::order_moves(board, &mut moves, None);
+
+        let mut best_score = stand_pat;
+        for mov in moves {
+            board.make_move(mov);
+            if board.is_in_check(to_move) {
+                board.unmake_move();
+                continue;
+            }
+            let score = -self.quiesce(board, ply + 1, -beta, -alpha);
+            board.unmake_move();
+
+            if self.stopped {
+                return scores::DRAW;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best_score
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -362,4 +821,57 @@ mod tests {
             }
         }
     }
+
+    fn search_mate(fen: &str, mate: u32) -> SearchInfo {
+        let mut board = Board::from_fen_str(fen).unwrap();
+        let limits = SearchLimits {
+            mate: Some(mate),
+            ..Default::default()
+        };
+        Searcher::new(1).search(&mut board, limits, |_| {})
+    }
+
+    #[test]
+    fn it_finds_mate_in_one() {
+        let info = search_mate("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1);
+        assert_eq!(Some(1), info.score.mate_moves());
+        assert_eq!("a1a8", info.best_move().unwrap().to_string());
+    }
+
+    #[test]
+    fn it_finds_mate_in_two() {
+        let info = search_mate(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            2,
+        );
+        assert_eq!(Some(2), info.score.mate_moves());
+        assert!(info.depth <= 3);
+        assert_eq!("d5f6", info.best_move().unwrap().to_string());
+    }
+
+    #[test]
+    fn it_reports_getting_mated() {
+        let mut board = Board::from_fen_str("6k1/5ppp/8/8/8/8/r7/r5K1 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let info = Searcher::new(1).search(&mut board, limits, |_| {});
+        assert_eq!(Some(0), info.score.mate_moves());
+        assert!(info.best_move().is_none());
+    }
+
+    #[test]
+    fn it_keeps_mate_scores_consistent_across_iterations() {
+        let mut board = Board::from_fen_str("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(1);
+        let mut scores = Vec::new();
+        searcher.search(&mut board, limits, |info| scores.push(info.score));
+        assert!(scores.iter().all(|score| *score == mate_in(1)));
+        assert_eq!("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", board.to_fen_string());
+    }
 }
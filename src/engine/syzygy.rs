@@ -0,0 +1,1656 @@
+//! Probing of Syzygy tablebases
+//!
+//! WDL tables (`.rtbw`) give the result of a position under the 50-move
+//! rule, DTZ tables (`.rtbz`) the distance in plies to the next capture or
+//! pawn move, the moves that reset the 50-move counter, on the way to that
+//! result. The search uses WDL values after such moves, the root uses DTZ
+//! values to play the moves that keep making progress.
+//!
+//! The file format and indexing scheme follow the reference probing code
+//! by Ronald de Man. Tables are read lazily, the first time a position
+//! with their material is probed.
+
+use crate::engine::{BitboardPrimitives, Board, Material, MoveGenerator, Position, TablebaseError};
+use crate::primitives::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Most pieces, kings included, the format handles
+pub const TB_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+const WDL_SUFFIX: &str = ".rtbw";
+const DTZ_SUFFIX: &str = ".rtbz";
+
+/// Separator of directories in `SyzygyPath`
+#[cfg(windows)]
+const PATH_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+const PATH_SEPARATOR: char = ':';
+
+mod tb_flags {
+    pub const STM: u8 = 1;
+    pub const MAPPED: u8 = 2;
+    pub const WIN_PLIES: u8 = 4;
+    pub const LOSS_PLIES: u8 = 8;
+    pub const WIDE: u8 = 16;
+    pub const SINGLE_VALUE: u8 = 128;
+}
+
+/// Result of a position under the 50-move rule, from the side to move's
+/// point of view. Cursed wins and blessed losses are drawn by the rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_i32(value: i32) -> Wdl {
+        match value {
+            v if v <= -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    /// DTZ of the position just before a zeroing move reaching this result
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+}
+
+/// Outcome of a table lookup, besides failing
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProbeState {
+    Ok,
+    /// The DTZ table only stores the other side to move
+    ChangeStm,
+    /// The best move resets the 50-move counter, the DTZ table is not to be
+    /// trusted
+    ZeroingBestMove,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+/// Square mappings and binomial coefficients of the indexing scheme
+struct Maps {
+    pawns: [u32; 64],
+    b1h1h7: [u32; 64],
+    a1d1d4: [u32; 64],
+    kk: [[u32; 64]; 10],
+    binomial: [[u64; 64]; 7],
+    lead_pawn_idx: [[u64; 64]; 7],
+    lead_pawns_size: [[u64; 4]; 7],
+}
+
+/// Rank minus file, negative below the a1-h8 diagonal
+#[inline]
+fn off_diagonal(sq: Square) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+impl Maps {
+    fn new() -> Maps {
+        let mut maps = Maps {
+            pawns: [0; 64],
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 7],
+            lead_pawn_idx: [[0; 64]; 7],
+            lead_pawns_size: [[0; 4]; 7],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_diagonal(sq) < 0 {
+                maps.b1h1h7[sq as usize] = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle, diagonal squares last
+        let mut diagonal = Vec::new();
+        code = 0;
+        for &sq in [0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19, 24, 25, 26, 27].iter() {
+            if off_diagonal(sq) < 0 {
+                maps.a1d1d4[sq as usize] = code;
+                code += 1;
+            } else if off_diagonal(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            maps.a1d1d4[sq as usize] = code;
+            code += 1;
+        }
+
+        // the 462 placements of two kings with the first in the triangle,
+        // those with both on the diagonal last
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..28u32 {
+                if maps.a1d1d4[s1 as usize] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64u32 {
+                    if s1.distance(s2) <= 1 || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        maps.kk[idx as usize][s2 as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            maps.kk[idx as usize][s2 as usize] = code;
+            code += 1;
+        }
+
+        maps.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..7.min(n + 1) {
+                maps.binomial[k][n] = if k > 0 {
+                    maps.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n { maps.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // pawns on a2 to h7, the further from the center and the lower the
+        // rank, the higher; the highest pawn leads
+        let mut available: i32 = 47;
+        for lead_pawns in 1..7 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        maps.pawns[sq] = available as u32;
+                        maps.pawns[sq ^ 7] = (available - 1) as u32;
+                        available -= 2;
+                    }
+                    maps.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += maps.binomial[lead_pawns - 1][maps.pawns[sq] as usize];
+                }
+                maps.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        maps
+    }
+}
+
+lazy_static! {
+    static ref MAPS: Maps = Maps::new();
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> usize {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+        None => 0,
+    }
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+        None => 0,
+    }
+}
+
+#[inline]
+fn read_u32_be(data: &[u8], offset: usize) -> u64 {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => u64::from(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => 0,
+    }
+}
+
+/// One compressed list of values, for a side to move and a leading file
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: u64,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    /// Offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    /// Lowest code of each length, left-aligned
+    base64: Vec<u64>,
+    /// Number of values a symbol expands to, minus one
+    symlen: Vec<u8>,
+    pieces: [u8; TB_PIECES],
+    group_idx: [u64; TB_PIECES + 1],
+    group_len: [usize; TB_PIECES + 1],
+    /// Offsets of the DTZ value maps for wins, losses, cursed wins and
+    /// blessed losses
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    /// Left and right child of a symbol in the pairing tree
+    #[inline]
+    fn children(&self, data: &[u8], sym: usize) -> (usize, usize) {
+        let offset = self.btree + 3 * sym;
+        match data.get(offset..offset + 3) {
+            Some(lr) => (
+                (usize::from(lr[1] & 0xF) << 8) | usize::from(lr[0]),
+                (usize::from(lr[2]) << 4) | usize::from(lr[1] >> 4),
+            ),
+            None => (0, 0xFFF),
+        }
+    }
+
+    fn set_symlen(&mut self, data: &[u8], sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let (left, right) = self.children(data, sym);
+        if right == 0xFFF || left >= visited.len() || right >= visited.len() {
+            return 0;
+        }
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(data, left, visited);
+        }
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(data, right, visited);
+        }
+        self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1)
+    }
+
+    /// Reads the block and symbol sizes at `offset`, returns the offset
+    /// following them
+    fn set_sizes(&mut self, data: &[u8], mut offset: usize) -> Option<usize> {
+        self.flags = *data.get(offset)?;
+        offset += 1;
+        if self.flags & tb_flags::SINGLE_VALUE != 0 {
+            self.min_sym_len = *data.get(offset)?;
+            return Some(offset + 1);
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0)?;
+        let tb_size = self.group_idx[groups];
+        let header = data.get(offset..offset + 9)?;
+        if header[0] >= 32 || header[1] >= 32 {
+            return None;
+        }
+        self.block_size = 1 << header[0];
+        self.span = 1 << header[1];
+        self.sparse_index_size = tb_size.div_ceil(self.span) as usize;
+        self.num_blocks = read_u32(data, offset + 3);
+        self.block_length_size = self.num_blocks + usize::from(header[2]);
+        let max_sym_len = header[7];
+        self.min_sym_len = header[8];
+        if max_sym_len < self.min_sym_len || self.min_sym_len == 0 || max_sym_len > 64 {
+            return None;
+        }
+        offset += 9;
+
+        // canonical codes get numerically lower with their length, so each
+        // length's lowest code follows from the lowest symbols
+        self.lowest_sym = offset;
+        let lengths = usize::from(max_sym_len - self.min_sym_len) + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = (self.base64[i + 1]
+                .wrapping_add(read_u16(data, offset + 2 * i) as u64)
+                .wrapping_sub(read_u16(data, offset + 2 * i + 2) as u64))
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl((64 - i - usize::from(self.min_sym_len)) as u32)
+                .unwrap_or(0);
+        }
+        offset += 2 * lengths;
+
+        let symbols = read_u16(data, offset);
+        offset += 2;
+        self.btree = offset;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(data, sym, &mut visited);
+            }
+        }
+        Some(offset + 3 * symbols + (symbols & 1))
+    }
+
+    /// Value at `idx`
+    fn decompress(&self, data: &[u8], idx: u64) -> i32 {
+        if self.flags & tb_flags::SINGLE_VALUE != 0 {
+            return i32::from(self.min_sym_len);
+        }
+
+        // the sparse index points into the block list every `span` values
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32(data, entry);
+        let mut offset = read_u16(data, entry + 4) as i64;
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| read_u16(data, self.block_length + 2 * block) as i64;
+        while offset < 0 {
+            if block == 0 {
+                return 0;
+            }
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+            if block >= self.block_length_size {
+                return 0;
+            }
+        }
+
+        // walk the symbols of the block up to the one covering the offset
+        let mut ptr = self.data + block * self.block_size;
+        let mut buf64 = (read_u32_be(data, ptr) << 32) | read_u32_be(data, ptr + 4);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buf64 < self.base64[len] {
+                len += 1;
+            }
+            let bits = len + usize::from(self.min_sym_len);
+            sym = (buf64.wrapping_sub(self.base64[len]) >> (64 - bits)) as usize;
+            sym = (sym + read_u16(data, self.lowest_sym + 2 * len)) & 0xFFFF;
+            let expands = i64::from(*self.symlen.get(sym).unwrap_or(&0)) + 1;
+            if offset < expands {
+                break;
+            }
+            offset -= expands;
+            buf64 = buf64.checked_shl(bits as u32).unwrap_or(0);
+            buf64_size -= bits as i32;
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= read_u32_be(data, ptr) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // then descend the pairs the symbol stands for
+        while sym < self.symlen.len() && self.symlen[sym] != 0 {
+            let (left, right) = self.children(data, sym);
+            let left_expands = i64::from(*self.symlen.get(left).unwrap_or(&0)) + 1;
+            if offset < left_expands {
+                sym = left;
+            } else {
+                offset -= left_expands;
+                sym = right;
+            }
+        }
+        self.children(data, sym).0 as i32
+    }
+}
+
+/// A table file read into memory
+struct EncodedTable {
+    kind: Kind,
+    /// White's pieces are the first side in the file name
+    material: Material,
+    symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    piece_count: usize,
+    /// Pawns of the leading color, and of the other one
+    pawn_count: [usize; 2],
+    /// By side to move, then by file of the leading pawn
+    items: Vec<Vec<PairsData>>,
+    dtz_map: usize,
+    data: Vec<u8>,
+}
+
+impl EncodedTable {
+    fn new(material: Material, kind: Kind, data: Vec<u8>) -> Option<EncodedTable> {
+        let magic = if kind == Kind::Wdl {
+            WDL_MAGIC
+        } else {
+            DTZ_MAGIC
+        };
+        if data.get(..4)? != magic {
+            return None;
+        }
+
+        let symmetric = material == material.flipped();
+        let (white_pawns, black_pawns) = (
+            material.count_of(colors::WHITE, piece_types::PAWN),
+            material.count_of(colors::BLACK, piece_types::PAWN),
+        );
+        // the side with fewer pawns leads, for better compression
+        let pawn_count = if black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns) {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        let has_unique_pieces = (colors::WHITE..=colors::BLACK).any(|color| {
+            (piece_types::PAWN..piece_types::KING).any(|piece| material.count_of(color, piece) == 1)
+        });
+        let mut table = EncodedTable {
+            kind,
+            material,
+            symmetric,
+            has_pawns: material.has_pawns(),
+            has_unique_pieces,
+            piece_count: material.count(),
+            pawn_count,
+            items: Vec::new(),
+            dtz_map: 0,
+            data: Vec::new(),
+        };
+        table.parse(&data)?;
+        table.data = data;
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == Kind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    #[inline]
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[stm % self.items.len()][if self.has_pawns { file } else { 0 }]
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Option<()> {
+        const SPLIT: u8 = 1;
+        const HAS_PAWNS: u8 = 2;
+
+        let header = *data.get(4)?;
+        if (header & HAS_PAWNS != 0) != self.has_pawns
+            || (header & SPLIT != 0) == self.symmetric
+            || self.piece_count > TB_PIECES
+        {
+            return None;
+        }
+
+        let sides = self.sides();
+        let files = if self.has_pawns { 4 } else { 1 };
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        self.items = vec![vec![PairsData::default(); files]; sides];
+
+        let mut offset = 5;
+        for file in 0..files {
+            let first = *data.get(offset)?;
+            let second = if pp { *data.get(offset + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            offset += 1 + pp as usize;
+
+            for k in 0..self.piece_count {
+                let pieces = *data.get(offset)?;
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] =
+                        if side == 1 { pieces >> 4 } else { pieces & 0xF };
+                }
+                offset += 1;
+            }
+            for (side, &order) in order.iter().enumerate().take(sides) {
+                let mut d = std::mem::take(&mut self.items[side][file]);
+                self.set_groups(&mut d, order, file)?;
+                self.items[side][file] = d;
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = self.items[side][file].set_sizes(data, offset)?;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            self.dtz_map = offset;
+            for file in 0..files {
+                let d = &mut self.items[0][file];
+                if d.flags & tb_flags::MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & tb_flags::WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = offset + 2;
+                        offset += 2 * read_u16(data, offset) + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = offset + 1;
+                        offset += usize::from(*data.get(offset)?) + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.sparse_index = offset;
+                offset += 6 * d.sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.block_length = offset;
+                offset += 2 * d.block_length_size;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                offset = (offset + 0x3F) & !0x3F;
+                d.data = offset;
+                offset += d.num_blocks * d.block_size;
+            }
+        }
+        if offset > data.len() {
+            return None;
+        }
+        Some(())
+    }
+
+    /// Splits the pieces into groups encoded together and computes each
+    /// group's factor in the index, in the order given by the file
+    fn set_groups(&self, d: &mut PairsData, order: [u8; 2], file: usize) -> Option<()> {
+        let mut n = 0;
+        let mut first_len: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        d.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+                d.group_len[n] += 1;
+            } else {
+                n += 1;
+                d.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        d.group_len[n] = 0;
+
+        let pp = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pp { 2 } else { 1 };
+        let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                d.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    *MAPS.lead_pawns_size.get(d.group_len[0])?.get(file)?
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                d.group_idx[1] = idx;
+                idx *= MAPS.binomial.get(d.group_len[1])?[48 - d.group_len[0]];
+            } else {
+                if next >= TB_PIECES || free_squares < d.group_len[next] {
+                    return None;
+                }
+                d.group_idx[next] = idx;
+                idx *= MAPS.binomial.get(d.group_len[next])?[free_squares];
+                free_squares -= d.group_len[next];
+                next += 1;
+            }
+            k += 1;
+            if k > 0xF {
+                return None;
+            }
+        }
+        d.group_idx[n] = idx;
+        Some(())
+    }
+
+    /// Finds the value of `pos`, which has this table's material either way
+    /// round
+    fn probe(&self, pos: &Position, wdl: Wdl) -> Result<i32, ProbeState> {
+        let (stm, file, idx) = self.locate(pos)?;
+        let value = self.get(stm, file).decompress(&self.data, idx);
+        Ok(self.map_score(file, value, wdl))
+    }
+
+    /// Side to move, leading file and index of `pos` in the table
+    fn locate(&self, pos: &Position) -> Result<(usize, usize, u64), ProbeState> {
+        // pieces are coded 1 to 6 from pawn to king, with bit 3 set for
+        // black
+        let mut squares = [0 as Square; TB_PIECES];
+        let mut pieces = [0u8; TB_PIECES];
+        let mut size = 0;
+
+        // tables store positions with white as the first side in the file
+        // name, and symmetric ones with white to move
+        let flip = (self.symmetric && pos.to_move() == colors::BLACK)
+            || Material::of(pos) != self.material;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let stm = (flip as usize) ^ pos.to_move() as usize;
+
+        let mut lead_pawns = 0;
+        let mut lead_pawns_count = 0;
+        let mut tb_file = 0;
+        if self.has_pawns {
+            let color = ((self.get(0, 0).pieces[0] ^ flip_color) >> 3) as Color;
+            lead_pawns = pos.bb_pawns(color);
+            let mut bb = lead_pawns;
+            for sq in bb.iter() {
+                squares[size] = sq ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+            let lead = (0..lead_pawns_count)
+                .rev()
+                .max_by_key(|&i| MAPS.pawns[squares[i] as usize])
+                .unwrap_or(0);
+            squares.swap(0, lead);
+            tb_file = (squares[0] & 7) as usize;
+            if tb_file > 3 {
+                tb_file = 7 - tb_file;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            let flags = self.get(stm, tb_file).flags;
+            if (flags & tb_flags::STM) as usize != stm && (!self.symmetric || self.has_pawns) {
+                return Err(ProbeState::ChangeStm);
+            }
+        }
+
+        let mut bb = (pos.bb_own(colors::WHITE) | pos.bb_own(colors::BLACK)) ^ lead_pawns;
+        for sq in bb.iter() {
+            if size >= TB_PIECES {
+                return Ok((stm, tb_file, 0));
+            }
+            let (piece, color) = pos.get_piece_and_color(sq);
+            squares[size] = sq ^ flip_squares;
+            pieces[size] = ((piece - 1) | (color << 3)) ^ flip_color;
+            size += 1;
+        }
+
+        let d = self.get(stm, tb_file);
+
+        // order the pieces like the table does
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // mirror the leading piece onto files a to d
+        if squares[0] & 7 > 3 {
+            for sq in squares[..size].iter_mut() {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = MAPS.lead_pawn_idx[lead_pawns_count][squares[0] as usize];
+            squares[1..lead_pawns_count].sort_by_key(|&sq| MAPS.pawns[sq as usize]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += MAPS.binomial[i][MAPS.pawns[sq as usize] as usize];
+            }
+        } else {
+            // then onto ranks 1 to 4, and below the a1-h8 diagonal
+            if squares[0] >> 3 > 3 {
+                for sq in squares[..size].iter_mut() {
+                    *sq ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for sq in squares[i..size].iter_mut() {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            idx = if self.has_unique_pieces {
+                let s = |i: usize| u64::from(squares[i]);
+                let rank = |i: usize| u64::from(squares[i] >> 3);
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                if off_diagonal(squares[0]) != 0 {
+                    (u64::from(MAPS.a1d1d4[squares[0] as usize]) * 63 + (s(1) - adjust1)) * 62
+                        + s(2)
+                        - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(0) * 28 + u64::from(MAPS.b1h1h7[squares[1] as usize])) * 62
+                        + s(2)
+                        - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(0) * 7 * 28
+                        + (rank(1) - adjust1) * 28
+                        + u64::from(MAPS.b1h1h7[squares[2] as usize])
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(0) * 7 * 6
+                        + (rank(1) - adjust1) * 6
+                        + (rank(2) - adjust2)
+                }
+            } else {
+                u64::from(MAPS.kk[MAPS.a1d1d4[squares[0] as usize] as usize][squares[1] as usize])
+            };
+        }
+
+        // the remaining groups, each sorted by square, skipping the squares
+        // taken by earlier groups
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[group_start..group_start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|&&s| sq > s).count();
+                let mapped = sq as usize - adjust - if remaining_pawns { 8 } else { 0 };
+                n += MAPS.binomial[i + 1][mapped];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += len;
+            next += 1;
+        }
+
+        Ok((stm, tb_file, idx))
+    }
+
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> i32 {
+        if self.kind == Kind::Wdl {
+            return value - 2;
+        }
+
+        let d = self.get(0, file);
+        let mut value = value;
+        if d.flags & tb_flags::MAPPED != 0 {
+            let map = d.map_idx[match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            }];
+            value = if d.flags & tb_flags::WIDE != 0 {
+                read_u16(&self.data, map + 2 * value as usize) as i32
+            } else {
+                i32::from(*self.data.get(map + value as usize).unwrap_or(&0))
+            };
+        }
+
+        // values are stored in moves unless the table says otherwise
+        if (wdl == Wdl::Win && d.flags & tb_flags::WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && d.flags & tb_flags::LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss
+        {
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+enum Loaded {
+    NotYet,
+    Table(Arc<EncodedTable>),
+    Failed,
+}
+
+/// A table file found on disk, read on first use
+struct TableFile {
+    path: PathBuf,
+    material: Material,
+    kind: Kind,
+    table: Mutex<Loaded>,
+}
+
+impl TableFile {
+    fn get(&self) -> Option<Arc<EncodedTable>> {
+        let mut table = self.table.lock().unwrap();
+        if let Loaded::NotYet = *table {
+            *table = fs::read(&self.path)
+                .ok()
+                .and_then(|data| EncodedTable::new(self.material, self.kind, data))
+                .map_or(Loaded::Failed, |t| Loaded::Table(Arc::new(t)));
+        }
+        match &*table {
+            Loaded::Table(t) => Some(Arc::clone(t)),
+            _ => None,
+        }
+    }
+}
+
+/// The Syzygy tables found in a set of directories
+#[derive(Default)]
+pub struct Syzygy {
+    wdl: HashMap<Material, TableFile>,
+    dtz: HashMap<Material, TableFile>,
+    max_pieces: usize,
+}
+
+#[inline]
+fn is_zeroing(board: &Board, mov: Move) -> bool {
+    mov.is_capture() || board.current().get_piece_and_color(mov.orig()).0 == piece_types::PAWN
+}
+
+impl Syzygy {
+    /// Finds the tables in `paths`, a list of directories separated like
+    /// the `PATH` variable
+    pub fn new(paths: &str) -> crate::Result<Syzygy> {
+        let mut syzygy = Syzygy::default();
+        for dir in paths.split(PATH_SEPARATOR).filter(|dir| !dir.is_empty()) {
+            for entry in fs::read_dir(dir).map_err(TablebaseError::from)? {
+                let path = entry.map_err(TablebaseError::from)?.path();
+                syzygy.add(&path);
+            }
+        }
+        Ok(syzygy)
+    }
+
+    fn add(&mut self, path: &Path) {
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return,
+        };
+        let (stem, kind) = if let Some(stem) = name.strip_suffix(WDL_SUFFIX) {
+            (stem, Kind::Wdl)
+        } else if let Some(stem) = name.strip_suffix(DTZ_SUFFIX) {
+            (stem, Kind::Dtz)
+        } else {
+            return;
+        };
+        let material = match stem.find('v') {
+            Some(v) if stem[v + 1..].starts_with('K') => {
+                format!("{}{}", &stem[..v], &stem[v + 1..]).parse::<Material>()
+            }
+            _ => return,
+        };
+        let material = match material {
+            Ok(material) if material.count() <= TB_PIECES => material,
+            _ => return,
+        };
+
+        let file = TableFile {
+            path: path.to_path_buf(),
+            material,
+            kind,
+            table: Mutex::new(Loaded::NotYet),
+        };
+        if kind == Kind::Wdl {
+            self.max_pieces = self.max_pieces.max(material.count());
+            self.wdl.insert(material, file);
+        } else {
+            self.dtz.insert(material, file);
+        }
+    }
+
+    /// Most pieces of any WDL table found, 0 without tables
+    #[inline]
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    pub fn len(&self) -> usize {
+        self.wdl.len() + self.dtz.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wdl.is_empty() && self.dtz.is_empty()
+    }
+
+    /// Whether `pos` may be found in the tables: it has few enough pieces
+    /// and no castling rights
+    pub fn covers(&self, pos: &Position) -> bool {
+        let pieces = (pos.bb_own(colors::WHITE) | pos.bb_own(colors::BLACK)).count() as usize;
+        pieces <= self.max_pieces && pos.castling() == Castling::empty()
+    }
+
+    fn table(&self, pos: &Position, kind: Kind) -> Option<Arc<EncodedTable>> {
+        let tables = if kind == Kind::Wdl {
+            &self.wdl
+        } else {
+            &self.dtz
+        };
+        let material = Material::of(pos);
+        tables
+            .get(&material)
+            .or_else(|| tables.get(&material.flipped()))
+            .and_then(TableFile::get)
+    }
+
+    fn probe_table(&self, pos: &Position, kind: Kind, wdl: Wdl) -> Option<Result<i32, ProbeState>> {
+        if (pos.bb_own(colors::WHITE) | pos.bb_own(colors::BLACK)).count() == 2 {
+            return Some(Ok(0));
+        }
+        if pos.castling() != Castling::empty() {
+            return None;
+        }
+        let table = self.table(pos, kind)?;
+        Some(table.probe(pos, wdl))
+    }
+
+    /// Tables treat positions with a winning capture as "don't care" and
+    /// may store losses for drawn positions with a drawing capture, so
+    /// captures, and for DTZ pawn moves, have to be tried as well. Returns
+    /// the result and whether the best move zeroes the 50-move counter.
+    fn search(&self, board: &mut Board, zeroing_moves: bool) -> Option<(i32, ProbeState)> {
+        let moves = board.legal_moves();
+        let mut best = Wdl::Loss as i32;
+        let mut count = 0;
+        for &mov in moves.iter() {
+            if !mov.is_capture() && (!zeroing_moves || !is_zeroing(board, mov)) {
+                continue;
+            }
+            count += 1;
+            board.make_move(mov);
+            let result = self.search(board, false);
+            board.unmake_move();
+            let value = -result?.0;
+            if value > best {
+                best = value;
+                if value >= Wdl::Win as i32 {
+                    return Some((value, ProbeState::ZeroingBestMove));
+                }
+            }
+        }
+
+        // without other moves, the table may be wrong, for instance it
+        // doesn't know about en passant captures
+        let no_more_moves = count > 0 && count == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(board.current(), Kind::Wdl, Wdl::Draw)? {
+                Ok(value) => value,
+                Err(_) => return None,
+            }
+        };
+        if best >= value {
+            let state = if best > Wdl::Draw as i32 || no_more_moves {
+                ProbeState::ZeroingBestMove
+            } else {
+                ProbeState::Ok
+            };
+            return Some((best, state));
+        }
+        Some((value, ProbeState::Ok))
+    }
+
+    /// Result of the current position, if it is in the tables
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if !self.covers(board.current()) {
+            return None;
+        }
+        self.search(board, false)
+            .map(|(value, _)| Wdl::from_i32(value))
+    }
+
+    /// Distance in plies to the next zeroing move on the way to the result,
+    /// positive for wins, negative for losses and 0 for draws. Cursed wins
+    /// and blessed losses are 100 plies further away.
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        if !self.covers(board.current()) {
+            return None;
+        }
+        let (value, state) = self.search(board, true)?;
+        let wdl = Wdl::from_i32(value);
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == ProbeState::ZeroingBestMove {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        match self.probe_table(board.current(), Kind::Dtz, wdl)? {
+            Ok(dtz) => {
+                let cursed = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+                Some((dtz + if cursed { 100 } else { 0 }) * value.signum())
+            }
+            Err(_) => {
+                // the table stores the other side to move, take the best of
+                // the moves
+                let to_move = board.current().to_move();
+                let mut min_dtz = i32::MAX;
+                for mov in board.legal_moves() {
+                    let zeroing = is_zeroing(board, mov);
+                    board.make_move(mov);
+                    let dtz = if zeroing {
+                        self.search(board, false)
+                            .map(|(value, _)| -Wdl::from_i32(value).dtz_before_zeroing())
+                    } else {
+                        self.probe_dtz(board).map(|dtz| -dtz)
+                    };
+                    let mates = dtz == Some(1)
+                        && board.is_in_check(1 ^ to_move)
+                        && board.legal_moves().is_empty();
+                    board.unmake_move();
+
+                    let mut dtz = dtz?;
+                    if mates {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == value.signum() {
+                        min_dtz = dtz;
+                    }
+                }
+                // without moves it's mate
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    /// The root moves keeping the tablebase result: the ones winning fastest,
+    /// every drawing one, or when lost every move if the loss is certain and
+    /// else the ones resisting longest, hoping for a 50-move draw
+    pub fn root_moves(&self, board: &mut Board) -> Option<(Wdl, Vec<Move>)> {
+        if !self.covers(board.current()) {
+            return None;
+        }
+        let halfmoves = board.current().halfmoves() as i32;
+        let to_move = board.current().to_move();
+
+        let mut ranked = Vec::new();
+        for mov in board.legal_moves() {
+            board.make_move(mov);
+            let dtz = if board.current().halfmoves() == 0 {
+                self.search(board, false)
+                    .map(|(value, _)| -Wdl::from_i32(value).dtz_before_zeroing())
+            } else {
+                self.probe_dtz(board).map(|dtz| match -dtz {
+                    dtz if dtz > 0 => dtz + 1,
+                    dtz if dtz < 0 => dtz - 1,
+                    dtz => dtz,
+                })
+            };
+            let mates =
+                dtz == Some(2) && board.is_in_check(1 ^ to_move) && board.legal_moves().is_empty();
+            board.unmake_move();
+            ranked.push((mov, if mates { 1 } else { dtz? }));
+        }
+
+        let wins = ranked
+            .iter()
+            .map(|&(_, dtz)| dtz)
+            .filter(|&dtz| dtz > 0)
+            .min();
+        let (wdl, best) = match wins {
+            Some(best) if best + halfmoves <= 100 => (Wdl::Win, Some(best)),
+            Some(best) => (Wdl::CursedWin, Some(best)),
+            None if ranked.iter().any(|&(_, dtz)| dtz == 0) => (Wdl::Draw, Some(0)),
+            None => {
+                let best = ranked.iter().map(|&(_, dtz)| dtz).min().unwrap_or(0);
+                if -best + halfmoves <= 100 {
+                    (Wdl::Loss, None)
+                } else {
+                    (Wdl::BlessedLoss, Some(best))
+                }
+            }
+        };
+        let moves = ranked
+            .into_iter()
+            .filter(|&(_, dtz)| best.is_none_or(|best| dtz == best))
+            .map(|(mov, _)| mov)
+            .collect();
+        Some((wdl, moves))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Dtm, Table, Tablebases};
+    use crate::interfaces::FenInterface;
+
+    #[test]
+    fn it_maps_squares_like_the_format() {
+        let kk_codes = MAPS.kk.iter().flat_map(|row| row.iter()).max();
+        assert_eq!(Some(&461), kk_codes);
+        assert_eq!(0, MAPS.a1d1d4[squares::B1 as usize]);
+        assert_eq!(9, MAPS.a1d1d4[squares::D4 as usize]);
+        assert_eq!(47, MAPS.pawns[squares::A2 as usize]);
+        assert_eq!(46, MAPS.pawns[squares::H2 as usize]);
+        assert_eq!(1128, MAPS.binomial[2][48]);
+        assert_eq!(6, MAPS.lead_pawns_size[1][0]);
+    }
+
+    /// Replaces the most frequent pair of neighbouring symbols by a new
+    /// symbol for as long as it pays, starting with one symbol per value.
+    /// Returns the children of every symbol, the value and `0xFFF` for
+    /// values, and the symbols standing for `values`.
+    fn pair_symbols(values: &[u8]) -> (Vec<(u16, u16)>, Vec<u8>) {
+        let leaves = usize::from(*values.iter().max().unwrap()) + 1;
+        let mut tree: Vec<(u16, u16)> = (0..leaves as u16).map(|value| (value, 0xFFF)).collect();
+        let mut expands = vec![1; leaves];
+        let mut symbols = values.to_vec();
+        let mut counts = vec![0u32; 1 << 16];
+        while tree.len() < 256 {
+            counts.iter_mut().for_each(|count| *count = 0);
+            for pair in symbols.windows(2) {
+                if expands[usize::from(pair[0])] + expands[usize::from(pair[1])] <= 256 {
+                    counts[usize::from(pair[0]) << 8 | usize::from(pair[1])] += 1;
+                }
+            }
+            let (best, &count) = counts
+                .iter()
+                .enumerate()
+                .max_by_key(|&(pair, count)| (count, std::cmp::Reverse(pair)))
+                .unwrap();
+            if count < 4 {
+                break;
+            }
+            let (left, right) = ((best >> 8) as u8, best as u8);
+            let sym = tree.len() as u8;
+            tree.push((u16::from(left), u16::from(right)));
+            expands.push(expands[usize::from(left)] + expands[usize::from(right)]);
+
+            let mut paired = Vec::with_capacity(symbols.len());
+            let mut i = 0;
+            while i < symbols.len() {
+                if i + 1 < symbols.len() && (symbols[i], symbols[i + 1]) == (left, right) {
+                    paired.push(sym);
+                    i += 2;
+                } else {
+                    paired.push(symbols[i]);
+                    i += 1;
+                }
+            }
+            symbols = paired;
+        }
+        (tree, symbols)
+    }
+
+    /// Writes a table with the values given per leading file and side to
+    /// move. The values are paired into symbols of one byte each, 32 to a
+    /// block.
+    fn write_table(path: &Path, kind: Kind, material: Material, values: &[Vec<u8>], flags: u8) {
+        const BLOCK_SIZE: usize = 32;
+        const SPAN: usize = 64;
+        let mut out = if kind == Kind::Wdl {
+            WDL_MAGIC
+        } else {
+            DTZ_MAGIC
+        }
+        .to_vec();
+        let split = if material == material.flipped() { 0 } else { 1 };
+        let has_pawns = if material.has_pawns() { 2 } else { 0 };
+        out.push(split | has_pawns);
+
+        // the leading pawn first, then the kings and the other pieces
+        let mut pieces = Vec::new();
+        if material.has_pawns() {
+            pieces.push(piece_types::PAWN - 1);
+        }
+        for color in colors::WHITE..=colors::BLACK {
+            pieces.push((piece_types::KING - 1) | (color << 3));
+            for &piece in [
+                piece_types::QUEEN,
+                piece_types::ROOK,
+                piece_types::BISHOP,
+                piece_types::KNIGHT,
+            ]
+            .iter()
+            {
+                let count = material.count_of(color, piece);
+                pieces.resize(pieces.len() + count, (piece - 1) | (color << 3));
+            }
+        }
+        // the same order and pieces for every file and side to move
+        let order = std::iter::once(0).chain(pieces.iter().map(|&piece| piece | (piece << 4)));
+        let order: Vec<u8> = order.collect();
+        let files = if material.has_pawns() { 4 } else { 1 };
+        for _ in 0..files {
+            out.extend_from_slice(&order);
+        }
+        if out.len() & 1 != 0 {
+            out.push(0);
+        }
+
+        let encoded: Vec<_> = values.iter().map(|side| pair_symbols(side)).collect();
+        for (tree, symbols) in &encoded {
+            let blocks = symbols.len().div_ceil(BLOCK_SIZE) as u32;
+            out.extend_from_slice(&[flags, BLOCK_SIZE.trailing_zeros() as u8]);
+            out.extend_from_slice(&[SPAN.trailing_zeros() as u8, 0]);
+            out.extend_from_slice(&blocks.to_le_bytes());
+            out.extend_from_slice(&[8, 8, 0, 0]);
+            out.extend_from_slice(&(tree.len() as u16).to_le_bytes());
+            for &(left, right) in tree {
+                let (left, right) = (usize::from(left), usize::from(right));
+                out.extend_from_slice(&[
+                    left as u8,
+                    (left >> 8 | right << 4) as u8,
+                    (right >> 4) as u8,
+                ]);
+            }
+            if tree.len() & 1 != 0 {
+                out.push(0);
+            }
+        }
+        if kind == Kind::Dtz && out.len() & 1 != 0 {
+            out.push(0);
+        }
+
+        // the values each block starts with
+        let block_starts: Vec<Vec<usize>> = encoded
+            .iter()
+            .map(|(tree, symbols)| {
+                let mut expands = Vec::new();
+                for &(left, right) in tree {
+                    let len = match right {
+                        0xFFF => 1,
+                        _ => expands[usize::from(left)] + expands[usize::from(right)],
+                    };
+                    expands.push(len);
+                }
+                let mut starts = vec![0];
+                for block in symbols.chunks(BLOCK_SIZE) {
+                    let len: usize = block.iter().map(|&sym| expands[usize::from(sym)]).sum();
+                    starts.push(starts.last().unwrap() + len);
+                }
+                starts
+            })
+            .collect();
+        for (side, starts) in values.iter().zip(&block_starts) {
+            for k in 0..side.len().div_ceil(SPAN) {
+                let value = k * SPAN + SPAN / 2;
+                let block = starts[1..starts.len() - 1].partition_point(|&start| start <= value);
+                out.extend_from_slice(&(block as u32).to_le_bytes());
+                out.extend_from_slice(&((value - starts[block]) as u16).to_le_bytes());
+            }
+        }
+        for starts in &block_starts {
+            for pair in starts.windows(2) {
+                out.extend_from_slice(&((pair[1] - pair[0] - 1) as u16).to_le_bytes());
+            }
+        }
+        for (_, symbols) in &encoded {
+            while out.len() % 64 != 0 {
+                out.push(0);
+            }
+            for block in symbols.chunks(BLOCK_SIZE) {
+                out.extend_from_slice(block);
+                out.resize(out.len() + BLOCK_SIZE - block.len(), 0);
+            }
+        }
+        fs::write(path, out).unwrap();
+    }
+
+    /// Squares of the pieces, in the order `write_table` gives them, of
+    /// every position a table for the white king and `piece` against the
+    /// lone king indexes, per leading file and by index. This follows the
+    /// format's description rather than `EncodedTable::locate`.
+    fn indexed_squares(piece: Piece) -> Vec<Vec<[Square; 3]>> {
+        let others = |taken: &[Square]| -> Vec<Square> {
+            (0..64).filter(|sq| !taken.contains(sq)).collect()
+        };
+        if piece == piece_types::PAWN {
+            // the pawn varies fastest over the ranks of its file, then the
+            // white king and the black king over the squares still free
+            return (0..4)
+                .map(|file| {
+                    let mut indexed = vec![[0; 3]; 6 * 63 * 62];
+                    for rank in 1..7 {
+                        let pawn = rank * 8 + file;
+                        for (wk_idx, &wk) in others(&[pawn]).iter().enumerate() {
+                            for (bk_idx, &bk) in others(&[pawn, wk]).iter().enumerate() {
+                                let idx = (rank - 1) as usize + 6 * (wk_idx + 63 * bk_idx);
+                                indexed[idx] = [pawn, wk, bk];
+                            }
+                        }
+                    }
+                    indexed
+                })
+                .collect();
+        }
+
+        // three unique pieces: the first off the diagonal in the a1-d1-d4
+        // triangle, then on the diagonal with the next one off it below,
+        // and so on
+        let diagonal = [0, 9, 18, 27, 36, 45, 54, 63];
+        let below: Vec<Square> = (0..64).filter(|sq| sq >> 3 < sq & 7).collect();
+        let triangle = [
+            squares::B1,
+            squares::C1,
+            squares::D1,
+            squares::C2,
+            squares::D2,
+            squares::D3,
+        ];
+        let mut indexed = Vec::new();
+        for &first in triangle.iter() {
+            for second in others(&[first]) {
+                for third in others(&[first, second]) {
+                    indexed.push([first, second, third]);
+                }
+            }
+        }
+        for &first in &diagonal[..4] {
+            for &second in &below {
+                for third in others(&[first, second]) {
+                    indexed.push([first, second, third]);
+                }
+            }
+        }
+        for &first in &diagonal[..4] {
+            for &second in diagonal.iter().filter(|&&sq| sq != first) {
+                for &third in &below {
+                    indexed.push([first, second, third]);
+                }
+            }
+        }
+        for &first in &diagonal[..4] {
+            for &second in diagonal.iter().filter(|&&sq| sq != first) {
+                for &third in diagonal.iter().filter(|&&sq| sq != first && sq != second) {
+                    indexed.push([first, second, third]);
+                }
+            }
+        }
+        assert_eq!(31332, indexed.len());
+        vec![indexed]
+    }
+
+    fn indexed_position(piece: Piece, squares: [Square; 3], to_move: Color) -> Position {
+        let (wk, sq, bk) = if piece == piece_types::PAWN {
+            (squares[1], squares[0], squares[2])
+        } else {
+            (squares[0], squares[1], squares[2])
+        };
+        let mut pos = Position::new();
+        pos.set_piece(piece_types::KING, colors::WHITE, wk);
+        pos.set_piece(piece, colors::WHITE, sq);
+        pos.set_piece(piece_types::KING, colors::BLACK, bk);
+        pos.set_to_move(to_move);
+        pos
+    }
+
+    /// Every position of the white king, the other white piece and the
+    /// black king, with either side to move
+    fn three_piece_positions(piece: Piece) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for wk in 0..64 {
+            for sq in 0..64 {
+                if piece == piece_types::PAWN && !(8..56).contains(&sq) {
+                    continue;
+                }
+                for bk in 0..64 {
+                    if wk == sq || wk == bk || sq == bk {
+                        continue;
+                    }
+                    for &to_move in [colors::WHITE, colors::BLACK].iter() {
+                        let mut pos = Position::new();
+                        pos.set_piece(piece_types::KING, colors::WHITE, wk);
+                        pos.set_piece(piece, colors::WHITE, sq);
+                        pos.set_piece(piece_types::KING, colors::BLACK, bk);
+                        pos.set_to_move(to_move);
+                        positions.push(pos);
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    fn table_name(piece: Piece) -> String {
+        format!("K{}vK", Piece::new(piece, colors::WHITE).to_san_string())
+    }
+
+    /// Syzygy tables in `dir` for the white king and `piece` against the
+    /// lone king, with the results of the DTM tables. Only pawnless tables
+    /// get a DTZ table, as the distance to mate is only the distance to
+    /// the next zeroing move without pawns.
+    fn generated_tables(dir: &Path, piece: Piece, tablebases: &Tablebases) -> Syzygy {
+        let name = table_name(piece);
+        let material: Material = name.replace("v", "").parse().unwrap();
+        fs::create_dir_all(dir).unwrap();
+
+        let mut wdl = Vec::new();
+        let mut dtz = Vec::new();
+        for indexed in indexed_squares(piece) {
+            for &to_move in [colors::WHITE, colors::BLACK].iter() {
+                let mut values = Vec::new();
+                for &squares in &indexed {
+                    let pos = indexed_position(piece, squares, to_move);
+                    let (value, plies) = match tablebases.probe(&pos) {
+                        Some(Dtm::Win(plies)) => (4, plies as u8),
+                        Some(Dtm::Loss(_)) => (0, 0),
+                        _ => (2, 0),
+                    };
+                    values.push(value);
+                    if to_move == colors::WHITE {
+                        dtz.push(if value == 4 { plies - 1 } else { 0 });
+                    }
+                }
+                wdl.push(values);
+            }
+        }
+        let dtz: Vec<Vec<u8>> = dtz.chunks(wdl[0].len()).map(|file| file.to_vec()).collect();
+        write_table(
+            &dir.join(format!("{}.rtbw", name)),
+            Kind::Wdl,
+            material,
+            &wdl,
+            0,
+        );
+        if !material.has_pawns() {
+            let plies = tb_flags::WIN_PLIES | tb_flags::LOSS_PLIES;
+            write_table(
+                &dir.join(format!("{}.rtbz", name)),
+                Kind::Dtz,
+                material,
+                &dtz,
+                plies,
+            );
+        }
+        Syzygy::new(dir.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn it_indexes_like_the_format() {
+        let tablebases = dtm_tables(&["KQK", "KRK", "KPK"]);
+        let dir = std::env::temp_dir().join("deeprust-syzygy-index-test");
+        for &piece in [piece_types::QUEEN, piece_types::PAWN].iter() {
+            generated_tables(&dir, piece, &tablebases);
+            let name = table_name(piece);
+            let material = name.replace("v", "").parse().unwrap();
+            let data = fs::read(dir.join(format!("{}.rtbw", name))).unwrap();
+            let table = EncodedTable::new(material, Kind::Wdl, data).unwrap();
+            for (file, indexed) in indexed_squares(piece).into_iter().enumerate() {
+                for (idx, &squares) in indexed.iter().enumerate() {
+                    for stm in 0..2 {
+                        let pos = indexed_position(piece, squares, stm as Color);
+                        let located = table.locate(&pos).ok();
+                        assert_eq!(
+                            Some((stm, file, idx as u64)),
+                            located,
+                            "{}",
+                            pos.to_fen_string()
+                        );
+                    }
+                }
+                // the symbols are pairs of symbols, not only values
+                let d = table.get(0, file);
+                assert!(d.symlen.iter().any(|&len| len > 0));
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn dtm_tables(names: &[&str]) -> Tablebases {
+        let mut tablebases = Tablebases::new();
+        for name in names {
+            tablebases.insert(Table::generate(&name.parse().unwrap(), &tablebases));
+        }
+        tablebases
+    }
+
+    #[test]
+    fn it_probes_tables() {
+        let tablebases = dtm_tables(&["KQK", "KRK", "KPK"]);
+        let dir = std::env::temp_dir().join("deeprust-syzygy-test");
+        // promotions need the KQvK tables
+        generated_tables(&dir, piece_types::QUEEN, &tablebases);
+        generated_tables(&dir, piece_types::ROOK, &tablebases);
+        let syzygy = generated_tables(&dir, piece_types::PAWN, &tablebases);
+        assert_eq!(5, syzygy.len());
+        assert_eq!(3, syzygy.max_pieces());
+
+        let mut board = Board::new();
+        for &(fen, wdl, dtz) in [
+            // Ra8#
+            ("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", Wdl::Win, Some(1)),
+            ("8/8/8/4k3/8/8/8/R3K3 b - - 0 1", Wdl::Loss, None),
+            // Kxa1
+            ("8/8/8/8/8/8/1k6/R3K3 b - - 0 1", Wdl::Draw, Some(0)),
+            // and the same for black
+            ("3k4/8/8/8/8/8/7r/3K4 w - - 0 1", Wdl::Loss, None),
+            ("8/8/8/8/2K5/8/1k6/5r2 b - - 0 1", Wdl::Win, None),
+            ("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1", Wdl::Win, None),
+            // stalemate
+            ("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1", Wdl::Draw, Some(0)),
+            // e8=Q
+            ("k7/4P3/8/8/8/8/8/K7 w - - 0 1", Wdl::Win, Some(1)),
+            ("k7/4P3/8/8/8/8/8/K7 b - - 0 1", Wdl::Loss, None),
+            // whoever has to give way loses
+            ("8/3k4/8/3K4/3P4/8/8/8 w - - 0 1", Wdl::Draw, Some(0)),
+            ("8/3k4/8/3K4/3P4/8/8/8 b - - 0 1", Wdl::Loss, None),
+            ("8/8/8/5p2/5k2/8/5K2/8 w - - 0 1", Wdl::Loss, None),
+        ]
+        .iter()
+        {
+            board.set_position(&Position::from_fen_str(fen).unwrap());
+            assert_eq!(Some(wdl), syzygy.probe_wdl(&mut board), "{}", fen);
+            if dtz.is_some() {
+                assert_eq!(dtz, syzygy.probe_dtz(&mut board), "{}", fen);
+            }
+        }
+
+        // every position agrees with the DTM tables
+        for piece in [piece_types::QUEEN, piece_types::ROOK, piece_types::PAWN].iter() {
+            for pos in three_piece_positions(*piece).iter().step_by(11) {
+                let dtm = match tablebases.probe(pos) {
+                    Some(dtm) if !pos.is_in_check(1 ^ pos.to_move()) => dtm,
+                    _ => continue,
+                };
+                board.set_position(pos);
+                let (wdl, dtz) = match dtm {
+                    Dtm::Win(plies) => (Wdl::Win, plies as i32),
+                    // mated positions count as a loss in one ply
+                    Dtm::Loss(plies) => (Wdl::Loss, -(plies.max(1) as i32)),
+                    Dtm::Draw => (Wdl::Draw, 0),
+                };
+                let fen = pos.to_fen_string();
+                assert_eq!(Some(wdl), syzygy.probe_wdl(&mut board), "{}", fen);
+                // mates are the only zeroing moves
+                if *piece != piece_types::PAWN {
+                    assert_eq!(Some(dtz), syzygy.probe_dtz(&mut board), "{}", fen);
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_the_fastest_wins_at_the_root() {
+        let tablebases = dtm_tables(&["KRK"]);
+        let dir = std::env::temp_dir().join("deeprust-syzygy-root-test");
+        let syzygy = generated_tables(&dir, piece_types::ROOK, &tablebases);
+        assert_eq!(2, syzygy.len());
+
+        let mut board = Board::from_fen_str("6k1/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+        assert_eq!(
+            Some((
+                Wdl::Win,
+                vec![Move::new(squares::A1, squares::A8, flags::MOV_QUIET)]
+            )),
+            syzygy.root_moves(&mut board)
+        );
+
+        board.set_position(&Position::from_fen_str("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap());
+        let mate = match tablebases.probe(board.current()) {
+            Some(Dtm::Win(plies)) => plies,
+            dtm => panic!("expected a win, got {:?}", dtm),
+        };
+        let (wdl, moves) = syzygy.root_moves(&mut board).unwrap();
+        assert_eq!(Wdl::Win, wdl);
+        assert!(!moves.is_empty());
+        for mov in moves {
+            board.make_move(mov);
+            assert_eq!(Some(Dtm::Loss(mate - 1)), tablebases.probe(board.current()));
+            board.unmake_move();
+        }
+
+        // the rook can't be saved
+        board.set_position(&Position::from_fen_str("8/8/8/8/8/8/1k6/R3K3 b - - 0 1").unwrap());
+        assert_eq!(
+            Some((
+                Wdl::Draw,
+                vec![Move::new(squares::B2, squares::A1, flags::MOV_CAPTURE)]
+            )),
+            syzygy.root_moves(&mut board)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,930 @@
+//! Distance-to-mate tablebases for endings with up to `MAX_PIECES` pieces
+//!
+//! Tables are generated by retrograde analysis. A forward pass over all
+//! positions finds the checkmates and stalemates, resolves captures and
+//! promotions through the smaller tables and counts the remaining moves.
+//! Then, ply by ply, the predecessors of every decided position are found
+//! with the `UnmoveGenerator`: a position with a move into a loss is won,
+//! and one whose moves all lead into wins is lost once the last of them is
+//! decided.
+//!
+//! Positions are indexed by the side to move and the squares of all pieces,
+//! with the first side's king restricted to files a to d, and for pawnless
+//! endings also to ranks 1 to 4, by mirroring the board. Tables store one
+//! byte per index: 0 for a draw, or the distance to mate in plies plus one,
+//! odd distances being wins for the side to move. Castling rights and en
+//! passant captures are not taken into account.
+//!
+//! Files start with the magic `DRTB`, a `u32` version, the `u32` number of
+//! indexes and the material. The bytes follow compressed as runs, each
+//! starting with a LEB128 number `length << 3 | kind`: kind 0 is followed
+//! by `length` literal bytes, the others repeat the bytes
+//! `COPY_DISTANCES[kind - 1]` indexes back, where a piece stood one square
+//! or one rank earlier. That makes the tables 2 to 13 times smaller on
+//! disk, while probes still read the plain bytes in memory.
+
+use crate::engine::{
+    mate_in, mated_in, scores, BitboardPrimitives, Board, MoveGenerator, Position, Score,
+    UnmoveGenerator, MAX_PLY,
+};
+use crate::primitives::*;
+
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Largest number of pieces, kings included, tables are generated for
+pub const MAX_PIECES: usize = 4;
+
+const MAGIC: &[u8; 4] = b"DRTB";
+const VERSION: u32 = 2;
+const NAME_SIZE: usize = 8;
+const HEADER_SIZE: usize = 12 + NAME_SIZE;
+const EXTENSION: &str = "dtm";
+
+/// Marks impossible positions during generation
+const INVALID: u8 = 255;
+/// Marks positions that can't be lost, because of a drawing or winning
+/// capture or promotion, or a stalemate
+const NO_LOSS: u8 = 255;
+
+/// Pieces from the strongest down, the order of the index
+const PIECE_ORDER: [Piece; 5] = [
+    piece_types::QUEEN,
+    piece_types::ROOK,
+    piece_types::BISHOP,
+    piece_types::KNIGHT,
+    piece_types::PAWN,
+];
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    Io(io::Error),
+    InvalidFile(PathBuf),
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TablebaseError::Io(e) => write!(f, "{}", e),
+            TablebaseError::InvalidFile(path) => {
+                write!(f, "{} is not a valid tablebase", path.display())
+            }
+        }
+    }
+}
+
+impl Error for TablebaseError {}
+
+impl From<io::Error> for TablebaseError {
+    fn from(e: io::Error) -> Self {
+        TablebaseError::Io(e)
+    }
+}
+
+/// Game theoretical value for the side to move, with the distance to mate
+/// in plies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dtm {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+impl Dtm {
+    fn from_value(value: u8) -> Dtm {
+        match value {
+            0 | INVALID => Dtm::Draw,
+            v if v % 2 == 0 => Dtm::Win(u32::from(v) - 1),
+            v => Dtm::Loss(u32::from(v) - 1),
+        }
+    }
+
+    /// Search score at `ply` plies from the root
+    ///
+    /// Mates too long for the mate band score as tablebase wins instead.
+    pub fn to_score(self, ply: usize) -> Score {
+        match self {
+            Dtm::Win(plies) if ply + plies as usize >= MAX_PLY => scores::TB_WIN - ply as Score,
+            Dtm::Loss(plies) if ply + plies as usize >= MAX_PLY => -scores::TB_WIN + ply as Score,
+            Dtm::Win(plies) => mate_in(ply + plies as usize),
+            Dtm::Loss(plies) => mated_in(ply + plies as usize),
+            Dtm::Draw => scores::DRAW,
+        }
+    }
+}
+
+/// The pieces of an ending, as counts per color and piece type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Material {
+    counts: [[u8; 8]; 2],
+}
+
+impl Material {
+    pub fn of(pos: &Position) -> Material {
+        let mut counts = [[0; 8]; 2];
+        for color in colors::WHITE..=colors::BLACK {
+            for &piece in PIECE_ORDER.iter() {
+                counts[color as usize][piece as usize] =
+                    pos.bb()[Position::bb_idx(color, piece)].count() as u8;
+            }
+        }
+        Material { counts }
+    }
+
+    /// Pieces other than the king of `color`, strongest first
+    fn pieces(&self, color: Color) -> impl Iterator<Item = Piece> + '_ {
+        PIECE_ORDER.iter().flat_map(move |&piece| {
+            (0..self.counts[color as usize][piece as usize]).map(move |_| piece)
+        })
+    }
+
+    /// Number of pieces of one color and type
+    #[inline]
+    pub fn count_of(&self, color: Color, piece: Piece) -> usize {
+        self.counts[color as usize][piece as usize] as usize
+    }
+
+    /// Number of pieces, kings included
+    pub fn count(&self) -> usize {
+        2 + self.pieces(colors::WHITE).count() + self.pieces(colors::BLACK).count()
+    }
+
+    pub fn has_pawns(&self) -> bool {
+        self.counts
+            .iter()
+            .any(|counts| counts[piece_types::PAWN as usize] > 0)
+    }
+
+    /// Whether neither side can possibly mate
+    pub fn is_insufficient(&self) -> bool {
+        let mut pieces = self.pieces(colors::WHITE).chain(self.pieces(colors::BLACK));
+        match (pieces.next(), pieces.next()) {
+            (None, _) => true,
+            (Some(piece), None) => piece == piece_types::KNIGHT || piece == piece_types::BISHOP,
+            _ => false,
+        }
+    }
+
+    /// The same material with colors swapped
+    pub fn flipped(&self) -> Material {
+        Material {
+            counts: [self.counts[1], self.counts[0]],
+        }
+    }
+
+    /// The material tables are stored for, with the stronger side first, and
+    /// whether the colors had to be swapped to get there
+    pub fn canonical(&self) -> (Material, bool) {
+        match self.pieces(colors::WHITE).cmp(self.pieces(colors::BLACK)) {
+            Ordering::Less => (self.flipped(), true),
+            _ => (*self, false),
+        }
+    }
+
+    /// All endings with up to `max_pieces` pieces that may be won
+    pub fn all(max_pieces: usize) -> Vec<Material> {
+        let mut all = vec![Material {
+            counts: [[0; 8]; 2],
+        }];
+        for _ in 2..max_pieces {
+            let mut more = Vec::new();
+            for material in all.iter() {
+                for color in colors::WHITE..=colors::BLACK {
+                    for &piece in PIECE_ORDER.iter() {
+                        let mut next = *material;
+                        next.counts[color as usize][piece as usize] += 1;
+                        let next = next.canonical().0;
+                        if !more.contains(&next) {
+                            more.push(next);
+                        }
+                    }
+                }
+            }
+            all.extend(more);
+        }
+        all.sort_by_key(|material| (material.count(), material.to_string()));
+        all.dedup();
+        all.retain(|material| !material.is_insufficient());
+        all
+    }
+
+    /// Tables reached by captures and promotions, which have to exist
+    /// before this one can be generated
+    pub fn dependencies(&self) -> Vec<Material> {
+        let mut deps = Vec::new();
+        for color in colors::WHITE..=colors::BLACK {
+            for &piece in PIECE_ORDER.iter() {
+                if self.counts[color as usize][piece as usize] == 0 {
+                    continue;
+                }
+                let mut captured = *self;
+                captured.counts[color as usize][piece as usize] -= 1;
+                deps.push(captured);
+                if piece == piece_types::PAWN {
+                    for &promoted in PIECE_ORDER[..4].iter() {
+                        let mut next = captured;
+                        next.counts[color as usize][promoted as usize] += 1;
+                        deps.push(next);
+                    }
+                }
+            }
+        }
+        let mut deps: Vec<Material> = deps
+            .into_iter()
+            .map(|material| material.canonical().0)
+            .filter(|material| !material.is_insufficient())
+            .collect();
+        deps.sort_by_key(|material| material.to_string());
+        deps.dedup();
+        deps
+    }
+
+    /// Number of squares the first side's king is restricted to
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() {
+            32
+        } else {
+            16
+        }
+    }
+
+    /// Number of index entries
+    pub fn size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.count() as u32 - 1)
+    }
+
+    /// Index of `pos`, which has this material with colors swapped if
+    /// `flip` is set
+    fn index(&self, pos: &Position, flip: bool) -> usize {
+        let (flip_color, flip_square) = if flip { (1, 56) } else { (0, 0) };
+        let king = |color: Color| pos.bb_king(color ^ flip_color).scan() ^ flip_square;
+
+        // mirror the first king into its corner of the board
+        let first_king = king(colors::WHITE);
+        let mut mirror = if first_king & 7 >= 4 { 7 } else { 0 };
+        if !self.has_pawns() && first_king >> 3 >= 4 {
+            mirror ^= 56;
+        }
+        let first_king = first_king ^ mirror;
+
+        let mut idx = (pos.to_move() ^ flip_color) as usize;
+        idx = idx * self.king_squares() + ((first_king >> 3) * 4 + (first_king & 7)) as usize;
+        idx = idx * 64 + (king(colors::BLACK) ^ mirror) as usize;
+        for color in colors::WHITE..=colors::BLACK {
+            for &piece in PIECE_ORDER.iter() {
+                let mut bb = pos.bb()[Position::bb_idx(color ^ flip_color, piece)];
+                // identical pieces are ordered by square
+                let mut squares: Vec<Square> =
+                    bb.iter().map(|sq| sq ^ flip_square ^ mirror).collect();
+                squares.sort_unstable();
+                for sq in squares {
+                    idx = idx * 64 + sq as usize;
+                }
+            }
+        }
+        idx
+    }
+
+    /// The position at `idx`, unless the pieces overlap or are out of order,
+    /// pawns stand on the first or last rank or the kings touch
+    fn position(&self, idx: usize) -> Option<Position> {
+        let mut slots: Vec<(Piece, Color)> = vec![
+            (piece_types::KING, colors::WHITE),
+            (piece_types::KING, colors::BLACK),
+        ];
+        for color in colors::WHITE..=colors::BLACK {
+            slots.extend(self.pieces(color).map(|piece| (piece, color)));
+        }
+
+        let mut squares = vec![0; slots.len()];
+        let mut rest = idx;
+        for sq in squares[1..].iter_mut().rev() {
+            *sq = (rest % 64) as Square;
+            rest /= 64;
+        }
+        let king_slot = (rest % self.king_squares()) as Square;
+        squares[0] = (king_slot / 4) * 8 + king_slot % 4;
+        let to_move = (rest / self.king_squares()) as Color;
+
+        if squares[0].distance(squares[1]) <= 1 {
+            return None;
+        }
+        let mut pos = Position::new();
+        for (i, (&(piece, color), &sq)) in slots.iter().zip(squares.iter()).enumerate() {
+            if pos.occupied()[sq as usize] != 0
+                || (piece == piece_types::PAWN && !(8..56).contains(&sq))
+                || (i > 0 && slots[i - 1] == (piece, color) && squares[i - 1] > sq)
+            {
+                return None;
+            }
+            pos.set_piece(piece, color, sq);
+        }
+        pos.set_to_move(to_move);
+        Some(pos)
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for color in colors::WHITE..=colors::BLACK {
+            write!(f, "K")?;
+            for piece in self.pieces(color) {
+                write!(f, "{}", Piece::new(piece, colors::WHITE).to_san_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Material {
+    type Err = ();
+
+    /// Parses names like `KRKP`, white's pieces first
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('K') {
+            return Err(());
+        }
+        let second_king = s[1..].find('K').ok_or(())? + 1;
+        let mut counts = [[0; 8]; 2];
+        for (color, side) in [&s[1..second_king], &s[second_king + 1..]]
+            .iter()
+            .enumerate()
+        {
+            for c in side.chars() {
+                let piece = match c {
+                    'Q' => piece_types::QUEEN,
+                    'R' => piece_types::ROOK,
+                    'B' => piece_types::BISHOP,
+                    'N' => piece_types::KNIGHT,
+                    'P' => piece_types::PAWN,
+                    _ => return Err(()),
+                };
+                counts[color][piece as usize] += 1;
+            }
+        }
+        Ok(Material { counts })
+    }
+}
+
+/// Per-position results of the forward pass
+#[derive(Clone, Copy)]
+struct Entry {
+    value: u8,
+    /// Moves staying within the table
+    moves: u8,
+    /// Distance of the longest loss through captures and promotions, or
+    /// `NO_LOSS`
+    loss_floor: u8,
+    /// Distance of the shortest win through captures and promotions, or 0
+    exit_win: u8,
+}
+
+const INVALID_ENTRY: Entry = Entry {
+    value: INVALID,
+    moves: 0,
+    loss_floor: NO_LOSS,
+    exit_win: 0,
+};
+
+/// Results of all positions with one material
+pub struct Table {
+    material: Material,
+    values: Vec<u8>,
+}
+
+impl Table {
+    #[inline]
+    pub fn material(&self) -> Material {
+        self.material
+    }
+
+    /// Looks up `pos`, with colors swapped if `flip` is set
+    pub fn probe(&self, pos: &Position, flip: bool) -> Dtm {
+        Dtm::from_value(self.values[self.material.index(pos, flip)])
+    }
+
+    /// Numbers of won, lost and drawn positions, and the longest mate in plies
+    pub fn stats(&self) -> (usize, usize, usize, u32) {
+        let (mut wins, mut losses, mut draws, mut longest) = (0, 0, 0, 0);
+        for &value in self.values.iter() {
+            match Dtm::from_value(value) {
+                Dtm::Win(plies) => {
+                    wins += 1;
+                    longest = longest.max(plies);
+                }
+                Dtm::Loss(plies) => {
+                    losses += 1;
+                    longest = longest.max(plies);
+                }
+                Dtm::Draw => draws += 1,
+            }
+        }
+        (wins, losses, draws, longest)
+    }
+
+    fn entry(material: &Material, tablebases: &Tablebases, board: &mut Board, idx: usize) -> Entry {
+        let pos = match material.position(idx) {
+            Some(pos) => pos,
+            None => return INVALID_ENTRY,
+        };
+        let to_move = pos.to_move();
+        board.set_position(&pos);
+        if board.is_in_check(1 ^ to_move) {
+            return INVALID_ENTRY;
+        }
+
+        let mut entry = Entry {
+            value: 0,
+            moves: 0,
+            loss_floor: 0,
+            exit_win: 0,
+        };
+        let mut legal = 0;
+        let mut escapes = false;
+        for mov in board.generate_moves() {
+            board.make_move(mov);
+            if board.is_in_check(to_move) {
+                board.unmake_move();
+                continue;
+            }
+            legal += 1;
+            if Material::of(board.current()) == *material {
+                entry.moves += 1;
+            } else {
+                let dtm = tablebases
+                    .probe(board.current())
+                    .unwrap_or_else(|| panic!("{} needs more tables", material));
+                match dtm {
+                    Dtm::Loss(plies) => {
+                        let win = plies as u8 + 1;
+                        if entry.exit_win == 0 || win < entry.exit_win {
+                            entry.exit_win = win;
+                        }
+                        escapes = true;
+                    }
+                    Dtm::Win(plies) => entry.loss_floor = entry.loss_floor.max(plies as u8 + 1),
+                    Dtm::Draw => escapes = true,
+                }
+            }
+            board.unmake_move();
+        }
+
+        if legal == 0 {
+            if board.is_in_check(to_move) {
+                entry.value = 1;
+            }
+            escapes = true;
+        }
+        if escapes {
+            entry.loss_floor = NO_LOSS;
+        }
+        entry
+    }
+
+    /// Generates the table for `material`, probing `tablebases` for all its
+    /// `dependencies`
+    pub fn generate(material: &Material, tablebases: &Tablebases) -> Table {
+        let size = material.size();
+        let entries: Vec<Entry> = (0..size)
+            .into_par_iter()
+            .map_init(Board::new, |board, idx| {
+                Self::entry(material, tablebases, board, idx)
+            })
+            .collect();
+
+        let mut values: Vec<u8> = entries.iter().map(|entry| entry.value).collect();
+        let mut moves: Vec<u8> = entries.iter().map(|entry| entry.moves).collect();
+        // positions decided by captures and promotions, by distance
+        let mut pending: Vec<Vec<usize>> = vec![Vec::new(); usize::from(INVALID)];
+        let mut current = Vec::new();
+        for (idx, entry) in entries.iter().enumerate() {
+            if entry.value == 1 {
+                current.push(idx);
+            } else if entry.value == 0 {
+                if entry.exit_win != 0 {
+                    pending[entry.exit_win as usize].push(idx);
+                } else if entry.moves == 0 && entry.loss_floor != NO_LOSS {
+                    pending[entry.loss_floor as usize].push(idx);
+                }
+            }
+        }
+        let floors: Vec<u8> = entries.iter().map(|entry| entry.loss_floor).collect();
+        drop(entries);
+
+        for plies in 0..pending.len() - 1 {
+            for idx in pending[plies].split_off(0) {
+                if values[idx] == 0 {
+                    values[idx] = plies as u8 + 1;
+                    current.push(idx);
+                }
+            }
+
+            let mut next = Vec::new();
+            for &idx in current.iter() {
+                let pos = material.position(idx).unwrap();
+                for unmove in pos.generate_unmoves() {
+                    let prev = material.index(&pos.retract(unmove), false);
+                    if values[prev] != 0 {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        // a move into a loss wins
+                        values[prev] = plies as u8 + 2;
+                        next.push(prev);
+                    } else {
+                        moves[prev] -= 1;
+                        if moves[prev] == 0 && floors[prev] != NO_LOSS {
+                            // every move loses, the longest way decides
+                            let loss = (plies + 1).max(floors[prev] as usize);
+                            if loss == plies + 1 {
+                                values[prev] = loss as u8 + 1;
+                                next.push(prev);
+                            } else {
+                                pending[loss].push(prev);
+                            }
+                        }
+                    }
+                }
+            }
+            current = next;
+        }
+        assert!(current.is_empty(), "{} is too deep", material);
+
+        for value in values.iter_mut() {
+            if *value == INVALID {
+                *value = 0;
+            }
+        }
+        Table {
+            material: *material,
+            values,
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> crate::Result<Table> {
+        let path = path.as_ref();
+        let invalid = || TablebaseError::InvalidFile(path.to_path_buf());
+        let bytes = fs::read(path).map_err(TablebaseError::from)?;
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(invalid().into());
+        }
+        let word = |i: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[4 * i..4 * i + 4]);
+            u32::from_le_bytes(word) as usize
+        };
+        let name = String::from_utf8_lossy(&bytes[12..HEADER_SIZE]);
+        let material: Material = name.trim_end_matches('\0').parse().map_err(|_| invalid())?;
+        if word(1) != VERSION as usize
+            || material.count() > MAX_PIECES
+            || word(2) != material.size()
+        {
+            return Err(invalid().into());
+        }
+        let values = decompress(&bytes[HEADER_SIZE..], material.size()).ok_or_else(invalid)?;
+        Ok(Table { material, values })
+    }
+
+    /// Writes the table into `dir`, named after its material
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> io::Result<PathBuf> {
+        let path = dir
+            .as_ref()
+            .join(format!("{}.{}", self.material, EXTENSION));
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.values.len() as u32).to_le_bytes());
+        let mut name = [0; NAME_SIZE];
+        let material = self.material.to_string();
+        name[..material.len()].copy_from_slice(material.as_bytes());
+        bytes.extend_from_slice(&name);
+        bytes.extend_from_slice(&compress(&self.values));
+        fs::write(&path, bytes)?;
+        Ok(path)
+    }
+}
+
+/// How far back the runs of a compressed table may copy from: one square
+/// and one rank of each piece, with the index counting 64 per square
+const COPY_DISTANCES: [usize; 7] = [1, 8, 64, 512, 4096, 32_768, 262_144];
+
+fn write_run(bytes: &mut Vec<u8>, length: usize, kind: usize) {
+    let mut header = length << 3 | kind;
+    while header >= 0x80 {
+        bytes.push(header as u8 | 0x80);
+        header >>= 7;
+    }
+    bytes.push(header as u8);
+}
+
+/// Compresses `values` into runs of literals and copies, always taking the
+/// longest copy
+fn compress(values: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut literals = 0;
+    let mut i = 0;
+    while i < values.len() {
+        let (kind, length) = COPY_DISTANCES
+            .iter()
+            .take_while(|&&distance| distance <= i)
+            .map(|&distance| {
+                values[i..]
+                    .iter()
+                    .zip(&values[i - distance..])
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .enumerate()
+            .max_by_key(|&(_, length)| length)
+            .map_or((0, 0), |(idx, length)| (idx + 1, length));
+        if length < 2 {
+            literals += 1;
+            i += 1;
+            continue;
+        }
+        if literals > 0 {
+            write_run(&mut bytes, literals, 0);
+            bytes.extend_from_slice(&values[i - literals..i]);
+            literals = 0;
+        }
+        write_run(&mut bytes, length, kind);
+        i += length;
+    }
+    if literals > 0 {
+        write_run(&mut bytes, literals, 0);
+        bytes.extend_from_slice(&values[i - literals..]);
+    }
+    bytes
+}
+
+/// Inverse of `compress`, `None` unless `bytes` hold exactly `size` values
+fn decompress(bytes: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut values = Vec::with_capacity(size);
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let mut header = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.get(pos)?;
+            pos += 1;
+            header |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            if shift > 35 {
+                return None;
+            }
+        }
+        let (length, kind) = (header >> 3, header & 7);
+        if length == 0 || values.len() + length > size {
+            return None;
+        }
+        if kind == 0 {
+            values.extend_from_slice(bytes.get(pos..pos + length)?);
+            pos += length;
+        } else {
+            // copies may overlap what they add
+            let start = values.len().checked_sub(COPY_DISTANCES[kind - 1])?;
+            for j in start..start + length {
+                let value = values[j];
+                values.push(value);
+            }
+        }
+    }
+    Some(values).filter(|values| values.len() == size)
+}
+
+/// A set of tables, probed by material
+#[derive(Default)]
+pub struct Tablebases {
+    tables: HashMap<Material, Table>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    pub fn new() -> Tablebases {
+        Self::default()
+    }
+
+    /// Loads all tables in `dir`
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> crate::Result<Tablebases> {
+        let mut tablebases = Tablebases::new();
+        for entry in fs::read_dir(dir).map_err(TablebaseError::from)? {
+            let path = entry.map_err(TablebaseError::from)?.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                tablebases.insert(Table::read(&path)?);
+            }
+        }
+        Ok(tablebases)
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.max_pieces = self.max_pieces.max(table.material.count());
+        self.tables.insert(table.material, table);
+    }
+
+    pub fn contains(&self, material: &Material) -> bool {
+        self.tables.contains_key(material)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Looks up `pos`, if there is a table for it and it has neither
+    /// castling rights nor an en passant square
+    pub fn probe(&self, pos: &Position) -> Option<Dtm> {
+        let pieces = (pos.bb_own(colors::WHITE) | pos.bb_own(colors::BLACK)).count() as usize;
+        if pieces > self.max_pieces.max(3)
+            || pos.castling() != Castling::empty()
+            || pos.en_passant().is_some()
+        {
+            return None;
+        }
+        let material = Material::of(pos);
+        if material.is_insufficient() {
+            return Some(Dtm::Draw);
+        }
+        let (material, flip) = material.canonical();
+        self.tables
+            .get(&material)
+            .map(|table| table.probe(pos, flip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::FenInterface;
+
+    fn material(name: &str) -> Material {
+        name.parse().unwrap()
+    }
+
+    fn generate(names: &[&str]) -> Tablebases {
+        let mut tablebases = Tablebases::new();
+        for name in names {
+            let table = Table::generate(&material(name), &tablebases);
+            tablebases.insert(table);
+        }
+        tablebases
+    }
+
+    fn probe(tablebases: &Tablebases, fen: &str) -> Option<Dtm> {
+        tablebases.probe(&Position::from_fen_str(fen).unwrap())
+    }
+
+    #[test]
+    fn it_keeps_long_mates_below_the_mate_band() {
+        assert_eq!(Dtm::Win(3).to_score(2), mate_in(5));
+        assert_eq!(Dtm::Loss(4).to_score(2), mated_in(6));
+        let win = Dtm::Win(200).to_score(10);
+        assert!(win < scores::MATE_BOUND);
+        assert!(win > scores::TB_WIN - MAX_PLY as Score);
+        assert_eq!(Dtm::Loss(200).to_score(10), -win);
+        assert!(Dtm::Win(MAX_PLY as u32).to_score(0) < scores::MATE_BOUND);
+    }
+
+    #[test]
+    fn it_names_materials() {
+        let pos = Position::from_fen_str("8/8/8/4k3/3p4/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!("KRKP", Material::of(&pos).to_string());
+        assert_eq!((material("KRKP"), true), material("KPKR").canonical());
+        assert_eq!((material("KQKR"), false), material("KQKR").canonical());
+        assert!(material("KNK").is_insufficient());
+        assert!(!material("KNNK").is_insufficient());
+
+        let deps: Vec<String> = material("KRKP")
+            .dependencies()
+            .iter()
+            .map(|material| material.to_string())
+            .collect();
+        assert_eq!(vec!["KPK", "KQKR", "KRK", "KRKB", "KRKN", "KRKR"], deps);
+        let all = Material::all(MAX_PIECES);
+        assert_eq!(3 + 30, all.len());
+        assert!(all.contains(&material("KBNK")) && all.contains(&material("KPKP")));
+    }
+
+    #[test]
+    fn it_indexes_symmetric_positions_alike() {
+        let krk = material("KRK");
+        let pos = Position::from_fen_str("8/8/8/8/8/2k5/8/R3K3 w - - 0 1").unwrap();
+        let mirrored = Position::from_fen_str("R3K3/8/2k5/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(krk.index(&pos, false), krk.index(&mirrored, false));
+        let flipped = Position::from_fen_str("r3k3/8/2K5/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(krk.index(&pos, false), krk.index(&flipped, true));
+
+        let idx = krk.index(&pos, false);
+        assert_eq!(idx, krk.index(&krk.position(idx).unwrap(), false));
+        assert!(idx < krk.size());
+    }
+
+    #[test]
+    fn it_generates_kqk_and_krk() {
+        let tablebases = generate(&["KQK", "KRK"]);
+        // the lone king survives at most 10 and 16 moves
+        let (.., longest) = tablebases.tables[&material("KQK")].stats();
+        assert_eq!(20, longest);
+        let (.., longest) = tablebases.tables[&material("KRK")].stats();
+        assert_eq!(32, longest);
+
+        assert_eq!(
+            Some(Dtm::Loss(0)),
+            probe(&tablebases, "k6R/8/1K6/8/8/8/8/8 b - - 0 1")
+        );
+        assert_eq!(
+            Some(Dtm::Win(1)),
+            probe(&tablebases, "k7/8/1K6/8/8/8/8/7R w - - 0 1")
+        );
+        // the same for black, and a stalemate
+        assert_eq!(
+            Some(Dtm::Win(1)),
+            probe(&tablebases, "7r/8/8/8/8/1k6/8/K7 b - - 0 1")
+        );
+        assert_eq!(
+            Some(Dtm::Draw),
+            probe(&tablebases, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1")
+        );
+        // the rook hangs
+        assert_eq!(
+            Some(Dtm::Draw),
+            probe(&tablebases, "8/8/8/8/8/8/1k6/R3K3 b - - 0 1")
+        );
+    }
+
+    #[test]
+    fn it_generates_kpk() {
+        let tablebases = generate(&["KQK", "KRK", "KPK"]);
+        assert_eq!(
+            Some(Dtm::Draw),
+            probe(&tablebases, "8/3k4/8/3K4/3P4/8/8/8 w - - 0 1")
+        );
+        // whoever has to give way loses
+        match probe(&tablebases, "8/3k4/8/3K4/3P4/8/8/8 b - - 0 1") {
+            Some(Dtm::Loss(_)) => (),
+            dtm => panic!("expected a loss, got {:?}", dtm),
+        }
+        match probe(&tablebases, "8/8/8/5p2/5k2/8/5K2/8 w - - 0 1") {
+            Some(Dtm::Loss(_)) => (),
+            dtm => panic!("expected a loss, got {:?}", dtm),
+        }
+        // the pawn queens and mates on the next move
+        assert_eq!(
+            Some(Dtm::Win(3)),
+            probe(&tablebases, "k7/2K3P1/8/8/8/8/8/8 w - - 0 1")
+        );
+    }
+
+    #[test]
+    fn it_reads_back_written_tables() {
+        let tablebases = generate(&["KQK"]);
+        let dir = std::env::temp_dir().join("deeprust-tablebase-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = tablebases.tables[&material("KQK")].write(&dir).unwrap();
+        assert_eq!(Some("KQK.dtm"), path.file_name().and_then(|n| n.to_str()));
+        let size = fs::metadata(&path).unwrap().len() as usize;
+        assert!(size * 2 < material("KQK").size());
+
+        let loaded = Tablebases::load_dir(&dir).unwrap();
+        assert_eq!(1, loaded.len());
+        assert_eq!(
+            tablebases.tables[&material("KQK")].values,
+            loaded.tables[&material("KQK")].values
+        );
+
+        fs::write(&path, b"DRTB").unwrap();
+        assert!(Table::read(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_compresses_runs() {
+        let values: Vec<u8> = [vec![3; 200], vec![1, 2], vec![3; 6], vec![1, 2, 9]].concat();
+        let bytes = compress(&values);
+        assert_eq!(
+            vec![8, 3, 0xb9, 0x0c, 16, 1, 2, 66, 8, 9],
+            bytes
+        );
+        assert_eq!(Some(values.clone()), decompress(&bytes, values.len()));
+
+        // runs have to fill the table exactly, copying from inside it
+        assert_eq!(None, decompress(&bytes, values.len() + 1));
+        assert_eq!(None, decompress(&bytes, values.len() - 1));
+        assert_eq!(None, decompress(&bytes[..bytes.len() - 1], values.len()));
+        assert_eq!(None, decompress(&[0], 0));
+        assert_eq!(None, decompress(&[8, 3, 10], 2));
+        assert_eq!(None, decompress(&[0xff; 8], 1));
+    }
+}
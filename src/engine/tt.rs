@@ -0,0 +1,132 @@
+use crate::engine::{Score, ScorePrimitives, ZobKey};
+use crate::primitives::Move;
+
+use std::mem;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TTEntry {
+    pub key: ZobKey,
+    pub mov: Option<Move>,
+    pub score: Score,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+/// Fixed size transposition table, keeping deeper entries for the same key
+///
+/// Mate scores are stored relative to the node they were found in and
+/// converted back to root distance on probe, see `ScorePrimitives::to_tt`.
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl TranspositionTable {
+    /// Creates a table using roughly `size_mb` megabytes
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let wanted = (size_mb.max(1) << 20) / mem::size_of::<Option<TTEntry>>();
+        // round down to a power of two so we can mask instead of mod
+        let len = 1 << (63 - (wanted as u64).leading_zeros());
+        TranspositionTable {
+            entries: vec![None; len],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    #[inline]
+    fn index(&self, key: ZobKey) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    /// Looks up `key`, returning the entry with its score adjusted to `ply`
+    pub fn probe(&self, key: ZobKey, ply: usize) -> Option<TTEntry> {
+        match self.entries[self.index(key)] {
+            Some(entry) if entry.key == key => Some(TTEntry {
+                score: entry.score.to_search(ply),
+                ..entry
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn store(
+        &mut self,
+        key: ZobKey,
+        mov: Option<Move>,
+        score: Score,
+        depth: u32,
+        bound: Bound,
+        ply: usize,
+    ) {
+        let idx = self.index(key);
+        if let Some(old) = self.entries[idx] {
+            if old.key == key && old.depth > depth && bound != Bound::Exact {
+                return;
+            }
+        }
+        self.entries[idx] = Some(TTEntry {
+            key,
+            mov,
+            score: score.to_tt(ply),
+            depth,
+            bound,
+        });
+    }
+
+    /// Permill of used entries, sampled from the first thousand slots
+    pub fn hashfull(&self) -> usize {
+        self.entries
+            .iter()
+            .take(1000)
+            .filter(|entry| entry.is_some())
+            .count()
+            * 1000
+            / self.entries.len().min(1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{mate_in, mated_in};
+
+    #[test]
+    fn it_adjusts_mate_scores_on_store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(0xdead_beef, None, mate_in(7), 4, Bound::Exact, 3);
+        assert_eq!(mate_in(7), tt.probe(0xdead_beef, 3).unwrap().score);
+        assert_eq!(mate_in(5), tt.probe(0xdead_beef, 1).unwrap().score);
+
+        tt.store(0xbeef_dead, None, mated_in(6), 4, Bound::Upper, 2);
+        assert_eq!(mated_in(10), tt.probe(0xbeef_dead, 6).unwrap().score);
+
+        assert!(tt.probe(0x1234, 0).is_none());
+    }
+
+    #[test]
+    fn it_prefers_deeper_entries() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(42, None, 10, 6, Bound::Lower, 0);
+        tt.store(42, None, 20, 2, Bound::Lower, 0);
+        assert_eq!(10, tt.probe(42, 0).unwrap().score);
+        tt.store(42, None, 30, 2, Bound::Exact, 0);
+        assert_eq!(30, tt.probe(42, 0).unwrap().score);
+    }
+}
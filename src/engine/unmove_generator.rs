@@ -0,0 +1,123 @@
+use crate::engine::bitboards::*;
+use crate::engine::{Bitboard, BitboardPrimitives, Position};
+use crate::primitives::*;
+
+/// Generation of moves backwards in time, for retrograde analysis
+pub trait UnmoveGenerator {
+    /// Quiet moves by the side that just moved which may have led to this
+    /// position, written as moves from the earlier position. Captures and
+    /// promotions are never undone, and the earlier positions are not checked
+    /// for legality.
+    fn generate_unmoves(&self) -> Vec<Move>;
+
+    /// The position before `mov`, one of `generate_unmoves`, was played
+    fn retract(&self, mov: Move) -> Self;
+}
+
+impl UnmoveGenerator for Position {
+    fn generate_unmoves(&self) -> Vec<Move> {
+        let mut unmoves = Vec::with_capacity(64);
+        let them = 1 ^ self.to_move();
+        let occupied = self.bb_own(them) | self.bb_opponent(them);
+        let empty = !occupied;
+
+        for piece in piece_types::KNIGHT..=piece_types::KING {
+            let mut pieces = self.bb()[Position::bb_idx(them, piece)];
+            for dest in pieces.iter() {
+                let mut origs = match piece {
+                    piece_types::KNIGHT => BB_KNIGHT_ATTACKS[dest as usize],
+                    piece_types::BISHOP => bishop_attacks(dest, occupied),
+                    piece_types::ROOK => {
+                        rank_attacks(dest, occupied) | file_attacks(dest, occupied)
+                    }
+                    piece_types::QUEEN => {
+                        bishop_attacks(dest, occupied)
+                            | rank_attacks(dest, occupied)
+                            | file_attacks(dest, occupied)
+                    }
+                    _ => BB_KING_ATTACKS[dest as usize],
+                } & empty;
+                for orig in origs.iter() {
+                    unmoves.push(Move::new(orig, dest, flags::MOV_QUIET));
+                }
+            }
+        }
+
+        // pawns step back towards their own side, to no further than the
+        // second rank
+        let (back, start_rank, double_rank): (i32, Bitboard, Bitboard) = if them == colors::WHITE {
+            (-8, BB_RANK_2, BB_RANK_4)
+        } else {
+            (8, BB_RANK_7, BB_RANK_5)
+        };
+        let mut pawns = self.bb_pawns(them);
+        for dest in pawns.iter() {
+            let single = (dest as i32 + back) as Square;
+            if !(8..56).contains(&single) || empty & BB_SQUARES[single as usize] == 0 {
+                continue;
+            }
+            unmoves.push(Move::new(single, dest, flags::MOV_QUIET));
+            if BB_SQUARES[dest as usize] & double_rank != 0 {
+                let double = (single as i32 + back) as Square;
+                if empty & BB_SQUARES[double as usize] & start_rank != 0 {
+                    unmoves.push(Move::new(double, dest, flags::MOV_DPP));
+                }
+            }
+        }
+        unmoves
+    }
+
+    fn retract(&self, mov: Move) -> Self {
+        let them = 1 ^ self.to_move();
+        let (piece, _) = self.get_piece_and_color(mov.dest());
+        let mut prev = self.clone();
+        prev.quiet_move_piece(piece, them, mov.dest(), mov.orig());
+        prev.set_en_passant(None);
+        prev.set_to_move(them);
+        prev
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::FenInterface;
+
+    #[test]
+    fn it_generates_unmoves() {
+        // white just moved: the king from one of 5 squares, the rook from
+        // one of 10 and the pawn from e3 or, as a double step, from e2
+        let pos = Position::from_fen_str("4k3/8/8/8/4P3/8/8/R3K3 b - - 0 1").unwrap();
+        let unmoves = pos.generate_unmoves();
+        assert_eq!(5 + 10 + 2, unmoves.len());
+        assert!(unmoves.contains(&Move::new(squares::E2, squares::E4, flags::MOV_DPP)));
+        assert!(unmoves.contains(&Move::new(squares::E3, squares::E4, flags::MOV_QUIET)));
+
+        let prev = pos.retract(Move::new(squares::A5, squares::A1, flags::MOV_QUIET));
+        assert_eq!("4k3/8/8/R7/4P3/8/8/4K3 w - - 0 1", prev.to_fen_string());
+        assert_eq!(
+            Position::from_fen_str("4k3/8/8/R7/4P3/8/8/4K3 w - - 0 1")
+                .unwrap()
+                .hash(),
+            prev.hash()
+        );
+    }
+
+    #[test]
+    fn it_retracts_black_pawns() {
+        let pos = Position::from_fen_str("4k3/8/8/3p4/8/2p5/8/4K3 w - - 0 1").unwrap();
+        let pawn_unmoves: Vec<Move> = pos
+            .generate_unmoves()
+            .into_iter()
+            .filter(|mov| pos.get_piece_and_color(mov.dest()).0 == piece_types::PAWN)
+            .collect();
+        assert_eq!(
+            vec![
+                Move::new(squares::C4, squares::C3, flags::MOV_QUIET),
+                Move::new(squares::D6, squares::D5, flags::MOV_QUIET),
+                Move::new(squares::D7, squares::D5, flags::MOV_DPP),
+            ],
+            pawn_unmoves
+        );
+    }
+}
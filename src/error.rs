@@ -0,0 +1,167 @@
+//! The error type of the crate
+//!
+//! Each module keeps its own error type, telling exactly what went wrong
+//! where, and all of them convert into `Error`. The public entry points
+//! reading positions, moves and files return `Error`: `from_fen_str`,
+//! `parse_san`, the move input of `Board` and the loaders of books,
+//! networks, parameters and tablebases.
+//!
+//! The `FromStr` impls, `lan`, the `from_bytes` decoders, the PGN reader and
+//! `Position::validate` keep the module errors, as these check one format
+//! and their callers match on the exact reason, e.g. the field of a FEN or
+//! the line of a PGN.
+
+use crate::engine::{BookError, NetworkError, ParamsError, PositionError, TablebaseError};
+use crate::interfaces::epd::EpdParseError;
+use crate::interfaces::fen::FenParseError;
+use crate::interfaces::lan::LanParseError;
+use crate::interfaces::pgn::PgnError;
+use crate::interfaces::san::SanParseError;
+
+use std::error;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Fen(FenParseError),
+    Epd(EpdParseError),
+    Pgn(PgnError),
+    Lan(LanParseError),
+    San(SanParseError),
+    Position(PositionError),
+    /// A square name other than `a1` to `h8`
+    InvalidSquare(String),
+    /// A move which isn't legal in the position
+    IllegalMove(String),
+    Book(BookError),
+    Network(NetworkError),
+    Params(ParamsError),
+    Tablebase(TablebaseError),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Fen(e) => write!(f, "invalid FEN: {}", e),
+            Error::Epd(e) => write!(f, "invalid EPD: {}", e),
+            Error::Pgn(e) => write!(f, "invalid PGN: {}", e),
+            Error::Lan(e) => write!(f, "{}", e),
+            Error::San(e) => write!(f, "{}", e),
+            Error::Position(e) => write!(f, "invalid position: {}", e),
+            Error::InvalidSquare(name) => write!(f, "no square {}", name),
+            Error::IllegalMove(mov) => write!(f, "illegal move {}", mov),
+            Error::Book(e) => write!(f, "{}", e),
+            Error::Network(e) => write!(f, "{}", e),
+            Error::Params(e) => write!(f, "{}", e),
+            Error::Tablebase(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Fen(e) => Some(e),
+            Error::Epd(e) => Some(e),
+            Error::Pgn(e) => Some(e),
+            Error::Lan(e) => Some(e),
+            Error::San(e) => Some(e),
+            Error::Position(e) => Some(e),
+            Error::InvalidSquare(_) | Error::IllegalMove(_) => None,
+            Error::Book(e) => Some(e),
+            Error::Network(e) => Some(e),
+            Error::Params(e) => Some(e),
+            Error::Tablebase(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+macro_rules! from_error {
+    ($($variant:ident($error:ty)),*) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Self {
+                    Error::$variant(e)
+                }
+            }
+        )*
+    };
+}
+
+from_error!(
+    Fen(FenParseError),
+    Epd(EpdParseError),
+    Pgn(PgnError),
+    Lan(LanParseError),
+    San(SanParseError),
+    Position(PositionError),
+    Book(BookError),
+    Network(NetworkError),
+    Params(ParamsError),
+    Tablebase(TablebaseError),
+    Io(io::Error)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Board, Book, Syzygy};
+    use crate::interfaces::{parse_san, FenInterface};
+    use crate::primitives::*;
+
+    use std::error::Error as StdError;
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn play(fen: &str, moves: &[&str]) -> Result<Board> {
+        let mut board = Board::from_fen_str(fen)?;
+        for mov in moves {
+            board.input_lan_move(mov)?;
+        }
+        Ok(board)
+    }
+
+    #[test]
+    fn it_converts_errors() {
+        assert!(play(STARTPOS, &["e2e4", "e7e5"]).is_ok());
+
+        let error = play("8/8/8/8 w - - 0 1", &[]).err().unwrap();
+        assert!(matches!(error, Error::Fen(_)));
+        assert!(error.source().is_some());
+
+        assert!(matches!(
+            play(STARTPOS, &["e2"]).err().unwrap(),
+            Error::Lan(LanParseError::Invalid)
+        ));
+        let error = play(STARTPOS, &["e3e4"]).err().unwrap();
+        assert!(matches!(error, Error::IllegalMove(_)));
+        assert_eq!("illegal move e3e4", error.to_string());
+
+        for name in &["i1", "a9", "a0", "e", "é1"] {
+            assert!(matches!(
+                Square::from_san_string(name),
+                Err(Error::InvalidSquare(_))
+            ));
+        }
+
+        let pos = Board::startpos();
+        assert!(matches!(
+            parse_san(pos.current(), "e5").err().unwrap(),
+            Error::San(SanParseError::Illegal)
+        ));
+        assert!(matches!(
+            Book::load("no/such/book.bin").err().unwrap(),
+            Error::Book(BookError::Io(_))
+        ));
+        assert!(matches!(
+            Syzygy::new("no/such/dir").err().unwrap(),
+            Error::Tablebase(TablebaseError::Io(_))
+        ));
+    }
+}
@@ -1,16 +1,52 @@
 use std::io;
 use std::io::Write;
 
-use crate::engine::{Board, MoveGenerator, Search};
+use crate::engine::{
+    Board, Book, EvalParams, Evaluation, MoveGenerator, Network, Search, SearchLimits, Searcher,
+    Syzygy, Tablebases,
+};
 use crate::interfaces::FenInterface;
 use crate::primitives::*;
 
+use std::slice::Iter;
+use std::sync::Arc;
+
+/// Moves to plan for when the GUI doesn't send `movestogo`
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
 pub struct UCIFrontend {
     pub board: Board,
+    searcher: Searcher,
+    network: Option<Arc<Network>>,
+    book: Option<Book>,
+    own_book: bool,
     // gen: MoveGenerator,
     run: bool,
 }
 
+/// `mov` as sent to the GUI, castling given by the king taking its own
+/// rook under `UCI_Chess960`. Only the squares of the result are meant for
+/// printing, with the rook files of `castling`, the rights at the root.
+fn uci_move(mov: Move, castling: Castling, chess960: bool) -> Move {
+    if !chess960 || !(mov.is_king_castle() || mov.is_queen_castle()) {
+        return mov;
+    }
+    let color = if mov.orig() < 8 {
+        colors::WHITE
+    } else {
+        colors::BLACK
+    };
+    let side = if mov.is_king_castle() {
+        sides::KING_SIDE
+    } else {
+        sides::QUEEN_SIDE
+    };
+    let rook = (mov.orig() & !7) + castling.rook_file(color, side);
+    let mut shown = mov;
+    shown.set_dest(rook);
+    shown
+}
+
 impl Default for UCIFrontend {
     fn default() -> Self {
         Self::new()
@@ -21,45 +57,161 @@ impl UCIFrontend {
     pub fn new() -> UCIFrontend {
         UCIFrontend {
             board: Board::new(),
+            searcher: Searcher::default(),
+            network: None,
+            book: None,
+            own_book: false,
             //gen: MoveGenerator::new(),
             run: true,
         }
     }
 
+    /// Evaluates with the parameters in `path`, or the compiled-in ones if
+    /// `path` is empty
+    pub fn load_eval_file(&mut self, path: &str) -> crate::Result<()> {
+        let params = if path.is_empty() || path == "<empty>" {
+            EvalParams::default()
+        } else {
+            EvalParams::load(path)?
+        };
+        self.searcher.set_params(params);
+        Ok(())
+    }
+
+    /// Evaluates with the network in `path`, or the handcrafted evaluation
+    /// if `path` is empty
+    pub fn load_nnue_file(&mut self, path: &str) -> crate::Result<()> {
+        self.network = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            Some(Arc::new(Network::load(path)?))
+        };
+        self.board.set_network(self.network.clone());
+        Ok(())
+    }
+
+    /// Probes the DTM tables in the directory `path` during search, or none
+    /// if `path` is empty
+    pub fn load_dtm_path(&mut self, path: &str) -> crate::Result<()> {
+        let tablebases = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            Some(Arc::new(Tablebases::load_dir(path)?))
+        };
+        self.searcher.set_tablebases(tablebases);
+        Ok(())
+    }
+
+    /// Probes the Syzygy tables in the directories listed in `path`, or none
+    /// if `path` is empty
+    pub fn load_syzygy_path(&mut self, path: &str) -> crate::Result<()> {
+        let syzygy = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            Some(Arc::new(Syzygy::new(path)?))
+        };
+        self.searcher.set_syzygy(syzygy);
+        Ok(())
+    }
+
+    /// Plays from the Polyglot book in `path` while `OwnBook` is set, or
+    /// from no book if `path` is empty
+    pub fn load_book_file(&mut self, path: &str) -> crate::Result<()> {
+        self.book = if path.is_empty() || path == "<empty>" {
+            None
+        } else {
+            Some(Book::load(path)?)
+        };
+        Ok(())
+    }
+
+    /// `mov` in the current position as sent to the GUI
+    fn uci_move(&self, mov: Move) -> Move {
+        uci_move(mov, self.board.current().castling(), self.board.chess960())
+    }
+
+    fn cmd_setoption(&mut self, cmd: Vec<&str>) {
+        // setoption name <id> [value <x>], both may contain spaces
+        let value_pos = cmd.iter().position(|&t| t == "value").unwrap_or(cmd.len());
+        let name = cmd.get(1..value_pos).map_or(String::new(), |t| t.join(" "));
+        let value = cmd
+            .get(value_pos + 1..)
+            .map_or(String::new(), |t| t.join(" "));
+
+        match name.as_str() {
+            "EvalFile" => {
+                if let Err(e) = self.load_eval_file(&value) {
+                    println!("info string Could not load {}: {}", value, e);
+                }
+            }
+            "NNUEFile" => {
+                if let Err(e) = self.load_nnue_file(&value) {
+                    println!("info string Could not load {}: {}", value, e);
+                }
+            }
+            "DTMPath" => {
+                if let Err(e) = self.load_dtm_path(&value) {
+                    println!("info string Could not load {}: {}", value, e);
+                }
+            }
+            "SyzygyPath" => {
+                if let Err(e) = self.load_syzygy_path(&value) {
+                    println!("info string Could not load {}: {}", value, e);
+                }
+            }
+            "OwnBook" => self.own_book = value == "true",
+            "UCI_Chess960" => self.board.set_chess960(value == "true"),
+            "BookFile" => {
+                if let Err(e) = self.load_book_file(&value) {
+                    println!("info string Could not load {}: {}", value, e);
+                }
+            }
+            _ => eprintln!("Unknown option: {}", name),
+        }
+    }
+
     fn cmd_position(&mut self, cmd: Vec<&str>) {
         if cmd.is_empty() {
             return;
         }
-        let mut tokens = cmd.iter();
+        // the FEN runs up to the moves, if there are any
+        let moves = cmd.iter().position(|&t| t == "moves").unwrap_or(cmd.len());
 
-        if let Some(postype) = tokens.next() {
-            match *postype {
-                "startpos" => self.board = Board::startpos(),
-                "fen" => {
-                    let s = Board::from_fen_str(&cmd[1..7].join(" "));
-                    match s {
-                        Ok(b) => self.board = b,
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                            return;
-                        }
-                    }
-                    for _ in 0..6 {
-                        tokens.next();
-                    }
+        // the board is only replaced once all moves turned out legal
+        let mut board = match cmd[0] {
+            "startpos" => Board::startpos(),
+            "fen" => match Board::from_fen_str(&cmd[1..moves].join(" ")) {
+                Ok(b) => b,
+                Err(e) => {
+                    println!("info string Invalid FEN: {}", e);
+                    return;
                 }
-                _ => (),
-            }
+            },
+            _ => self.board.clone(),
+        };
+        board.set_chess960(self.board.chess960());
+        board.set_network(self.network.clone());
+
+        if Self::make_moves(&mut board, cmd.iter().skip(moves + 1)) {
+            self.board = board;
         }
+    }
 
-        if tokens.next().is_some() {
-            for mov in tokens {
-                match self.board.input_san_move(mov) {
-                    Ok(_) => {}
-                    Err(e) => println!("{:?}", e),
+    /// Plays the `moves` on `board`, false if one of them is illegal
+    fn make_moves<'a, I>(board: &mut Board, moves: I) -> bool
+    where
+        I: Iterator<Item = &'a &'a str>,
+    {
+        for mov in moves {
+            match board.parse_uci_move(mov) {
+                Ok(mov) => board.make_move(mov),
+                Err(e) => {
+                    println!("info string Invalid move {}: {}", mov, e);
+                    return false;
                 }
             }
         }
+        true
     }
 
     fn cmd_move(&mut self, cmd: Vec<&str>) {
@@ -67,11 +219,9 @@ impl UCIFrontend {
             return;
         }
 
-        for mov in cmd.iter() {
-            match self.board.input_san_move(mov) {
-                Ok(_) => {}
-                Err(e) => println!("{:?}", e),
-            }
+        let mut board = self.board.clone();
+        if Self::make_moves(&mut board, cmd.iter()) {
+            self.board = board;
         }
     }
 
@@ -137,6 +287,79 @@ impl UCIFrontend {
         println!("{} moves, {} nodes", moves.len(), nodes);
     }
 
+    fn cmd_go(&mut self, cmd: Vec<&str>) {
+        fn value<T: std::str::FromStr>(tokens: &mut Iter<&str>) -> Option<T> {
+            tokens.next().and_then(|token| token.parse::<T>().ok())
+        }
+
+        let mut limits = SearchLimits::default();
+        let (mut time, mut inc, mut movestogo): (Option<u64>, u64, u64) =
+            (None, 0, DEFAULT_MOVES_TO_GO);
+        let to_move = self.board.current().to_move();
+
+        let mut tokens = cmd.iter();
+        while let Some(token) = tokens.next() {
+            match *token {
+                "depth" => limits.depth = value(&mut tokens),
+                "nodes" => limits.nodes = value(&mut tokens),
+                "movetime" => limits.movetime = value(&mut tokens),
+                "mate" => limits.mate = value(&mut tokens),
+                "movestogo" => movestogo = value(&mut tokens).unwrap_or(movestogo).max(1),
+                "wtime" | "btime" => {
+                    let t = value(&mut tokens);
+                    if (*token == "wtime") == (to_move == colors::WHITE) {
+                        time = t;
+                    }
+                }
+                "winc" | "binc" => {
+                    let i = value(&mut tokens);
+                    if (*token == "winc") == (to_move == colors::WHITE) {
+                        inc = i.unwrap_or(0);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let (true, Some(book)) = (self.own_book, &self.book) {
+            if let Some(mov) = book.pick(&mut self.board, &mut rand::thread_rng()) {
+                println!("bestmove {}", self.uci_move(mov));
+                return;
+            }
+        }
+
+        if let (None, Some(time)) = (limits.movetime, time) {
+            limits.movetime = Some((time / movestogo + inc / 2).min(time * 9 / 10));
+        }
+
+        let castling = self.board.current().castling();
+        let chess960 = self.board.chess960();
+        let info = self.searcher.search(&mut self.board, limits, |info| {
+            let mut info = info.clone();
+            for mov in info.pv.iter_mut() {
+                *mov = uci_move(*mov, castling, chess960);
+            }
+            println!("info {}", info)
+        });
+        match info.best_move() {
+            Some(mov) => println!("bestmove {}", self.uci_move(mov)),
+            None => println!("bestmove 0000"),
+        }
+    }
+
+    fn cmd_eval(&self) {
+        let pos = self.board.current();
+        println!("{}", pos.eval_trace_with(self.searcher.params()));
+        if let Some(score) = self.board.evaluate_network() {
+            let score = if pos.to_move() == colors::WHITE {
+                score
+            } else {
+                -score
+            };
+            println!("NNUE evaluation: {} cp (white side)", score);
+        }
+    }
+
     pub fn parse(&mut self, cmd: String) {
         let tokens: Vec<&str> = cmd.trim().split_whitespace().collect();
 
@@ -150,12 +373,23 @@ impl UCIFrontend {
                 "p" | "perft" => self.cmd_perft(tokens[1..].to_vec()),
                 "d" | "divide" => self.cmd_divide(tokens[1..].to_vec()),
                 "g" | "generate" => self.cmd_moves(),
+                "eval" => self.cmd_eval(),
                 "uci" => {
                     println!("id name deeprust v{}", env!("CARGO_PKG_VERSION"));
                     println!("id author {}", env!("CARGO_PKG_AUTHORS"));
+                    println!("option name EvalFile type string default <empty>");
+                    println!("option name NNUEFile type string default <empty>");
+                    println!("option name DTMPath type string default <empty>");
+                    println!("option name SyzygyPath type string default <empty>");
+                    println!("option name OwnBook type check default false");
+                    println!("option name BookFile type string default <empty>");
+                    println!("option name UCI_Chess960 type check default false");
                     println!("uciok");
                 }
                 "isready" => println!("readyok"),
+                "setoption" => self.cmd_setoption(tokens[1..].to_vec()),
+                "ucinewgame" => self.searcher.clear(),
+                "go" => self.cmd_go(tokens[1..].to_vec()),
                 "quit" | "q" => self.run = false,
                 unknown => eprintln!("Unknown command: {}", unknown),
             }
@@ -213,6 +447,151 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_loads_eval_files() {
+        let path = std::env::temp_dir().join("deeprust-uci-evalfile.params");
+        std::fs::write(&path, "doubled: -50,-60\n").unwrap();
+
+        let mut c = UCIFrontend::new();
+        c.parse(format!("setoption name EvalFile value {}", path.display()));
+        assert_eq!(-50, c.searcher.params().doubled.mg);
+
+        // a broken file keeps the current parameters
+        std::fs::write(&path, "doubled: x\n").unwrap();
+        c.parse(format!("setoption name EvalFile value {}", path.display()));
+        assert_eq!(-50, c.searcher.params().doubled.mg);
+
+        c.parse(String::from("setoption name EvalFile value <empty>"));
+        assert_eq!(&EvalParams::default(), c.searcher.params());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn it_keeps_the_evaluation_on_broken_networks() {
+        let path = std::env::temp_dir().join("deeprust-uci-nnuefile.nnue");
+        std::fs::write(&path, "DRNN").unwrap();
+
+        let mut c = UCIFrontend::new();
+        c.parse(format!("setoption name NNUEFile value {}", path.display()));
+        c.parse(String::from("position startpos moves e2e4"));
+        assert!(c.board.network().is_none());
+
+        c.parse(String::from("setoption name NNUEFile value <empty>"));
+        assert!(c.network.is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn it_plays_from_the_book() {
+        use crate::engine::{polyglot_key, BookEntry};
+
+        let path = std::env::temp_dir().join("deeprust-uci-bookfile.bin");
+        let entry = BookEntry {
+            key: polyglot_key(Board::startpos().current()),
+            mov: (squares::E2 as u16) << 6 | squares::E4 as u16,
+            weight: 1,
+            learn: 0,
+        };
+        std::fs::write(&path, entry.to_bytes()).unwrap();
+
+        let mut c = UCIFrontend::new();
+        c.parse(format!("setoption name BookFile value {}", path.display()));
+        assert_eq!(1, c.book.as_ref().unwrap().len());
+        assert!(!c.own_book);
+        c.parse(String::from("setoption name OwnBook value true"));
+        assert!(c.own_book);
+
+        c.parse(String::from("setoption name BookFile value <empty>"));
+        assert!(c.book.is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn it_castles_in_chess960() {
+        let mut c = UCIFrontend::new();
+        c.parse(String::from("setoption name UCI_Chess960 value true"));
+        assert!(c.board.chess960());
+
+        // the king stays on g1, or lands where the rook stood
+        c.parse(String::from(
+            "position fen 1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1 moves g1h1",
+        ));
+        assert_eq!(
+            "1r4kr/8/8/8/8/8/8/1R3RK1 b kq - 1 1",
+            c.board.to_fen_string()
+        );
+        c.parse(String::from(
+            "position fen 1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1 moves g1b1 g8h8",
+        ));
+        assert_eq!(
+            "1r3rk1/8/8/8/8/8/8/2KR3R w - - 2 2",
+            c.board.to_fen_string()
+        );
+        // the black king would pass d8, attacked by the rook on d1
+        c.parse(String::from(
+            "position fen 1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1 moves g1b1 g8b8",
+        ));
+        assert_eq!(
+            "1r3rk1/8/8/8/8/8/8/2KR3R w - - 2 2",
+            c.board.to_fen_string()
+        );
+        c.parse(String::from(
+            "position fen 4k3/8/8/8/8/8/8/1KR5 w C - 0 1 moves b1c1",
+        ));
+        assert_eq!("4k3/8/8/8/8/8/8/5RK1 b - - 1 1", c.board.to_fen_string());
+        c.parse(String::from(
+            "position fen 4k3/8/8/8/8/8/8/2R1K3 w C - 0 1 moves e1c1",
+        ));
+        assert_eq!("4k3/8/8/8/8/8/8/2KR4 b - - 1 1", c.board.to_fen_string());
+
+        // and never as the king moving two squares
+        c.parse(String::from(
+            "position fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1g1",
+        ));
+        assert_eq!("4k3/8/8/8/8/8/8/2KR4 b - - 1 1", c.board.to_fen_string());
+        c.parse(String::from(
+            "position fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1h1",
+        ));
+        assert_eq!(
+            "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1",
+            c.board.to_fen_string()
+        );
+
+        // castles are sent as the king taking its own rook
+        c.parse(String::from(
+            "position fen 1r4kr/8/8/8/8/8/8/1R4KR w HBhb - 0 1",
+        ));
+        let castles: Vec<String> = c
+            .board
+            .generate_moves()
+            .into_iter()
+            .filter(|m| m.is_king_castle() || m.is_queen_castle())
+            .map(|m| c.uci_move(m).to_string())
+            .collect();
+        assert_eq!(vec!["g1h1", "g1b1"], castles);
+    }
+
+    #[test]
+    fn it_ignores_illegal_moves() {
+        let mut c = UCIFrontend::new();
+        c.parse(String::from("position startpos moves e2e4"));
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(fen, c.board.to_fen_string());
+
+        // the board stays as it was, not at the last legal move
+        c.parse(String::from("position startpos moves d2d4 e7e5 e7e4"));
+        assert_eq!(fen, c.board.to_fen_string());
+        assert_eq!(1, c.board.history().len());
+        c.parse(String::from("position fen 8/8/8 w - - 0 1"));
+        assert_eq!(fen, c.board.to_fen_string());
+        c.parse(String::from("move e7e5 e1e3"));
+        assert_eq!(fen, c.board.to_fen_string());
+        assert_eq!(1, c.board.history().len());
+
+        c.parse(String::from("move e7e5 g1f3"));
+        assert_eq!(3, c.board.history().len());
+    }
+
     // TODO: make full UCI commands
     // #[test]
     fn it_handles_moves() {
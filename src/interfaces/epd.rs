@@ -0,0 +1,418 @@
+//! Extended Position Description
+//!
+//! An EPD record is a position, given by the first four fields of a FEN,
+//! followed by operations: an opcode and its operands, each operation ended
+//! by a semicolon. The opcodes test suites are made of are read into typed
+//! operations, all others are kept as they were written. The `hmvc` and
+//! `fmvn` operations set the move counters of the position.
+//!
+//! Reading is lenient about the variants found in the wild: the two move
+//! counters may follow the position as in a FEN, semicolons may stand before
+//! the operations instead of after them, and perft counts may be written
+//! with the bare depth, `1 20` for `D1 20`.
+
+use crate::engine::{Position, Score};
+use crate::interfaces::fen::{self, FenErrorKind, FenField, FenParseError};
+use crate::interfaces::san::{self, SanParseError};
+use crate::interfaces::FenInterface;
+use crate::primitives::*;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdParseError {
+    Empty,
+    InvalidPosition(FenParseError),
+    UnterminatedString,
+    InvalidOperands(String),
+    IllegalMove { san: String, error: SanParseError },
+}
+
+impl fmt::Display for EpdParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdParseError::Empty => write!(f, "empty record"),
+            EpdParseError::InvalidPosition(error) => write!(f, "invalid position: {}", error),
+            EpdParseError::UnterminatedString => write!(f, "unterminated string"),
+            EpdParseError::InvalidOperands(opcode) => write!(f, "invalid operands of {}", opcode),
+            EpdParseError::IllegalMove { san, error } => write!(f, "{}: {}", san, error),
+        }
+    }
+}
+
+impl Error for EpdParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// `bm`, the best moves
+    BestMoves(Vec<Move>),
+    /// `am`, the moves to avoid
+    AvoidMoves(Vec<Move>),
+    /// `id`, the name of the record
+    Id(String),
+    /// `c0` to `c9`, comments
+    Comment(u8, String),
+    /// `D1` to `Dn`, the number of leaf nodes at a depth
+    Perft(u32, u64),
+    /// `acd`, the depth of an analysis
+    Depth(u32),
+    /// `ce`, the evaluation in centipawns for the side to move
+    Eval(Score),
+    Other(String, Vec<String>),
+}
+
+/// A position with its operations
+#[derive(Clone)]
+pub struct Epd {
+    pub position: Position,
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn new(position: Position) -> Epd {
+        Epd {
+            position,
+            operations: Vec::new(),
+        }
+    }
+
+    /// The name given by the `id` operation
+    pub fn id(&self) -> Option<&str> {
+        self.operations.iter().find_map(|op| match op {
+            Operation::Id(id) => Some(id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The moves of the `bm` operation
+    pub fn best_moves(&self) -> &[Move] {
+        self.operations
+            .iter()
+            .find_map(|op| match op {
+                Operation::BestMoves(moves) => Some(moves.as_slice()),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    /// The moves of the `am` operation
+    pub fn avoid_moves(&self) -> &[Move] {
+        self.operations
+            .iter()
+            .find_map(|op| match op {
+                Operation::AvoidMoves(moves) => Some(moves.as_slice()),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    /// The depths and leaf node counts of the `Dn` operations
+    pub fn perft(&self) -> Vec<(u32, u64)> {
+        self.operations
+            .iter()
+            .filter_map(|op| match *op {
+                Operation::Perft(depth, nodes) => Some((depth, nodes)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The first field of `text` and what follows it, stopping at semicolons
+fn next_field(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == ';')
+        .unwrap_or(text.len());
+    if end == 0 {
+        None
+    } else {
+        Some(text.split_at(end))
+    }
+}
+
+/// Splits operations into their opcodes and operands, with the quotes
+/// around string operands removed
+fn split_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdParseError> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    operations.push((opcode, std::mem::take(&mut words)));
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(EpdParseError::UnterminatedString),
+                    }
+                }
+                words.push(string);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+    Ok(operations)
+}
+
+/// The single number `operands` hold
+fn number<T: FromStr>(opcode: &str, operands: &[String]) -> Result<T, EpdParseError> {
+    match operands {
+        [operand] => operand
+            .parse()
+            .map_err(|_| EpdParseError::InvalidOperands(opcode.to_string())),
+        _ => Err(EpdParseError::InvalidOperands(opcode.to_string())),
+    }
+}
+
+fn parse_operation(
+    pos: &Position,
+    opcode: String,
+    operands: Vec<String>,
+) -> Result<Operation, EpdParseError> {
+    let moves = |operands: &[String]| {
+        operands
+            .iter()
+            .map(|operand| {
+                san::read_san(pos, operand).map_err(|error| EpdParseError::IllegalMove {
+                    san: operand.clone(),
+                    error,
+                })
+            })
+            .collect::<Result<Vec<Move>, EpdParseError>>()
+    };
+    let digits = opcode.strip_prefix('D').unwrap_or(&opcode);
+    let comment = opcode
+        .strip_prefix('c')
+        .filter(|n| n.len() == 1)
+        .and_then(|n| n.parse::<u8>().ok());
+
+    Ok(match opcode.as_str() {
+        "bm" => Operation::BestMoves(moves(&operands)?),
+        "am" => Operation::AvoidMoves(moves(&operands)?),
+        "id" => Operation::Id(operands.join(" ")),
+        "acd" => Operation::Depth(number(&opcode, &operands)?),
+        "ce" => Operation::Eval(number(&opcode, &operands)?),
+        _ => {
+            if let Some(n) = comment {
+                Operation::Comment(n, operands.join(" "))
+            } else if let Ok(depth) = digits.parse() {
+                Operation::Perft(depth, number(&opcode, &operands)?)
+            } else {
+                Operation::Other(opcode, operands)
+            }
+        }
+    })
+}
+
+impl FromStr for Epd {
+    type Err = EpdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err(EpdParseError::Empty);
+        }
+        let mut fields = Vec::new();
+        let mut rest = s;
+        while fields.len() < 4 {
+            match next_field(rest) {
+                Some((field, after)) => {
+                    fields.push(field);
+                    rest = after;
+                }
+                None => {
+                    let field = [
+                        FenField::Placement,
+                        FenField::ToMove,
+                        FenField::Castling,
+                        FenField::EnPassant,
+                    ][fields.len()];
+                    let missing = FenParseError {
+                        field,
+                        offset: s.len(),
+                        kind: FenErrorKind::Missing,
+                    };
+                    return Err(EpdParseError::InvalidPosition(missing));
+                }
+            }
+        }
+        // move counters written as in a FEN
+        let mut counters = ("0", "1");
+        if let Some((halfmoves, after)) = next_field(rest) {
+            if let Some((fullmoves, after)) = next_field(after) {
+                if halfmoves.parse::<u32>().is_ok() && fullmoves.parse::<u32>().is_ok() {
+                    counters = (halfmoves, fullmoves);
+                    rest = after;
+                }
+            }
+        }
+
+        let fen = format!("{} {} {}", fields.join(" "), counters.0, counters.1);
+        let mut position = fen::read_fen(&fen).map_err(EpdParseError::InvalidPosition)?;
+        let mut operations = Vec::new();
+        for (opcode, operands) in split_operations(rest)? {
+            match opcode.as_str() {
+                "hmvc" => position.set_halfmoves(number(&opcode, &operands)?),
+                "fmvn" => position.set_fullmoves(number(&opcode, &operands)?),
+                _ => operations.push(parse_operation(&position, opcode, operands)?),
+            }
+        }
+        Ok(Epd {
+            position,
+            operations,
+        })
+    }
+}
+
+/// `operand` as written in a record, quoted if it has to be
+fn write_operand(f: &mut fmt::Formatter, operand: &str) -> fmt::Result {
+    if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';') {
+        write!(f, " \"{}\"", operand.replace('"', ""))
+    } else {
+        write!(f, " {}", operand)
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fen = self.position.to_fen_string();
+        let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+        f.write_str(&fields.join(" "))?;
+        if self.position.halfmoves() != 0 {
+            write!(f, " hmvc {};", self.position.halfmoves())?;
+        }
+        if self.position.fullmoves() != 1 {
+            write!(f, " fmvn {};", self.position.fullmoves())?;
+        }
+
+        for op in &self.operations {
+            match op {
+                Operation::BestMoves(moves) | Operation::AvoidMoves(moves) => {
+                    let opcode = match op {
+                        Operation::BestMoves(_) => "bm",
+                        _ => "am",
+                    };
+                    write!(f, " {}", opcode)?;
+                    for &mov in moves {
+                        write!(f, " {}", san::to_san(&self.position, mov))?;
+                    }
+                }
+                Operation::Id(id) => write!(f, " id \"{}\"", id.replace('"', ""))?,
+                Operation::Comment(n, text) => write!(f, " c{} \"{}\"", n, text.replace('"', ""))?,
+                Operation::Perft(depth, nodes) => write!(f, " D{} {}", depth, nodes)?,
+                Operation::Depth(depth) => write!(f, " acd {}", depth)?,
+                Operation::Eval(score) => write!(f, " ce {}", score)?,
+                Operation::Other(opcode, operands) => {
+                    write!(f, " {}", opcode)?;
+                    for operand in operands {
+                        write_operand(f, operand)?;
+                    }
+                }
+            }
+            f.write_str(";")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_and_writes_records() {
+        let line = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - \
+                    hmvc 2; fmvn 3; bm Bb5 Bc4; am Ng5; id \"Test; one\"; c0 \"Ruy or Italian\"; \
+                    acd 12; ce 35; pv Bb5 a6;";
+        let epd: Epd = line.parse().unwrap();
+        assert_eq!(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            epd.position.to_fen_string()
+        );
+        assert_eq!(Some("Test; one"), epd.id());
+        let best: Vec<String> = epd.best_moves().iter().map(|m| m.to_string()).collect();
+        assert_eq!(vec!["f1b5", "f1c4"], best);
+        assert_eq!("f3g5", epd.avoid_moves()[0].to_string());
+        assert_eq!(
+            &Operation::Comment(0, "Ruy or Italian".to_string()),
+            &epd.operations[3]
+        );
+        assert_eq!(&Operation::Depth(12), &epd.operations[4]);
+        assert_eq!(&Operation::Eval(35), &epd.operations[5]);
+        assert_eq!(
+            &Operation::Other("pv".to_string(), vec!["Bb5".to_string(), "a6".to_string()]),
+            &epd.operations[6]
+        );
+
+        let written = epd.to_string();
+        assert_eq!(line, written);
+        assert_eq!(written, written.parse::<Epd>().unwrap().to_string());
+    }
+
+    #[test]
+    fn it_reads_perft_suites() {
+        let epd: Epd = "4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;1 15 ;2 66 ;D3 1197"
+            .parse()
+            .unwrap();
+        assert_eq!(vec![(1, 15), (2, 66), (3, 1197)], epd.perft());
+        assert_eq!(
+            "4k3/8/8/8/8/8/8/4K2R w K - D1 15; D2 66; D3 1197;",
+            epd.to_string()
+        );
+
+        assert_eq!(Err(EpdParseError::Empty), " ".parse::<Epd>().map(|_| ()));
+        let errors = [
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - D1 1;",
+                EpdParseError::InvalidPosition(FenParseError {
+                    field: FenField::ToMove,
+                    offset: 20,
+                    kind: FenErrorKind::Invalid,
+                }),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - id \"x",
+                EpdParseError::UnterminatedString,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - D1 x;",
+                EpdParseError::InvalidOperands("D1".into()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - acd;",
+                EpdParseError::InvalidOperands("acd".into()),
+            ),
+        ];
+        for (line, error) in errors.iter() {
+            assert_eq!(Err(error.clone()), line.parse::<Epd>().map(|_| ()));
+        }
+        assert!(matches!(
+            "4k3/8/8/8/8/8/8/4K3 w - - bm Ke3 Ra1;".parse::<Epd>(),
+            Err(EpdParseError::IllegalMove { .. })
+        ));
+    }
+}
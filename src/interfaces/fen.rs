@@ -7,11 +7,12 @@ use crate::primitives::*;
 use crate::engine::Board;
 // use crate::color::*;
 // use crate::common::BitTwiddling;
-use crate::engine::Position;
+use crate::engine::{BitboardPrimitives, Position, PositionError};
 
 //use regex::Regex;
 // use pest::Parser;
 
+use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 use std::string::String;
@@ -21,71 +22,12 @@ use nom::IResult;
 use nom::{
     branch::alt,
     bytes::complete::{is_a, tag, take},
-    character::complete::{digit1, multispace1, one_of},
+    character::complete::{digit1, one_of},
     combinator::{map, map_res, peek},
-    multi::{many1, separated_nonempty_list},
+    multi::many1,
     sequence::{preceded, tuple},
 };
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub struct ParsedFen {
-    pub placement: Vec<Piece>,
-    pub to_move: Color,
-    pub castling: Castling,
-    pub ep_target: Option<Square>,
-    pub halfmoves: u32,
-    pub fullmoves: u32,
-}
-
-pub fn fen(input: &str) -> IResult<&str, ParsedFen> {
-    let result = tuple((
-        placement,
-        multispace1,
-        to_move,
-        multispace1,
-        castling,
-        multispace1,
-        ep_target,
-        multispace1,
-        halfmoves,
-        multispace1,
-        fullmoves,
-    ))(input);
-
-    match result {
-        Ok(tup) => {
-            let (
-                rest,
-                (placement, _, to_move, _, castling, _, ep_target, _, halfmoves, _, fullmoves),
-            ) = tup;
-            if placement.len() != 64 {
-                panic!(
-                    "Invalid FEN string, found {} squares when there should be 64",
-                    placement.len()
-                );
-            }
-            Ok((
-                rest,
-                ParsedFen {
-                    placement,
-                    to_move,
-                    castling,
-                    ep_target,
-                    halfmoves,
-                    fullmoves,
-                },
-            ))
-        }
-        Err(e) => Err(e),
-    }
-}
-
-fn placement(input: &str) -> IResult<&str, Vec<u8>> {
-    map(separated_nonempty_list(tag("/"), rank), |l| {
-        l.into_iter().rev().flatten().collect::<Vec<u8>>()
-    })(input)
-}
-
 fn rank(input: &str) -> IResult<&str, Vec<u8>> {
     map(many1(alt((empty_square, occupied_square))), |l| {
         l.into_iter().flatten().collect()
@@ -106,23 +48,59 @@ fn to_move(input: &str) -> IResult<&str, Color> {
     map(one_of("bw"), ColorPrimitives::from_char)(input)
 }
 
-fn castling(input: &str) -> IResult<&str, Castling> {
-    let (rest, c) = alt((tag("-"), is_a("KQkq")))(input)?;
+fn castling(input: &str) -> IResult<&str, &str> {
+    alt((tag("-"), is_a("KQkqABCDEFGHabcdefgh")))(input)
+}
 
-    //let mut cast = [0_u32; 2];
-    let mut cast = Castling::empty();
+/// The color, side and rook file of a castling right written as `chr`,
+/// which may name the rook as in X-FEN, where `K` and `Q` stand for the
+/// outermost rook on either side of the king, or by its file as in
+/// Shredder-FEN. `None` if there's no rook for `K` or `Q` to stand for.
+fn castling_right(pos: &Position, chr: char) -> Option<(Color, CastlingSide, u32)> {
+    let color = if chr.is_ascii_uppercase() {
+        colors::WHITE
+    } else {
+        colors::BLACK
+    };
+    let back_rank = 7 * color as u32;
+    let is_rook = |file: u32| {
+        pos.get_piece_and_color(Square::from_coords(file, back_rank)) == (piece_types::ROOK, color)
+    };
+    // a missing king is left to `Position::validate`
+    let king = pos.bb_king(color);
+    let king_file = if king == 0 { 4 } else { king.scan() & 7 };
+
+    match chr.to_ascii_lowercase() {
+        'k' => (king_file + 1..8)
+            .rev()
+            .find(|&file| is_rook(file))
+            .map(|file| (color, sides::KING_SIDE, file)),
+        'q' => (0..king_file)
+            .find(|&file| is_rook(file))
+            .map(|file| (color, sides::QUEEN_SIDE, file)),
+        letter => {
+            let file = letter as u32 - 'a' as u32;
+            let side = if file > king_file {
+                sides::KING_SIDE
+            } else {
+                sides::QUEEN_SIDE
+            };
+            Some((color, side, file))
+        }
+    }
+}
 
-    for chr in c.chars() {
-        match chr {
-            '-' => {}
-            'K' => cast.set(colors::WHITE, sides::KING_SIDE),
-            'Q' => cast.set(colors::WHITE, sides::QUEEN_SIDE),
-            'k' => cast.set(colors::BLACK, sides::KING_SIDE),
-            'q' => cast.set(colors::BLACK, sides::QUEEN_SIDE),
-            _ => unreachable!("Internal parser error: castling"), // _ => return Err(Error),
+/// The castling rights of a castling field, see `castling_right`. Fails
+/// with the index of the first right without a rook.
+fn castling_rights(pos: &Position, field: &str) -> Result<Castling, usize> {
+    let mut cast = Castling::empty();
+    for (idx, chr) in field.char_indices().filter(|&(_, c)| c != '-') {
+        match castling_right(pos, chr) {
+            Some((color, side, file)) => cast.set_with_rook(color, side, file),
+            None => return Err(idx),
         }
     }
-    Ok((rest, cast))
+    Ok(cast)
 }
 
 fn ep_square(input: &str) -> IResult<&str, &str> {
@@ -153,59 +131,269 @@ fn fullmoves(input: &str) -> IResult<&str, u32> {
     preceded(peek(one_of("123456789")), halfmoves)(input)
 }
 
-pub fn parse(input: &str) -> IResult<&str, ParsedFen> {
-    fen(input)
+/// The fields of a FEN string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Placement,
+    ToMove,
+    Castling,
+    EnPassant,
+    Halfmoves,
+    Fullmoves,
 }
 
-#[derive(Debug, Clone)]
-pub enum FenParseError {
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FenField::Placement => "piece placement",
+            FenField::ToMove => "side to move",
+            FenField::Castling => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::Halfmoves => "halfmove clock",
+            FenField::Fullmoves => "fullmove number",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenErrorKind {
     Empty,
+    Missing,
     Invalid,
-    // ParserError(Box<nom::error::ParseError>),
+    /// The number of ranks, if not eight
+    RankCount(usize),
+    /// The number of squares of a rank, if not eight
+    RankLength(usize),
+    /// The number of kings of a color, if not one
+    KingCount(Color, usize),
+    PawnOnBackRank,
+    /// An en passant square no pawn can just have passed
+    ImpossibleEnPassant,
+    /// A castling right without its king or rook on the back rank
+    CastlingWithoutPieces(char),
+    /// The side not to move is in check
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenErrorKind::Empty => write!(f, "empty FEN"),
+            FenErrorKind::Missing => write!(f, "missing"),
+            FenErrorKind::Invalid => write!(f, "invalid"),
+            FenErrorKind::RankCount(ranks) => write!(f, "{} ranks instead of 8", ranks),
+            FenErrorKind::RankLength(squares) => {
+                write!(f, "rank of {} squares instead of 8", squares)
+            }
+            FenErrorKind::KingCount(color, kings) => write!(
+                f,
+                "{} {} kings instead of 1",
+                kings,
+                if *color == colors::WHITE {
+                    "white"
+                } else {
+                    "black"
+                }
+            ),
+            FenErrorKind::PawnOnBackRank => write!(f, "pawn on the first or last rank"),
+            FenErrorKind::ImpossibleEnPassant => write!(f, "no pawn can have just passed it"),
+            FenErrorKind::CastlingWithoutPieces(chr) => {
+                write!(f, "no king and rook at home for {}", chr)
+            }
+            FenErrorKind::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+/// Why a FEN string was rejected, in which field and at which byte offset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenParseError {
+    pub field: FenField,
+    pub offset: usize,
+    pub kind: FenErrorKind,
+}
+
+impl FenParseError {
+    fn new(field: FenField, offset: usize, kind: FenErrorKind) -> Self {
+        FenParseError {
+            field,
+            offset,
+            kind,
+        }
+    }
 }
 
 impl fmt::Display for FenParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{} at offset {}: {}", self.field, self.offset, self.kind)
     }
 }
 
-pub trait FenInterface<T = Self> {
-    type Err;
-    fn from_fen_str(s: &str) -> Result<T, Self::Err>;
-    fn to_fen_string(&self) -> String;
+impl Error for FenParseError {}
+
+/// The fields of `s` with their byte offsets
+fn fields(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    s.split_ascii_whitespace()
+        .map(move |field| (field.as_ptr() as usize - s.as_ptr() as usize, field))
 }
 
-impl FenInterface for Position {
-    type Err = FenParseError;
+/// Reads the next field with `parser`, which has to consume all of it
+fn read_field<'a, T>(
+    fields: &mut impl Iterator<Item = (usize, &'a str)>,
+    end: usize,
+    field: FenField,
+    parser: impl Fn(&'a str) -> IResult<&'a str, T>,
+) -> Result<(usize, &'a str, T), FenParseError> {
+    let (offset, text) = fields
+        .next()
+        .ok_or_else(|| FenParseError::new(field, end, FenErrorKind::Missing))?;
+    match parser(text) {
+        Ok(("", value)) => Ok((offset, text, value)),
+        Ok((rest, _)) => Err(FenParseError::new(
+            field,
+            offset + text.len() - rest.len(),
+            FenErrorKind::Invalid,
+        )),
+        Err(_) => Err(FenParseError::new(field, offset, FenErrorKind::Invalid)),
+    }
+}
 
-    fn from_fen_str(s: &str) -> Result<Self, FenParseError> {
-        if s.is_empty() {
-            return Err(FenParseError::Empty);
-        }
+/// The squares of a placement field starting at `offset`, from a1 to h8,
+/// checking the ranks have eight each
+fn read_placement(offset: usize, text: &str) -> Result<Vec<Piece>, FenParseError> {
+    let error = |offset, kind| FenParseError::new(FenField::Placement, offset, kind);
+    let ranks: Vec<&str> = text.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(error(offset, FenErrorKind::RankCount(ranks.len())));
+    }
 
-        let parser = fen(s);
-        let result = match parser {
-            Ok((_, res)) => res,
-            Err(_e) => return Err(FenParseError::Invalid),
+    let mut squares_by_rank = Vec::with_capacity(8);
+    let mut rank_offset = offset;
+    for text in ranks {
+        let squares = match rank(text) {
+            Ok(("", squares)) => squares,
+            Ok((rest, _)) => {
+                let at = rank_offset + text.len() - rest.len();
+                return Err(error(at, FenErrorKind::Invalid));
+            }
+            Err(_) => return Err(error(rank_offset, FenErrorKind::Invalid)),
         };
+        if squares.len() != 8 {
+            return Err(error(rank_offset, FenErrorKind::RankLength(squares.len())));
+        }
+        squares_by_rank.push(squares);
+        rank_offset += text.len() + 1;
+    }
+    Ok(squares_by_rank.into_iter().rev().flatten().collect())
+}
 
-        // position
-        let mut position = Self::new();
-        position.set_to_move(result.to_move);
-        position.set_castling(result.castling);
-        position.set_en_passant(result.ep_target);
-        position.set_halfmoves(result.halfmoves);
-        position.set_fullmoves(result.fullmoves);
-
-        for i in 0..64 {
-            if result.placement[i] != piece_types::EMPTY {
-                let piece = result.placement[i] as Piece;
-                position.set_piece(piece.code(), piece.color(), i as Square);
-            }
+/// Reads the six fields of a FEN, ignoring anything after them, and
+/// checks the position could come up in a game
+pub(crate) fn read_fen(s: &str) -> Result<Position, FenParseError> {
+    if s.trim().is_empty() {
+        return Err(FenParseError::new(
+            FenField::Placement,
+            0,
+            FenErrorKind::Empty,
+        ));
+    }
+
+    let mut fields = fields(s);
+    let end = s.len();
+    let (placement_offset, placement_field) = fields.next().unwrap();
+    let placement = read_placement(placement_offset, placement_field)?;
+    let (to_move_offset, _, to_move) = read_field(&mut fields, end, FenField::ToMove, to_move)?;
+    let (castling_offset, castling_field, _) =
+        read_field(&mut fields, end, FenField::Castling, castling)?;
+    let (ep_offset, _, ep_target) = read_field(&mut fields, end, FenField::EnPassant, ep_target)?;
+    let (_, _, halfmoves) = read_field(&mut fields, end, FenField::Halfmoves, halfmoves)?;
+    let (_, _, fullmoves) = read_field(&mut fields, end, FenField::Fullmoves, fullmoves)?;
+
+    // position
+    let mut position = Position::new();
+    for (i, &piece) in placement.iter().enumerate() {
+        if piece != piece_types::EMPTY {
+            position.set_piece(piece.code(), piece.color(), i as Square);
+        }
+    }
+    position.set_to_move(to_move);
+    position.set_en_passant(ep_target);
+    position.set_halfmoves(halfmoves);
+    position.set_fullmoves(fullmoves);
+
+    let castling_error = |idx: usize| {
+        FenParseError::new(
+            FenField::Castling,
+            castling_offset + idx,
+            FenErrorKind::CastlingWithoutPieces(castling_field[idx..].chars().next().unwrap()),
+        )
+    };
+    let castling = castling_rights(&position, castling_field).map_err(castling_error)?;
+    position.set_castling(castling);
+
+    // the rest is up to the position, pointed back to the fields
+    position.validate().map_err(|e| match e {
+        PositionError::KingCount(color, kings) => FenParseError::new(
+            FenField::Placement,
+            placement_offset,
+            FenErrorKind::KingCount(color, kings as usize),
+        ),
+        PositionError::PawnOnBackRank(square) => {
+            // ranks are written from the eighth down
+            let rank_offset: usize = placement_field
+                .split('/')
+                .take(7 - square as usize / 8)
+                .map(|rank| rank.len() + 1)
+                .sum();
+            FenParseError::new(
+                FenField::Placement,
+                placement_offset + rank_offset,
+                FenErrorKind::PawnOnBackRank,
+            )
         }
+        PositionError::CastlingRights(color, side) => {
+            let idx = castling_field
+                .char_indices()
+                .find(|&(_, chr)| {
+                    castling_right(&position, chr)
+                        .is_some_and(|(c, s, _)| (c, s) == (color, side))
+                })
+                .map_or(0, |(idx, _)| idx);
+            castling_error(idx)
+        }
+        PositionError::EnPassant(_) => FenParseError::new(
+            FenField::EnPassant,
+            ep_offset,
+            FenErrorKind::ImpossibleEnPassant,
+        ),
+        // a placement puts one piece per square
+        PositionError::Inconsistent(_) | PositionError::OverlappingColors(_) => {
+            FenParseError::new(FenField::Placement, placement_offset, FenErrorKind::Invalid)
+        }
+    })?;
+
+    if position.is_in_check(1 ^ to_move) {
+        return Err(FenParseError::new(
+            FenField::ToMove,
+            to_move_offset,
+            FenErrorKind::OpponentInCheck,
+        ));
+    }
+    Ok(position)
+}
 
-        Ok(position)
+pub trait FenInterface<T = Self> {
+    type Err;
+    fn from_fen_str(s: &str) -> Result<T, Self::Err>;
+    fn to_fen_string(&self) -> String;
+}
+
+impl FenInterface for Position {
+    type Err = crate::Error;
+
+    fn from_fen_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(read_fen(s)?)
     }
 
     fn to_fen_string(&self) -> String {
@@ -247,21 +435,37 @@ impl FenInterface for Position {
 
         // Castling rights
         fen_string.push(' ');
+        // as in X-FEN, rooks are only named by their files if they aren't
+        // the outermost ones
         let castling = self.castling();
         if castling.is_empty() {
             fen_string.push('-');
-        } else {
-            if castling.get(colors::WHITE, sides::KING_SIDE) {
-                fen_string.push('K');
-            }
-            if castling.get(colors::WHITE, sides::QUEEN_SIDE) {
-                fen_string.push('Q');
-            }
-            if castling.get(colors::BLACK, sides::KING_SIDE) {
-                fen_string.push('k');
-            }
-            if castling.get(colors::BLACK, sides::QUEEN_SIDE) {
-                fen_string.push('q');
+        }
+        for &color in [colors::WHITE, colors::BLACK].iter() {
+            for &side in [sides::KING_SIDE, sides::QUEEN_SIDE].iter() {
+                if !castling.get(color, side) {
+                    continue;
+                }
+                let file = castling.rook_file(color, side);
+                let outer_files = if side == sides::KING_SIDE {
+                    file + 1..8
+                } else {
+                    0..file
+                };
+                let outermost = outer_files.into_iter().all(|outer| {
+                    self.get_piece_and_color(Square::from_coords(outer, 7 * u32::from(color)))
+                        != (piece_types::ROOK, color)
+                });
+                let chr = if outermost {
+                    ['K', 'Q'][side as usize]
+                } else {
+                    squares::FILE_NAMES[file as usize].chars().next().unwrap()
+                };
+                fen_string.push(if color == colors::WHITE {
+                    chr.to_ascii_uppercase()
+                } else {
+                    chr.to_ascii_lowercase()
+                });
             }
         }
 
@@ -287,21 +491,13 @@ impl FenInterface for Position {
 }
 
 impl FenInterface for Board {
-    type Err = FenParseError;
+    type Err = crate::Error;
 
     fn from_fen_str(s: &str) -> Result<Board, Self::Err> {
-        if s.is_empty() {
-            return Err(FenParseError::Empty);
-        }
-
+        let position = Position::from_fen_str(s)?;
         let mut board = Self::new();
-        match Position::from_fen_str(s) {
-            Ok(p) => {
-                board.set_position(&p);
-                Ok(board)
-            }
-            Err(_e) => Err(FenParseError::Invalid),
-        }
+        board.set_position(&position);
+        Ok(board)
     }
 
     fn to_fen_string(&self) -> String {
@@ -12,6 +12,8 @@ use nom::{
 };
 
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
 
 //type IResult<I, O, E = (I, ErrorKind)> = Result<(I, O), Err<E>>;
@@ -23,6 +25,17 @@ pub enum LanParseError {
     // ParserError(Box<nom::error::ParseError>),
 }
 
+impl fmt::Display for LanParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LanParseError::Empty => write!(f, "empty move"),
+            LanParseError::Invalid => write!(f, "not a move in coordinate notation"),
+        }
+    }
+}
+
+impl Error for LanParseError {}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Needed {
     Unknown,
@@ -1,7 +1,10 @@
-// pub mod error;
+pub mod epd;
 pub mod fen;
 pub mod lan;
+pub mod pgn;
+pub mod san;
 // pub mod uci;
 
 pub use crate::interfaces::fen::FenInterface;
 pub use crate::interfaces::lan::lan;
+pub use crate::interfaces::san::{parse_san, to_san};
@@ -0,0 +1,853 @@
+//! Reading and writing games in Portable Game Notation
+//!
+//! `PgnReader` parses one game at a time from any buffered reader, so
+//! databases of any size can be walked through without loading them. Games
+//! keep their tag pairs and their moves as written, with comments, NAGs and
+//! recursive variations attached to the moves they follow. A game which
+//! can't be parsed comes out as an error carrying the line number, and
+//! reading goes on with the next game.
+//!
+//! Moves are only checked when a game is replayed onto a `Board`.
+//!
+//! Games are written in export format by their `Display` implementation.
+//! `PgnGame::from_board` turns the moves played on a `Board` into a game with
+//! the Seven Tag Roster, and `PgnMove::annotate` adds the `[%eval]` and
+//! `[%clk]` commands most GUIs show alongside the moves.
+
+use crate::engine::{Board, Score, ScorePrimitives};
+use crate::interfaces::fen::{self, FenParseError};
+use crate::interfaces::san::{self, SanParseError};
+use crate::interfaces::FenInterface;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum PgnErrorKind {
+    Io(io::Error),
+    Syntax(&'static str),
+    InvalidFen { fen: String, error: FenParseError },
+    IllegalMove { san: String, error: SanParseError },
+}
+
+/// An error in a game, at the line where it was found
+#[derive(Debug)]
+pub struct PgnError {
+    pub line: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            PgnErrorKind::Io(e) => write!(f, "{}", e),
+            PgnErrorKind::Syntax(what) => write!(f, "{}", what),
+            PgnErrorKind::InvalidFen { fen, error } => write!(f, "invalid FEN {}: {}", fen, error),
+            PgnErrorKind::IllegalMove { san, error } => write!(f, "{}: {}", san, error),
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    #[default]
+    Unfinished,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unfinished => "*",
+        })
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(GameResult::WhiteWins),
+            "0-1" => Ok(GameResult::BlackWins),
+            "1/2-1/2" => Ok(GameResult::Draw),
+            "*" => Ok(GameResult::Unfinished),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A move as written in the movetext
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnMove {
+    pub san: String,
+    pub line: usize,
+    /// Numeric annotation glyphs, with `!`, `?` and the like turned into
+    /// their codes 1 to 6
+    pub nags: Vec<u8>,
+    /// The comments after the move, joined by spaces
+    pub comment: Option<String>,
+    /// Alternatives to this move, each a line of moves of its own
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    /// Adds `[%eval]` and `[%clk]` commands to the comment, with `score` in
+    /// centipawns from white's point of view and `clock` the time left to
+    /// the mover
+    pub fn annotate(&mut self, score: Option<Score>, clock: Option<Duration>) {
+        let mut commands = Vec::new();
+        if let Some(score) = score {
+            commands.push(match score.mate_moves() {
+                Some(moves) => format!("[%eval #{}]", moves),
+                None => format!("[%eval {:.2}]", f64::from(score) / 100.0),
+            });
+        }
+        if let Some(clock) = clock {
+            let secs = clock.as_secs();
+            commands.push(format!(
+                "[%clk {}:{:02}:{:02}]",
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60
+            ));
+        }
+        if commands.is_empty() {
+            return;
+        }
+        let commands = commands.join(" ");
+        self.comment = Some(match self.comment.take() {
+            Some(comment) => format!("{} {}", commands, comment),
+            None => commands,
+        });
+    }
+}
+
+/// The tags every exported game carries, with their values when unknown
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+/// Movetext lines are kept this short, as the standard asks
+const MAX_LINE_LENGTH: usize = 79;
+
+/// A game as read from PGN
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    /// The tag pairs in the order they were written
+    pub tags: Vec<(String, String)>,
+    /// A comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    /// The game termination marker, or else the `Result` tag
+    pub result: GameResult,
+    /// The line the game starts on
+    pub line: usize,
+}
+
+impl PgnGame {
+    /// The game played on `board`, with the Seven Tag Roster unknown but for
+    /// the result. Games not starting from the initial position get `SetUp`
+    /// and `FEN` tags.
+    pub fn from_board(board: &Board, result: GameResult) -> PgnGame {
+        let mut game = PgnGame {
+            tags: SEVEN_TAG_ROSTER
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            result,
+            ..PgnGame::default()
+        };
+        game.set_tag("Result", &result.to_string());
+
+        let mut pos = board.root().clone();
+        let fen = pos.to_fen_string();
+        if fen != Board::startpos().current().to_fen_string() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game.moves = board
+            .history()
+            .iter()
+            .map(|&mov| {
+                let san = san::to_san(&pos, mov);
+                pos.make_move(mov);
+                PgnMove {
+                    san,
+                    ..PgnMove::default()
+                }
+            })
+            .collect();
+        game
+    }
+
+    /// Sets the tag `name`, keeping its place if it is already there
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// The value of the tag `name`
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, the `FEN` tag if there is one
+    pub fn start(&self) -> Result<Board, PgnError> {
+        match self.tag("FEN") {
+            Some(fen) => match fen::read_fen(fen) {
+                Ok(position) => {
+                    let mut board = Board::new();
+                    board.set_position(&position);
+                    Ok(board)
+                }
+                Err(error) => Err(PgnError {
+                    line: self.line,
+                    kind: PgnErrorKind::InvalidFen {
+                        fen: fen.to_string(),
+                        error,
+                    },
+                }),
+            },
+            None => Ok(Board::startpos()),
+        }
+    }
+
+    /// Plays the main line of the game from its start
+    pub fn replay(&self) -> Result<Board, PgnError> {
+        let mut board = self.start()?;
+        for mov in &self.moves {
+            match san::read_san(board.current(), &mov.san) {
+                Ok(parsed) => board.make_move(parsed),
+                Err(error) => {
+                    return Err(PgnError {
+                        line: mov.line,
+                        kind: PgnErrorKind::IllegalMove {
+                            san: mov.san.clone(),
+                            error,
+                        },
+                    })
+                }
+            }
+        }
+        Ok(board)
+    }
+
+    /// Plies played before the first move, from the `FEN` tag
+    fn first_ply(&self) -> usize {
+        let fields: Vec<&str> = self
+            .tag("FEN")
+            .map(|fen| fen.split_whitespace().collect())
+            .unwrap_or_default();
+        let fullmoves = fields
+            .get(5)
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(1);
+        2 * (fullmoves - 1) + usize::from(fields.get(1) == Some(&"b"))
+    }
+}
+
+/// Adds the words of a comment in braces to `tokens`
+fn push_comment(tokens: &mut Vec<String>, comment: &str) {
+    let comment = comment.replace('}', "");
+    let mut words: Vec<String> = comment.split_whitespace().map(String::from).collect();
+    if let Some(last) = words.last_mut() {
+        last.push('}');
+        words[0].insert(0, '{');
+        tokens.extend(words);
+    }
+}
+
+/// Adds the movetext of `moves`, the first played at `ply`, to `tokens`
+fn push_moves(tokens: &mut Vec<String>, moves: &[PgnMove], mut ply: usize) {
+    // black moves get their number at the start and after interruptions
+    let mut interrupted = true;
+    for mov in moves {
+        if ply.is_multiple_of(2) {
+            tokens.push(format!("{}.", ply / 2 + 1));
+        } else if interrupted {
+            tokens.push(format!("{}...", ply / 2 + 1));
+        }
+        tokens.push(mov.san.clone());
+        tokens.extend(mov.nags.iter().map(|nag| format!("${}", nag)));
+        interrupted = false;
+        if let Some(comment) = &mov.comment {
+            push_comment(tokens, comment);
+            interrupted = true;
+        }
+        for variation in &mov.variations {
+            let mut inner = Vec::new();
+            push_moves(&mut inner, variation, ply);
+            if let Some(last) = inner.last_mut() {
+                last.push(')');
+                inner[0].insert(0, '(');
+                tokens.extend(inner);
+                interrupted = true;
+            }
+        }
+        ply += 1;
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(f)?;
+        }
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            push_comment(&mut tokens, comment);
+        }
+        push_moves(&mut tokens, &self.moves, self.first_ply());
+        tokens.push(self.result.to_string());
+
+        let mut length = 0;
+        for token in tokens {
+            if length > 0 && length + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f)?;
+                length = 0;
+            } else if length > 0 {
+                f.write_str(" ")?;
+                length += 1;
+            }
+            f.write_str(&token)?;
+            length += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+/// Annotation suffixes and the NAGs they stand for
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+    ("!", 1),
+    ("?", 2),
+];
+
+/// State of the game being read
+#[derive(Default)]
+struct GameParser {
+    game: PgnGame,
+    /// The main line and the variations opened inside it
+    lines: Vec<Vec<PgnMove>>,
+    /// The unfinished comment, when inside braces
+    comment: Option<String>,
+    /// Where the unfinished comment and variations were opened
+    opened_at: Vec<usize>,
+    has_movetext: bool,
+    error: Option<PgnError>,
+}
+
+impl GameParser {
+    fn new(line: usize) -> GameParser {
+        GameParser {
+            game: PgnGame {
+                line,
+                ..PgnGame::default()
+            },
+            lines: vec![Vec::new()],
+            ..GameParser::default()
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.game.tags.is_empty() && !self.has_movetext
+    }
+
+    fn fail(&mut self, line: usize, what: &'static str) {
+        if self.error.is_none() {
+            self.error = Some(PgnError {
+                line,
+                kind: PgnErrorKind::Syntax(what),
+            });
+        }
+    }
+
+    fn add_comment(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        let depth = self.lines.len();
+        let target = match self.lines.last_mut().and_then(|moves| moves.last_mut()) {
+            Some(mov) => &mut mov.comment,
+            None if depth == 1 => &mut self.game.comment,
+            // comments opening a variation are dropped
+            None => return,
+        };
+        match target {
+            Some(comment) => {
+                comment.push(' ');
+                comment.push_str(text);
+            }
+            None => *target = Some(text.to_string()),
+        }
+    }
+
+    fn add_nag(&mut self, line: usize, nag: u8) {
+        match self.lines.last_mut().and_then(|moves| moves.last_mut()) {
+            Some(mov) => mov.nags.push(nag),
+            None => self.fail(line, "annotation before the first move"),
+        }
+    }
+
+    fn parse_tag(&mut self, line: usize, text: &str) {
+        let text = text.trim();
+        let inner = match text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            Some(inner) => inner.trim(),
+            None => return self.fail(line, "tag pair without brackets"),
+        };
+        let (name, value) = match inner.find(char::is_whitespace) {
+            Some(split) => (&inner[..split], inner[split..].trim()),
+            None => return self.fail(line, "tag pair without a value"),
+        };
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(value) => value.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => return self.fail(line, "tag value without quotes"),
+        };
+        if name == "Result" {
+            if let Ok(result) = value.parse() {
+                self.game.result = result;
+            }
+        }
+        self.game.tags.push((name.to_string(), value));
+    }
+
+    /// Reads movetext, returning true at the game termination marker
+    fn parse_movetext(&mut self, line: usize, text: &str) -> bool {
+        self.has_movetext = true;
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(comment) = &mut self.comment {
+                match rest.find('}') {
+                    Some(end) => {
+                        comment.push(' ');
+                        comment.push_str(&rest[..end]);
+                        let comment = self.comment.take().unwrap();
+                        self.opened_at.pop();
+                        self.add_comment(&comment);
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        comment.push(' ');
+                        comment.push_str(rest);
+                        return false;
+                    }
+                }
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap();
+            let token_end = rest
+                .find(|c: char| c.is_whitespace() || "{}();[]".contains(c))
+                .unwrap_or(rest.len());
+            let (token, after) = if token_end == 0 {
+                rest.split_at(c.len_utf8())
+            } else {
+                rest.split_at(token_end)
+            };
+            rest = after;
+
+            match c {
+                c if c.is_whitespace() => (),
+                '{' => {
+                    self.comment = Some(String::new());
+                    self.opened_at.push(line);
+                }
+                ';' => {
+                    self.add_comment(rest);
+                    return false;
+                }
+                '(' => {
+                    if self.lines.last().is_none_or(|moves| moves.is_empty()) {
+                        self.fail(line, "variation before the first move");
+                    }
+                    self.lines.push(Vec::new());
+                    self.opened_at.push(line);
+                }
+                ')' => {
+                    if self.lines.len() == 1 {
+                        self.fail(line, "unmatched closing parenthesis");
+                        continue;
+                    }
+                    let variation = self.lines.pop().unwrap();
+                    self.opened_at.pop();
+                    if let Some(mov) = self.lines.last_mut().and_then(|moves| moves.last_mut()) {
+                        mov.variations.push(variation);
+                    }
+                }
+                '}' | '[' | ']' => self.fail(line, "unexpected bracket"),
+                '$' => match token[1..].parse() {
+                    Ok(nag) => self.add_nag(line, nag),
+                    Err(_) => self.fail(line, "invalid NAG"),
+                },
+                _ => {
+                    if let Ok(result) = token.parse::<GameResult>() {
+                        if self.lines.len() > 1 {
+                            self.fail(line, "game ends inside a variation");
+                        }
+                        self.game.result = result;
+                        return true;
+                    }
+                    self.parse_move(line, token);
+                }
+            }
+        }
+        false
+    }
+
+    fn parse_move(&mut self, line: usize, token: &str) {
+        // move numbers, possibly run together with the move
+        let mut san = token;
+        if san.starts_with(|c: char| c.is_ascii_digit()) && !san.starts_with("0-0") {
+            let digits = san.trim_start_matches(|c: char| c.is_ascii_digit());
+            if !digits.starts_with('.') {
+                return self.fail(line, "invalid move number");
+            }
+            san = digits.trim_start_matches('.');
+        }
+        if san.is_empty() {
+            return;
+        }
+
+        let stripped = san.trim_end_matches(['!', '?']);
+        let suffix = &san[stripped.len()..];
+        if stripped.is_empty() {
+            // an annotation standing on its own
+            return match SUFFIX_NAGS.iter().find(|(s, _)| *s == suffix) {
+                Some(&(_, nag)) => self.add_nag(line, nag),
+                None => self.fail(line, "invalid annotation"),
+            };
+        }
+        let mut mov = PgnMove {
+            san: stripped.to_string(),
+            line,
+            ..PgnMove::default()
+        };
+        if !suffix.is_empty() {
+            match SUFFIX_NAGS.iter().find(|(s, _)| *s == suffix) {
+                Some(&(_, nag)) => mov.nags.push(nag),
+                None => return self.fail(line, "invalid annotation"),
+            }
+        }
+        self.lines.last_mut().unwrap().push(mov);
+    }
+
+    fn finish(mut self) -> Result<PgnGame, PgnError> {
+        if let Some(&opened) = self.opened_at.last() {
+            if self.comment.is_some() {
+                self.fail(opened, "unterminated comment");
+            } else {
+                self.fail(opened, "unterminated variation");
+            }
+        }
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        self.game.moves = mem::take(&mut self.lines[0]);
+        Ok(self.game)
+    }
+}
+
+/// Reads the games of a PGN file one by one
+pub struct PgnReader<R> {
+    reader: R,
+    line: usize,
+    /// A line read ahead, which starts the next game
+    pending: Option<String>,
+    done: bool,
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader {
+            reader,
+            line: 0,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// The next line, without its line break. Bytes which aren't UTF-8, as
+    /// in the many files written in Latin-1, are replaced.
+    fn read_line(&mut self) -> Option<io::Result<String>> {
+        let mut bytes = Vec::new();
+        match self.reader.read_until(b'\n', &mut bytes) {
+            Ok(0) => None,
+            Ok(_) => {
+                self.line += 1;
+                let text = String::from_utf8_lossy(&bytes);
+                Some(Ok(text.trim_end_matches(&['\n', '\r'][..]).to_string()))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut parser = GameParser::new(self.line + 1);
+
+        loop {
+            let text = match self.pending.take() {
+                Some(text) => {
+                    self.line += 1;
+                    text
+                }
+                None => match self.read_line() {
+                    Some(Ok(text)) => text,
+                    Some(Err(e)) => {
+                        self.done = true;
+                        return Some(Err(PgnError {
+                            line: self.line + 1,
+                            kind: PgnErrorKind::Io(e),
+                        }));
+                    }
+                    None => {
+                        self.done = true;
+                        return if parser.is_empty() {
+                            None
+                        } else {
+                            Some(parser.finish())
+                        };
+                    }
+                },
+            };
+
+            let trimmed = text.trim_start();
+            if parser.comment.is_none() {
+                // blank and escaped lines
+                if trimmed.is_empty() || text.starts_with('%') {
+                    continue;
+                }
+                if parser.is_empty() {
+                    parser.game.line = self.line;
+                }
+                if parser.lines.len() == 1 && trimmed.starts_with('[') {
+                    if parser.has_movetext {
+                        // a new game begins without the last one having ended
+                        self.pending = Some(text);
+                        self.line -= 1;
+                        return Some(parser.finish());
+                    }
+                    parser.parse_tag(self.line, trimmed);
+                    continue;
+                }
+            }
+            if parser.parse_movetext(self.line, &text) {
+                return Some(parser.finish());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(text: &str) -> Vec<Result<PgnGame, PgnError>> {
+        PgnReader::new(text.as_bytes()).collect()
+    }
+
+    #[test]
+    fn it_reads_games() {
+        let pgn = r#"
+[Event "Casual \"blitz\""]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3!? (2. f4 exf4 (2... d5) 3. Nf3) Nc6 $14 {A
+comment over two lines} 3.Bb5 a6?! ; rest of line
+% escaped line
+4. Ba4 1-0
+
+1. d4 d5 *
+"#;
+        let games = read_all(pgn);
+        assert_eq!(2, games.len());
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(2, game.line);
+        assert_eq!(Some("Casual \"blitz\""), game.tag("Event"));
+        assert_eq!(GameResult::WhiteWins, game.result);
+        assert_eq!(Some("Opening comment"), game.comment.as_deref());
+        let sans: Vec<&str> = game.moves.iter().map(|mov| mov.san.as_str()).collect();
+        assert_eq!(vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"], sans);
+
+        let nf3 = &game.moves[2];
+        assert_eq!(vec![5], nf3.nags);
+        assert_eq!(1, nf3.variations.len());
+        let variation = &nf3.variations[0];
+        assert_eq!(3, variation.len());
+        assert_eq!("d5", variation[1].variations[0][0].san);
+        let nc6 = &game.moves[3];
+        assert_eq!(vec![14], nc6.nags);
+        assert_eq!(Some("A comment over two lines"), nc6.comment.as_deref());
+        assert_eq!(6, game.moves[4].line);
+        assert_eq!(vec![6], game.moves[5].nags);
+        assert_eq!(Some("rest of line"), game.moves[5].comment.as_deref());
+
+        let board = game.replay().unwrap();
+        assert_eq!(7, board.history().len());
+
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(10, game.line);
+        assert!(game.tags.is_empty());
+        assert_eq!(GameResult::Unfinished, game.result);
+        assert_eq!(2, game.moves.len());
+    }
+
+    #[test]
+    fn it_reports_errors_per_game() {
+        let pgn = r#"[Event "one"]
+
+1. e4 (1. d4 e5 2. Qxe5 *
+
+[Event "two"]
+[Result "0-1"]
+[FEN "4k3/8/8/8/8/8/8/4K2R w K - 0 1"]
+
+1. O-O Kd7 2. Rf8 Ke6 3. Ra1 0-1
+
+[Event "three"]
+1. e4 e5 2. Ke3
+[Event "four"]
+1. e4
+"#;
+        let games = read_all(pgn);
+        assert_eq!(4, games.len());
+
+        let error = games[0].as_ref().unwrap_err();
+        assert_eq!(3, error.line);
+        assert!(matches!(error.kind, PgnErrorKind::Syntax(_)));
+
+        // legal moves are only checked when replaying
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(GameResult::BlackWins, game.result);
+        let error = game.replay().unwrap_err();
+        assert_eq!(9, error.line);
+        match &error.kind {
+            PgnErrorKind::IllegalMove { san, error } => {
+                assert_eq!("Ra1", san);
+                assert_eq!(&SanParseError::Illegal, error);
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        // a game without a termination marker ends at the next tag pair
+        let game = games[2].as_ref().unwrap();
+        assert_eq!(Some("three"), game.tag("Event"));
+        assert_eq!(3, game.moves.len());
+        assert_eq!(13, games[3].as_ref().unwrap().line);
+
+        assert_eq!(0, read_all("\n\n").len());
+        let games = read_all("1. e4 (1. d4\n{never closed\n\n2. c4");
+        assert_eq!(2, games[0].as_ref().unwrap_err().line);
+        let games = read_all("1. e4 (1. d4\n2. c4");
+        assert_eq!(1, games[0].as_ref().unwrap_err().line);
+    }
+
+    #[test]
+    fn it_writes_games() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K2R b K - 3 20";
+        let mut board = Board::from_fen_str(fen).unwrap();
+        for san in ["Kd7", "O-O", "Ke6", "e4"].iter() {
+            board.input_san_move(san).unwrap();
+        }
+        let mut game = PgnGame::from_board(&board, GameResult::WhiteWins);
+        game.set_tag("White", "deeprust");
+        game.moves[0].annotate(Some(-35), Some(Duration::from_secs(65)));
+        game.moves[1].annotate(Some(crate::engine::mate_in(5)), None);
+
+        let pgn = game.to_string();
+        assert_eq!(
+            r#"[Event "?"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "deeprust"]
+[Black "?"]
+[Result "1-0"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K2R b K - 3 20"]
+
+20... Kd7 {[%eval -0.35] [%clk 0:01:05]} 21. O-O {[%eval #3]} 21... Ke6 22. e4
+1-0
+"#,
+            pgn
+        );
+
+        let games = read_all(&pgn);
+        let read = games[0].as_ref().unwrap();
+        assert_eq!(
+            board.to_fen_string(),
+            read.replay().unwrap().to_fen_string()
+        );
+        assert_eq!(pgn, read.to_string());
+        let pgn = PgnGame::from_board(&Board::startpos(), GameResult::Unfinished).to_string();
+        assert!(!pgn.contains("FEN"));
+        assert!(pgn.ends_with("\n\n*\n"));
+    }
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let pgn = r#"[Event "Casual \"blitz\""]
+{Opening comment} 1. e4 e5 2. Nf3!? (2. f4 exf4 (2... d5) 3. Nf3) Nc6 $14 {A
+comment over two lines} 3.Bb5 a6?! 4. Ba4 1-0
+"#;
+        let game = read_all(pgn).remove(0).unwrap();
+        let written = game.to_string();
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(written.starts_with("[Event \"Casual \\\"blitz\\\"\"]\n\n{Opening"));
+        assert!(written.contains("2. Nf3 $5 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $14"));
+
+        let read = read_all(&written).remove(0).unwrap();
+        assert_eq!(game.tags, read.tags);
+        assert_eq!(game.comment, read.comment);
+        assert_eq!(written, read.to_string());
+    }
+}
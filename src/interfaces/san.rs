@@ -0,0 +1,308 @@
+//! Standard algebraic notation
+//!
+//! Moves are written the way PGN expects them: the piece letter, as little
+//! of the origin square as tells the move apart from the other legal ones,
+//! `x` for captures, the destination, `=Q` for promotions, `O-O` and `O-O-O`
+//! for castling and `+` or `#` after checks and mates.
+//!
+//! Parsing is lenient about what doesn't change the move: check marks,
+//! annotations like `!?`, a missing `x` or `=`, a `-` between the squares,
+//! castling written with zeros and superfluous disambiguation are all
+//! accepted.
+
+use crate::engine::Position;
+use crate::primitives::*;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanParseError {
+    Empty,
+    Invalid,
+    Illegal,
+    Ambiguous,
+}
+
+impl fmt::Display for SanParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanParseError::Empty => write!(f, "empty move"),
+            SanParseError::Invalid => write!(f, "not a move in algebraic notation"),
+            SanParseError::Illegal => write!(f, "no legal move matches"),
+            SanParseError::Ambiguous => write!(f, "more than one legal move matches"),
+        }
+    }
+}
+
+impl Error for SanParseError {}
+
+fn piece_letter(piece: Piece) -> &'static str {
+    Piece::new(piece, colors::WHITE).to_san_string()
+}
+
+fn piece_type(c: char) -> Option<Piece> {
+    match c {
+        'N' => Some(piece_types::KNIGHT),
+        'B' => Some(piece_types::BISHOP),
+        'R' => Some(piece_types::ROOK),
+        'Q' => Some(piece_types::QUEEN),
+        'K' => Some(piece_types::KING),
+        _ => None,
+    }
+}
+
+/// `mov`, a legal move in `pos`, in standard algebraic notation
+pub fn to_san(pos: &Position, mov: Move) -> String {
+    let mut san = if mov.is_king_castle() {
+        String::from("O-O")
+    } else if mov.is_queen_castle() {
+        String::from("O-O-O")
+    } else {
+        let (orig, dest) = (mov.orig(), mov.dest());
+        let piece = pos.get_piece_and_color(orig).0;
+        let mut san = String::new();
+
+        if piece == piece_types::PAWN {
+            if mov.is_capture() {
+                san.push_str(squares::FILE_NAMES[(orig & 7) as usize]);
+            }
+        } else {
+            san.push_str(piece_letter(piece));
+            let others: Vec<Square> = pos
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.dest() == dest
+                        && other.orig() != orig
+                        && pos.get_piece_and_color(other.orig()).0 == piece
+                })
+                .map(|other| other.orig())
+                .collect();
+            if !others.is_empty() {
+                let orig_name = orig.to_san_string();
+                if others.iter().all(|other| other & 7 != orig & 7) {
+                    san.push_str(&orig_name[..1]);
+                } else if others.iter().all(|other| other >> 3 != orig >> 3) {
+                    san.push_str(&orig_name[1..]);
+                } else {
+                    san.push_str(&orig_name);
+                }
+            }
+        }
+
+        if mov.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&dest.to_san_string());
+        if mov.is_promotion() {
+            san.push('=');
+            san.push_str(piece_letter(mov.prom_piece_code()));
+        }
+        san
+    };
+
+    let mut next = pos.clone();
+    next.make_move(mov);
+    if next.is_in_check(next.to_move()) {
+        san.push(if next.legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+/// The legal move of `pos` written as `san`
+pub fn parse_san(pos: &Position, san: &str) -> crate::Result<Move> {
+    Ok(read_san(pos, san)?)
+}
+
+/// `parse_san` telling why `san` is no legal move of `pos`
+pub(crate) fn read_san(pos: &Position, san: &str) -> Result<Move, SanParseError> {
+    let san = san.trim();
+    if san.is_empty() {
+        return Err(SanParseError::Empty);
+    }
+    let san = san
+        .trim_end_matches(|c| "+#!?".contains(c))
+        .trim_end_matches("e.p.")
+        .trim_end();
+    let moves = pos.legal_moves();
+
+    let castling = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(king_side) = castling {
+        return moves
+            .into_iter()
+            .find(|mov| {
+                if king_side {
+                    mov.is_king_castle()
+                } else {
+                    mov.is_queen_castle()
+                }
+            })
+            .ok_or(SanParseError::Illegal);
+    }
+
+    // [piece] [file] [rank] [x] square [[=] promotion]
+    let mut chars: Vec<char> = san.chars().filter(|&c| !"x-=".contains(c)).collect();
+    let piece = match chars.first().and_then(|&c| piece_type(c)) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => piece_types::PAWN,
+    };
+    let promotion = match chars
+        .last()
+        .and_then(|&c| piece_type(c.to_ascii_uppercase()))
+    {
+        Some(promotion) if piece == piece_types::PAWN && promotion != piece_types::KING => {
+            chars.pop();
+            Some(promotion)
+        }
+        _ => None,
+    };
+    let file_of = |c: char| match c {
+        'a'..='h' => Some(c as u32 - 'a' as u32),
+        _ => None,
+    };
+    let rank_of = |c: char| match c {
+        '1'..='8' => Some(c as u32 - '1' as u32),
+        _ => None,
+    };
+    let n = chars.len();
+    if !(2..=4).contains(&n) {
+        return Err(SanParseError::Invalid);
+    }
+    let dest = match (file_of(chars[n - 2]), rank_of(chars[n - 1])) {
+        (Some(file), Some(rank)) => Square::from_coords(file, rank),
+        _ => return Err(SanParseError::Invalid),
+    };
+    let mut prefix = chars[..n - 2].iter().peekable();
+    let from_file = prefix.peek().and_then(|&&c| file_of(c));
+    if from_file.is_some() {
+        prefix.next();
+    }
+    let from_rank = prefix.peek().and_then(|&&c| rank_of(c));
+    if from_rank.is_some() {
+        prefix.next();
+    }
+    if prefix.next().is_some() {
+        return Err(SanParseError::Invalid);
+    }
+
+    let mut candidates = moves.into_iter().filter(|mov| {
+        mov.dest() == dest
+            && pos.get_piece_and_color(mov.orig()).0 == piece
+            && from_file.is_none_or(|file| mov.orig() & 7 == file)
+            && from_rank.is_none_or(|rank| mov.orig() >> 3 == rank)
+            && mov.is_promotion() == promotion.is_some()
+            && promotion.is_none_or(|p| mov.prom_piece_code() == p)
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(mov), None) => Ok(mov),
+        (Some(_), Some(_)) => Err(SanParseError::Ambiguous),
+        _ => Err(SanParseError::Illegal),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Board;
+    use crate::interfaces::FenInterface;
+
+    fn san_of(fen: &str, lan: &str) -> String {
+        let pos = Position::from_fen_str(fen).unwrap();
+        let mov = pos
+            .legal_moves()
+            .into_iter()
+            .find(|mov| mov.to_string() == lan)
+            .unwrap();
+        to_san(&pos, mov)
+    }
+
+    #[test]
+    fn it_writes_san() {
+        let knights = "7k/8/8/8/8/8/8/1N1NK3 w - - 0 1";
+        assert_eq!("Nbc3", san_of(knights, "b1c3"));
+        assert_eq!("Ne3", san_of(knights, "d1e3"));
+        assert_eq!("R1a3", san_of("7k/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"));
+        let queens = "1k6/8/8/8/4Q2Q/8/K7/7Q w - - 0 1";
+        assert_eq!("Qh4e1", san_of(queens, "h4e1"));
+        assert_eq!("Qee1", san_of(queens, "e4e1"));
+
+        assert_eq!("exd6", san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"));
+        assert_eq!("e8=Q+", san_of("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q"));
+        assert_eq!("e8=N", san_of("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8n"));
+        let mate = "6k1/5ppp/8/8/8/8/8/R3K3 w Q - 0 1";
+        assert_eq!("Ra8#", san_of(mate, "a1a8"));
+        assert_eq!("O-O-O", san_of(mate, "e1c1"));
+    }
+
+    #[test]
+    fn it_parses_what_it_writes() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "1k6/8/8/8/4Q2Q/8/K7/7Q w - - 0 1",
+        ]
+        .iter()
+        {
+            let pos = Position::from_fen_str(fen).unwrap();
+            let moves = pos.legal_moves();
+            let sans: Vec<String> = moves.iter().map(|&mov| to_san(&pos, mov)).collect();
+            for (&mov, san) in moves.iter().zip(sans.iter()) {
+                assert_eq!(Ok(mov), read_san(&pos, san), "{} in {}", san, fen);
+                assert_eq!(1, sans.iter().filter(|&other| other == san).count());
+            }
+        }
+    }
+
+    #[test]
+    fn it_parses_lenient_san() {
+        let pos = Position::from_fen_str("7k/8/8/8/8/8/8/1N1NK3 w - - 0 1").unwrap();
+        let nbc3 = read_san(&pos, "Nbc3").unwrap();
+        assert_eq!(Ok(nbc3), read_san(&pos, "Nb1c3"));
+        assert_eq!(Ok(nbc3), read_san(&pos, "Nb1-c3!?"));
+        assert_eq!(Err(SanParseError::Invalid), read_san(&pos, "Nb1c3c4"));
+        assert_eq!(Err(SanParseError::Ambiguous), read_san(&pos, "Nc3"));
+        assert_eq!(Err(SanParseError::Illegal), read_san(&pos, "Nc4"));
+        assert_eq!(Err(SanParseError::Illegal), read_san(&pos, "O-O"));
+        assert_eq!(Err(SanParseError::Invalid), read_san(&pos, "Zz9"));
+        assert_eq!(Err(SanParseError::Empty), read_san(&pos, " "));
+        assert!(matches!(
+            parse_san(&pos, "Nc3"),
+            Err(crate::Error::San(SanParseError::Ambiguous))
+        ));
+
+        let pos = Position::from_fen_str("r3k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert!(read_san(&pos, "exd6 e.p.").unwrap().is_capture_en_passant());
+        let promotion = read_san(&pos, "bxa8=Q+").unwrap();
+        assert_eq!(Ok(promotion), read_san(&pos, "ba8q"));
+        assert_eq!(Err(SanParseError::Illegal), read_san(&pos, "bxa8"));
+        let pos = Position::from_fen_str("r3k3/8/8/8/8/8/8/4K3 b q - 0 1").unwrap();
+        assert!(read_san(&pos, "0-0-0").unwrap().is_queen_castle());
+    }
+
+    #[test]
+    fn it_plays_san_moves() {
+        let mut board = Board::startpos();
+        for san in ["e4", "e5", "Nf3", "Nc6", "Bb5"].iter() {
+            board.input_san_move(san).unwrap();
+        }
+        assert_eq!(
+            "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+            board.to_fen_string()
+        );
+        assert!(board.input_san_move("Bb5").is_err());
+        assert_eq!(5, board.history().len());
+    }
+}
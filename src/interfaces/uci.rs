@@ -167,7 +167,7 @@ impl Uci {
         }
 
         for mov in cmd.iter() {
-            self.board.input_san_move(mov);
+            self.board.input_lan_move(mov);
             // if mov.len() < 4 {
             //     eprintln!("error: incomplete move");
             //     return;
@@ -15,6 +15,9 @@ extern crate test;
 
 pub mod common;
 pub mod engine;
+pub mod error;
 pub mod frontends;
 pub mod interfaces;
 pub mod primitives;
+
+pub use crate::error::{Error, Result};
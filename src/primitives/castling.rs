@@ -9,6 +9,13 @@ pub mod sides {
     pub const QUEEN_SIDE: CastlingSide = 1;
 }
 
+/// Files of the usual castling rooks, by side
+pub const DEFAULT_ROOK_FILES: [u32; 2] = [7, 0];
+
+/// Castling rights in the lowest four bits, followed by the file of each
+/// right's rook in three bits. Files are stored relative to the default
+/// ones, so the usual rooks leave these bits clear.
+
 #[derive(Clone, Copy, Debug, Eq)]
 pub struct Castling(pub u32);
 
@@ -38,12 +45,33 @@ impl Castling {
     #[inline]
     pub fn clear(&mut self, color: Color, side: CastlingSide) {
         self.0.clear_bit((u32::from(color) << 1) + side);
+        self.0 &= !(7 << Self::file_shift(color, side));
     }
 
     #[inline]
     pub fn clear_color(&mut self, color: Color) {
-        self.0.clear_bit(u32::from(color) << 1);
-        self.0.clear_bit((u32::from(color) << 1) + 1);
+        self.clear(color, sides::KING_SIDE);
+        self.clear(color, sides::QUEEN_SIDE);
+    }
+
+    #[inline]
+    fn file_shift(color: Color, side: CastlingSide) -> u32 {
+        4 + 3 * ((u32::from(color) << 1) + side)
+    }
+
+    /// The file of the rook castling on `side`
+    #[inline]
+    pub fn rook_file(self, color: Color, side: CastlingSide) -> u32 {
+        ((self.0 >> Self::file_shift(color, side)) & 7) ^ DEFAULT_ROOK_FILES[side as usize]
+    }
+
+    /// Sets the right to castle on `side` with the rook on `file`
+    #[inline]
+    pub fn set_with_rook(&mut self, color: Color, side: CastlingSide, file: u32) {
+        let shift = Self::file_shift(color, side);
+        self.set(color, side);
+        self.0 &= !(7 << shift);
+        self.0 |= (file ^ DEFAULT_ROOK_FILES[side as usize]) << shift;
     }
 
     #[inline]
@@ -19,7 +19,7 @@ mod r#move;
 mod piece;
 mod square;
 
-pub use castling::{sides, Castling, CastlingSide};
+pub use castling::{sides, Castling, CastlingSide, DEFAULT_ROOK_FILES};
 pub use color::{colors, Color, ColorPrimitives};
 pub use piece::{piece_types, Piece, PiecePrimitives};
 pub use r#move::{flags, Move, MoveStack};
@@ -8,7 +8,7 @@ use std::fmt;
 pub type MoveStack = Vec<Move>;
 
 pub type Movesize = u32;
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Move(Movesize);
 // bit mask:
 // (from https://chessprogramming.wikispaces.com/Encoding+Moves)
@@ -202,7 +202,15 @@ impl fmt::Display for Move {
             self.orig().to_san_string(),
             // if self.is_capture() { "x" } else { "-" },
             self.dest().to_san_string()
-        )
+        )?;
+        if self.is_promotion() {
+            write!(
+                f,
+                "{}",
+                Piece::new(self.prom_piece_code(), colors::BLACK).to_san_string()
+            )?;
+        }
+        Ok(())
     }
 }
 
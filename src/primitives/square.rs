@@ -1,3 +1,5 @@
+use crate::error::Error;
+
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::string::String;
@@ -6,10 +8,11 @@ pub type Square = u32;
 
 pub trait SquarePrimitives<T> {
     fn from_coords(x: u32, y: u32) -> Square;
-    fn from_san_string(square: &str) -> Result<Square, &'static str>;
+    fn from_san_string(square: &str) -> Result<Square, Error>;
     fn to_san_string(self) -> String;
 
     fn flipped(self) -> Square;
+    fn distance(self, other: Square) -> u32;
 }
 
 impl SquarePrimitives<Square> for Square {
@@ -18,9 +21,9 @@ impl SquarePrimitives<Square> for Square {
         ((y << 3) + x) as Square
     }
 
-    fn from_san_string(square: &str) -> Result<Square, &'static str> {
-        if square.len() != 2 {
-            return Err("Invalid square string");
+    fn from_san_string(square: &str) -> Result<Square, Error> {
+        if square.len() != 2 || !square.is_ascii() {
+            return Err(Error::InvalidSquare(square.to_string()));
         }
 
         let file_lookup: HashMap<&'static str, Square> = [
@@ -39,11 +42,11 @@ impl SquarePrimitives<Square> for Square {
 
         let x = match file_lookup.get(&square[0..1]) {
             Some(val) => val,
-            None => return Err("Invalid square string"),
+            None => return Err(Error::InvalidSquare(square.to_string())),
         };
         let y = match u32::from_str(&square[1..2]) {
-            Ok(val) => val - 1,
-            Err(_) => return Err("Invalid square string"),
+            Ok(val) if (1..=8).contains(&val) => val - 1,
+            _ => return Err(Error::InvalidSquare(square.to_string())),
         };
 
         Ok((y << 3) + *x)
@@ -60,6 +63,14 @@ impl SquarePrimitives<Square> for Square {
     fn flipped(self) -> Square {
         self ^ 56
     }
+
+    /// Chebyshev (king move) distance between two squares
+    #[inline]
+    fn distance(self, other: Square) -> u32 {
+        let file_dist = ((self & 7) as i32 - (other & 7) as i32).abs();
+        let rank_dist = ((self >> 3) as i32 - (other >> 3) as i32).abs();
+        file_dist.max(rank_dist) as u32
+    }
 }
 
 pub mod squares {
@@ -148,4 +159,12 @@ mod tests {
             assert_eq!(st, (index as Square).to_san_string());
         }
     }
+
+    #[test]
+    fn it_measures_king_distance() {
+        assert_eq!(0, squares::E4.distance(squares::E4));
+        assert_eq!(1, squares::E4.distance(squares::F5));
+        assert_eq!(7, squares::A1.distance(squares::H8));
+        assert_eq!(3, squares::B2.distance(squares::C5));
+    }
 }